@@ -8,7 +8,6 @@ pub(crate) struct Aabb {
 }
 
 impl Aabb {
-    #[allow(dead_code)]
     pub(crate) fn new(x: Interval, y: Interval, z: Interval) -> Self {
         Aabb { x, y, z }
     }
@@ -22,21 +21,21 @@ impl Aabb {
         .pad_to_minimums()
     }
 
-    pub(crate) fn pad_to_minimums(self) -> Aabb {
+    pub(crate) fn pad_to_minimums(mut self) -> Aabb {
+        // Flat boxes (axis aligned quads and triangles) would otherwise never be hit
         let delta = 0.0001;
         if self.x.size() < delta {
-            self.x.expand(delta);
+            self.x = self.x.expand(delta);
         }
         if self.y.size() < delta {
-            self.y.expand(delta);
+            self.y = self.y.expand(delta);
         }
         if self.z.size() < delta {
-            self.z.expand(delta);
+            self.z = self.z.expand(delta);
         }
         self
     }
 
-    #[allow(dead_code)]
     pub(crate) fn new_from_point(p: Point) -> Aabb {
         Aabb {
             x: Interval::new(p.x, p.x),
@@ -49,7 +48,7 @@ impl Aabb {
         self.x = Interval::tight(self.x, box1.x);
         self.y = Interval::tight(self.y, box1.y);
         self.z = Interval::tight(self.z, box1.z);
-        self.pad_to_minimums();
+        *self = self.pad_to_minimums();
     }
 
     pub(crate) fn axis_interval(&self, axis: Axis) -> Interval {
@@ -83,6 +82,23 @@ impl Aabb {
         .pad_to_minimums()
     }
 
+    // Zero for empty boxes
+    pub(crate) fn surface_area(&self) -> f32 {
+        let (x, y, z) = (self.x.size(), self.y.size(), self.z.size());
+        if x < 0.0 || y < 0.0 || z < 0.0 {
+            return 0.0;
+        }
+        2.0 * (x * y + y * z + z * x)
+    }
+
+    pub(crate) fn centroid(&self) -> Point {
+        Point::new(
+            (self.x.min + self.x.max) * 0.5,
+            (self.y.min + self.y.max) * 0.5,
+            (self.z.min + self.z.max) * 0.5,
+        )
+    }
+
     pub(crate) fn longest_axis(&self) -> Axis {
         let xy = self.x.size() > self.y.size();
         let yz = self.y.size() > self.z.size();
@@ -126,7 +142,8 @@ impl Aabb {
                 }
             }
 
-            if ray_interval_max <= ray_interval_min {
+            // Padded flat boxes far from the origin can round to t0 == t1, that's still a hit
+            if ray_interval_max < ray_interval_min {
                 return false;
             }
         }
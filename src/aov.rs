@@ -0,0 +1,283 @@
+use std::{path::Path, sync::Arc};
+
+use crate::{
+    color::Color, hittable::HitRecord, image::exr::ExrChannel, material::Material, ray::Ray,
+    vec3::Vec3,
+};
+
+// Arbitrary output variables, render passes next to the beauty image for denoisers and
+// compositing
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Aov {
+    // == What the camera ray hit first, zero where it missed ==
+    // Distance from the camera in world units
+    Depth,
+    // World space shading normal, facing the camera
+    Normal,
+    // Texture color of the material, white for glass and black for lights
+    Albedo,
+    // Top level primitive in the built world, -1 where the ray missed
+    Object,
+    // Material in the order they turn up in the world, -1 where the ray missed
+    Material,
+    Uv,
+    // World space
+    Position,
+    // Share of camera rays that hit anything, the alpha channel of png and exr outputs
+    Alpha,
+
+    // == Light split by the path it took, direct and indirect add up to the beauty image ==
+    // Emitters and background seen directly or after one bounce
+    Direct,
+    // Light after two or more bounces
+    Indirect,
+    // Light whose first bounce was diffuse, or through a medium
+    Diffuse,
+    // Light whose first bounce was off metal or glass
+    Specular,
+}
+
+impl Aov {
+    pub(crate) const ALL: [Aov; 12] = [
+        Aov::Depth,
+        Aov::Normal,
+        Aov::Albedo,
+        Aov::Object,
+        Aov::Material,
+        Aov::Uv,
+        Aov::Position,
+        Aov::Alpha,
+        Aov::Direct,
+        Aov::Indirect,
+        Aov::Diffuse,
+        Aov::Specular,
+    ];
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::Object => "object",
+            Aov::Material => "material",
+            Aov::Uv => "uv",
+            Aov::Position => "position",
+            Aov::Alpha => "alpha",
+            Aov::Direct => "direct",
+            Aov::Indirect => "indirect",
+            Aov::Diffuse => "diffuse",
+            Aov::Specular => "specular",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        Aov::ALL.into_iter().find(|aov| aov.name() == name)
+    }
+
+    // Exr channels for the red, green and blue components of the pass in that order, scalar
+    // passes only use red
+    fn channel_names(&self) -> &'static [&'static str] {
+        match self {
+            Aov::Depth => &["Z"],
+            Aov::Normal => &["normal.X", "normal.Y", "normal.Z"],
+            Aov::Albedo => &["albedo.R", "albedo.G", "albedo.B"],
+            Aov::Object => &["object"],
+            Aov::Material => &["material"],
+            Aov::Uv => &["uv.U", "uv.V"],
+            Aov::Position => &["position.X", "position.Y", "position.Z"],
+            Aov::Alpha => &["A"],
+            Aov::Direct => &["direct.R", "direct.G", "direct.B"],
+            Aov::Indirect => &["indirect.R", "indirect.G", "indirect.B"],
+            Aov::Diffuse => &["diffuse.R", "diffuse.G", "diffuse.B"],
+            Aov::Specular => &["specular.R", "specular.G", "specular.B"],
+        }
+    }
+
+    pub(crate) fn channels(&self, pixels: &[Color]) -> Vec<ExrChannel> {
+        let components: [fn(&Color) -> f32; 3] = [|c| c.r, |c| c.g, |c| c.b];
+        self.channel_names()
+            .iter()
+            .zip(components)
+            .map(|(name, component)| ExrChannel::new(name, pixels.iter().map(component).collect()))
+            .collect()
+    }
+
+    // Averaging ids of different objects would give the id of neither, these keep the first
+    // sample's value instead
+    pub(crate) fn is_id(&self) -> bool {
+        matches!(self, Aov::Object | Aov::Material)
+    }
+
+    // Image of its own next to output, "render.png" gets "render.depth.png"
+    pub(crate) fn path(&self, output: &str) -> String {
+        let path = Path::new(output);
+        match path.extension() {
+            Some(extension) => path
+                .with_extension(format!("{}.{}", self.name(), extension.to_string_lossy()))
+                .to_string_lossy()
+                .into_owned(),
+            None => format!("{output}.{}", self.name()),
+        }
+    }
+}
+
+// Result of tracing one camera sample
+#[derive(Clone)]
+pub(crate) struct PathSample {
+    pub(crate) color: Color,
+    pub(crate) direct: Color,
+    pub(crate) indirect: Color,
+    pub(crate) diffuse: Color,
+    pub(crate) specular: Color,
+    // Whether the first bounce was specular, unset until the path scatters
+    pub(crate) first_specular: Option<bool>,
+    // Index of the top level primitive, the camera ray and its hit. Only kept when passes are
+    // rendered
+    pub(crate) first_hit: Option<(usize, Ray, HitRecord)>,
+}
+
+impl PathSample {
+    pub(crate) fn new() -> Self {
+        let black = Color::new(0.0, 0.0, 0.0);
+        PathSample {
+            color: black,
+            direct: black,
+            indirect: black,
+            diffuse: black,
+            specular: black,
+            first_specular: None,
+            first_hit: None,
+        }
+    }
+
+    // Light reaching the camera after bounces scattering events
+    pub(crate) fn add(&mut self, light: Color, bounces: u32) {
+        self.color += light;
+        if bounces <= 1 {
+            self.direct += light;
+        } else {
+            self.indirect += light;
+        }
+        match self.first_specular {
+            Some(false) => self.diffuse += light,
+            Some(true) => self.specular += light,
+            None => (),
+        }
+    }
+
+    // Value of a pass for this sample, materials lists every material of the world once
+    pub(crate) fn value(&self, aov: Aov, materials: &[&Arc<Material>]) -> Color {
+        let gray = |value: f32| Color::new(value, value, value);
+        let hit = self.first_hit.as_ref().map(|(_, _, hit)| hit);
+        let or_black = |value: Option<Color>| value.unwrap_or(Color::new(0.0, 0.0, 0.0));
+
+        match aov {
+            // Camera rays aren't unit length, t is in multiples of their direction
+            Aov::Depth => or_black(
+                self.first_hit
+                    .as_ref()
+                    .map(|(_, ray, hit)| gray(hit.t * ray.direction.length())),
+            ),
+            Aov::Normal => or_black(hit.map(|hit| Color::from(hit.normal))),
+            Aov::Albedo => or_black(hit.map(|hit| hit.material.albedo(hit))),
+            Aov::Object => gray(
+                self.first_hit
+                    .as_ref()
+                    .map_or(-1.0, |(object, _, _)| *object as f32),
+            ),
+            Aov::Material => gray(
+                hit.and_then(|hit| {
+                    materials
+                        .iter()
+                        .position(|material| Arc::ptr_eq(material, &hit.material))
+                })
+                .map_or(-1.0, |idx| idx as f32),
+            ),
+            Aov::Uv => or_black(hit.map(|hit| Color::new(hit.u, hit.v, 0.0))),
+            Aov::Position => or_black(hit.map(|hit| Color::from(Vec3::from(hit.position)))),
+            Aov::Alpha => gray(if hit.is_some() { 1.0 } else { 0.0 }),
+            Aov::Direct => self.direct,
+            Aov::Indirect => self.indirect,
+            Aov::Diffuse => self.diffuse,
+            Aov::Specular => self.specular,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::{Aov, PathSample};
+    use crate::{
+        color::Color,
+        interval::Interval,
+        material::Material,
+        point::Point,
+        primitive::{Primitive, SphereParams},
+        ray::Ray,
+        texture::Texture,
+        vec3::Vec3,
+    };
+
+    #[test]
+    fn light_passes_split_the_beauty() {
+        let mut sample = PathSample::new();
+        // Seen directly, then a diffuse first bounce
+        sample.add(Color::new(1.0, 0.0, 0.0), 0);
+        sample.first_specular = Some(false);
+        sample.add(Color::new(0.0, 2.0, 0.0), 1);
+        sample.add(Color::new(0.0, 0.0, 4.0), 3);
+
+        let value = |aov| sample.value(aov, &[]);
+        let sum = |a: Color, b: Color| (a.r + b.r, a.g + b.g, a.b + b.b);
+        let beauty = (sample.color.r, sample.color.g, sample.color.b);
+        assert_eq!(beauty, (1.0, 2.0, 4.0));
+        assert_eq!(sum(value(Aov::Direct), value(Aov::Indirect)), beauty);
+        // Light that never bounced is neither diffuse nor specular
+        assert_eq!(
+            sum(value(Aov::Diffuse), value(Aov::Specular)),
+            (0.0, 2.0, 4.0)
+        );
+
+        // Misses have no first hit
+        assert_eq!(value(Aov::Depth).r, 0.0);
+        assert_eq!(value(Aov::Object).r, -1.0);
+        assert_eq!(value(Aov::Alpha).r, 0.0);
+    }
+
+    #[test]
+    fn depth_is_the_distance_to_the_position() {
+        let material = Arc::new(Material::Lambertian(Arc::new(Texture::from_color(
+            Color::new(0.5, 0.5, 0.5),
+        ))));
+        let sphere = Primitive::Sphere(SphereParams::new(Point::new(0.0, 0.0, 0.0), 1.0, material));
+        // Like camera rays with a focal length other than one
+        let ray = Ray::new(Point::new(0.0, 2.0, 9.0), Vec3::new(0.0, -0.6, -2.5), 0.0);
+
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let hit = sphere
+            .hit(&mut rng, ray, Interval::new(0.001, f32::INFINITY))
+            .unwrap();
+        let mut sample = PathSample::new();
+        sample.first_hit = Some((0, ray, hit));
+
+        let depth = sample.value(Aov::Depth, &[]).r;
+        let position = sample.value(Aov::Position, &[]);
+        let distance = (Vec3::from(position) - Vec3::from(ray.origin)).length();
+        assert!((depth - distance).abs() < 1e-4, "{depth} {distance}");
+        assert_eq!(sample.value(Aov::Alpha, &[]).r, 1.0);
+    }
+
+    #[test]
+    fn pass_names_round_trip() {
+        for aov in Aov::ALL {
+            assert_eq!(Aov::from_name(aov.name()), Some(aov));
+        }
+        assert_eq!(Aov::Depth.path("out/render.png"), "out/render.depth.png");
+        assert_eq!(Aov::Normal.path("render"), "render.normal");
+    }
+}
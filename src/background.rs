@@ -0,0 +1,204 @@
+use std::f32::consts::PI;
+
+use rand::Rng;
+
+use crate::{color::Color, image::Image, point::Point, primitive::Primitive, vec3::Vec3};
+
+// Radiance for rays that escape the scene
+#[derive(Debug)]
+pub(crate) enum Background {
+    Solid(Color),
+
+    // horizon (straight down), zenith (straight up)
+    Gradient(Color, Color),
+
+    // equirectangular (latitude/longitude) image, same mapping as sphere uvs
+    Environment(EnvironmentMap),
+}
+
+impl Background {
+    // Blue sky used by the book scenes
+    pub(crate) fn sky() -> Self {
+        Background::Gradient(Color::new(1.0, 1.0, 1.0), Color::new(0.5, 0.7, 1.0))
+    }
+
+    pub(crate) fn sample(&self, direction: Vec3) -> Color {
+        match self {
+            Background::Solid(color) => *color,
+            Background::Gradient(horizon, zenith) => {
+                let unit_direction = direction.unit();
+                let a = (unit_direction.y + 1.0) * 0.5;
+                (1.0 - a) * *horizon + a * *zenith
+            }
+            Background::Environment(map) => map.radiance(direction),
+        }
+    }
+
+    // The background as a light that can be sampled, only environment maps with some light in
+    // them. Solid and gradient skies are left to the bsdf samples
+    pub(crate) fn light(&self) -> Option<&EnvironmentMap> {
+        match self {
+            Background::Environment(map) if map.total > 0.0 => Some(map),
+            _ => None,
+        }
+    }
+}
+
+// Environment image with a distribution over its pixels for importance sampling, each pixel is
+// picked with a chance proportional to its luminance and the solid angle it covers
+// https://pbr-book.org/4ed/Light_Sources/Infinite_Area_Lights#ImageInfiniteLights
+#[derive(Debug)]
+pub(crate) struct EnvironmentMap {
+    image: Image,
+    weights: Vec<f32>,
+    // Running sums of the weights along each row, then of the row totals
+    columns: Vec<f32>,
+    rows: Vec<f32>,
+    total: f32,
+}
+
+impl EnvironmentMap {
+    pub(crate) fn new(image: Image) -> Self {
+        let (width, height) = (image.width, image.height);
+        let mut weights = Vec::with_capacity(width * height);
+        let mut columns = Vec::with_capacity(width * height);
+        let mut rows = Vec::with_capacity(height);
+        let mut total = 0.0;
+
+        for j in 0..height {
+            // Rows near the poles are squeezed into a smaller solid angle
+            let sin_theta = ((j as f32 + 0.5) / height as f32 * PI).sin();
+            let mut row = 0.0;
+            for i in 0..width {
+                let color = image.sample(i, j).unwrap_or(Color::new(0.0, 0.0, 0.0));
+                let weight = color.luminance().max(0.0) * sin_theta;
+                row += weight;
+                weights.push(weight);
+                columns.push(row);
+            }
+            total += row;
+            rows.push(total);
+        }
+
+        EnvironmentMap {
+            image,
+            weights,
+            columns,
+            rows,
+            total,
+        }
+    }
+
+    pub(crate) fn radiance(&self, direction: Vec3) -> Color {
+        let (u, v) = Primitive::get_sphere_uv(Point::from(direction.unit()));
+        self.image.sample_uv(u, v)
+    }
+
+    // Density over solid angle of random() picking direction
+    pub(crate) fn pdf_value(&self, direction: Vec3) -> f32 {
+        let direction = direction.unit();
+        let sin_theta = (direction.x * direction.x + direction.z * direction.z).sqrt();
+        if self.total <= 0.0 || sin_theta <= 0.0 {
+            return 0.0;
+        }
+
+        // Same pixel as sample_uv
+        let (width, height) = (self.image.width, self.image.height);
+        let (u, v) = Primitive::get_sphere_uv(Point::from(direction));
+        let i = ((u * width as f32) as usize).min(width - 1);
+        let j = (((1.0 - v) * height as f32) as usize).min(height - 1);
+
+        // Uniform within the pixel in uv, which spans 2 pi by pi radians
+        let pdf_uv = self.weights[j * width + i] / self.total * (width * height) as f32;
+        pdf_uv / (2.0 * PI * PI * sin_theta)
+    }
+
+    // Unit direction toward a random point of a pixel
+    pub(crate) fn random<R: Rng>(&self, rng: &mut R) -> Vec3 {
+        let (width, height) = (self.image.width, self.image.height);
+
+        let target = rng.random::<f32>() * self.total;
+        let j = self
+            .rows
+            .partition_point(|sum| *sum <= target)
+            .min(height - 1);
+
+        let row = &self.columns[j * width..(j + 1) * width];
+        let target = rng.random::<f32>() * row[width - 1];
+        let i = row.partition_point(|sum| *sum <= target).min(width - 1);
+
+        let u = (i as f32 + rng.random::<f32>()) / width as f32;
+        let v = 1.0 - (j as f32 + rng.random::<f32>()) / height as f32;
+
+        // Inverse of get_sphere_uv
+        let (phi, theta) = (2.0 * PI * u, PI * v);
+        Vec3::new(
+            -phi.cos() * theta.sin(),
+            -theta.cos(),
+            phi.sin() * theta.sin(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::EnvironmentMap;
+    use crate::{
+        color::Color,
+        image::{Image, ImageFormat},
+        vec3::Vec3,
+    };
+
+    // The density integrates to one over the sphere of directions, and sampling the map estimates
+    // the same total light as sampling directions uniformly
+    #[test]
+    fn environment_sampling_matches_radiance() {
+        let (width, height) = (16, 8);
+        let pixels = (0..width * height)
+            .map(|idx| match (idx % width, idx / width) {
+                (5, 2) => Color::new(50.0, 40.0, 30.0),
+                (_, 0..=3) => Color::new(0.3, 0.4, 0.6),
+                // one black pixel has no chance of being picked
+                (0, 5) => Color::new(0.0, 0.0, 0.0),
+                _ => Color::new(0.1, 0.1, 0.1),
+            })
+            .collect::<Vec<_>>();
+
+        let path = std::env::temp_dir().join(format!("weekend-rt-{}.hdr", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        Image::write_as(&path, ImageFormat::Hdr, width, height, &pixels).unwrap();
+        let image = Image::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        let map = EnvironmentMap::new(image.unwrap());
+
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let samples = 200_000;
+        let (mut integral, mut uniform, mut sampled) = (0.0, 0.0, 0.0);
+        for _ in 0..samples {
+            let direction = Vec3::random_unit_vector(&mut rng);
+            integral += map.pdf_value(direction) * 4.0 * PI;
+            uniform += map.radiance(direction).luminance() * 4.0 * PI;
+
+            let direction = map.random(&mut rng);
+            let pdf = map.pdf_value(direction);
+            assert!(pdf > 0.0, "{direction:?}");
+            sampled += map.radiance(direction).luminance() / pdf;
+        }
+
+        let (integral, uniform, sampled) = (
+            integral / samples as f32,
+            uniform / samples as f32,
+            sampled / samples as f32,
+        );
+        assert!((integral - 1.0).abs() < 0.03, "{integral}");
+        assert!(
+            (sampled - uniform).abs() < 0.03 * uniform,
+            "{sampled} {uniform}"
+        );
+    }
+}
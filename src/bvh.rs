@@ -1,6 +1,44 @@
-use std::sync::Arc;
+use std::{fmt, sync::Arc};
 
-use crate::{aabb::Aabb, hittable::HitRecord, interval::Interval, primitive::Primitive};
+#[cfg(test)]
+use rand::Rng;
+
+use crate::{aabb::Aabb, primitive::Primitive};
+// Only the recursive traversal below uses these
+#[cfg(test)]
+use crate::{hittable::HitRecord, interval::Interval, ray::Ray};
+
+mod flat;
+mod sah;
+
+pub(crate) use flat::FlatBvh;
+pub(crate) use sah::SahConfig;
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum BuildStrategy {
+    // Sort along the longest axis and split at the median count
+    Median,
+    Sah(SahConfig),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct BuildStats {
+    pub(crate) node_count: usize,
+    pub(crate) leaf_count: usize,
+    pub(crate) depth: usize,
+    // Expected cost of a random ray hitting the root, relative to the root's surface area
+    pub(crate) sah_cost: f32,
+}
+
+impl fmt::Display for BuildStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} nodes ({} leaves), depth {}, SAH cost {:.2}",
+            self.node_count, self.leaf_count, self.depth, self.sah_cost
+        )
+    }
+}
 
 #[derive(Debug)]
 pub(crate) struct BVHNode {
@@ -12,6 +50,14 @@ pub(crate) struct BVHNode {
 }
 
 impl BVHNode {
+    pub(crate) fn build(objects: &mut Vec<Primitive>, strategy: BuildStrategy) -> Arc<Self> {
+        let end = objects.len();
+        match strategy {
+            BuildStrategy::Median => BVHNode::new(objects, 0, end),
+            BuildStrategy::Sah(config) => sah::build(objects, 0, end, &config),
+        }
+    }
+
     pub(crate) fn new(objects: &mut Vec<Primitive>, start: usize, end: usize) -> Arc<Self> {
         let mut bounding_box = Aabb::empty();
 
@@ -32,10 +78,9 @@ impl BVHNode {
 
         let object_span = end - start;
 
-        let (left, right, start_idx, primitive_count) = if object_span == 1 {
-            (None, None, start, 1usize)
-        } else if object_span == 2 {
-            (None, None, start, 2usize)
+        // Empty worlds end up as a single leaf without primitives
+        let (left, right, start_idx, primitive_count) = if object_span <= 2 {
+            (None, None, start, object_span)
         } else {
             objects.as_mut_slice()[start..end].sort_by(key_lambda);
 
@@ -62,16 +107,20 @@ impl BVHNode {
         })
     }
 
-    pub(crate) fn hit(
+    // Rendering goes through the flattened tree, this recursive traversal is only kept as the
+    // reference the tests match it against
+    #[cfg(test)]
+    pub(crate) fn hit<R: Rng>(
         &self,
-        ray: crate::ray::Ray,
-        ray_interval: crate::interval::Interval,
+        rng: &mut R,
+        ray: Ray,
+        ray_interval: Interval,
         world: &Arc<Vec<Primitive>>,
-    ) -> Option<crate::hittable::HitRecord> {
+    ) -> Option<HitRecord> {
         if self.aabb.hit(ray, ray_interval) {
             if let (Some(left), Some(right)) = (&self.left, &self.right) {
-                let hit_left = left.hit(ray, ray_interval, world);
-                let hit_right = right.hit(ray, ray_interval, world);
+                let hit_left = left.hit(rng, ray, ray_interval, world);
+                let hit_right = right.hit(rng, ray, ray_interval, world);
 
                 if hit_left.is_some() && hit_right.is_none() {
                     return hit_left;
@@ -95,7 +144,7 @@ impl BVHNode {
 
                 for object in world_slice.iter() {
                     if let Some(hit) =
-                        object.hit(ray, Interval::new(ray_interval.min, closest_so_far))
+                        object.hit(rng, ray, Interval::new(ray_interval.min, closest_so_far))
                     {
                         closest_so_far = hit.t;
                         potential_hit = Some(hit);
@@ -108,7 +157,329 @@ impl BVHNode {
         None
     }
 
-    fn bounding_box(&self) -> &crate::aabb::Aabb {
+    // Stops at the first hit in any leaf, for shadow rays
+    #[cfg(test)]
+    pub(crate) fn occluded<R: Rng>(
+        &self,
+        rng: &mut R,
+        ray: Ray,
+        ray_interval: Interval,
+        world: &[Primitive],
+    ) -> bool {
+        if !self.aabb.hit(ray, ray_interval) {
+            return false;
+        }
+
+        if let (Some(left), Some(right)) = (&self.left, &self.right) {
+            return left.occluded(rng, ray, ray_interval, world)
+                || right.occluded(rng, ray, ray_interval, world);
+        }
+
+        world[self.start_idx..(self.start_idx + self.primitive_count)]
+            .iter()
+            .any(|object| object.occluded(rng, ray, ray_interval))
+    }
+
+    pub(crate) fn flatten(&self) -> FlatBvh {
+        FlatBvh::new(self)
+    }
+
+    fn bounding_box(&self) -> &Aabb {
         &self.aabb
     }
+
+    fn leaf(start_idx: usize, primitive_count: usize, aabb: Aabb) -> Arc<Self> {
+        Arc::new(BVHNode {
+            left: None,
+            right: None,
+            start_idx,
+            primitive_count,
+            aabb,
+        })
+    }
+
+    fn interior(left: Arc<BVHNode>, right: Arc<BVHNode>) -> Arc<Self> {
+        let mut aabb = left.aabb;
+        aabb.expand(&right.aabb);
+
+        Arc::new(BVHNode {
+            left: Some(left),
+            right: Some(right),
+            start_idx: 0,
+            primitive_count: 0,
+            aabb,
+        })
+    }
+
+    // Costs come from the config so trees from different strategies can be compared
+    pub(crate) fn stats(&self, config: &SahConfig) -> BuildStats {
+        let mut stats = BuildStats {
+            node_count: 0,
+            leaf_count: 0,
+            depth: 0,
+            sah_cost: 0.0,
+        };
+        let root_area = self.aabb.surface_area().max(f32::MIN_POSITIVE);
+        self.collect_stats(config, root_area, 1, &mut stats);
+        stats
+    }
+
+    fn collect_stats(
+        &self,
+        config: &SahConfig,
+        root_area: f32,
+        depth: usize,
+        stats: &mut BuildStats,
+    ) {
+        let area_ratio = self.aabb.surface_area() / root_area;
+
+        stats.node_count += 1;
+        stats.depth = stats.depth.max(depth);
+
+        if let (Some(left), Some(right)) = (&self.left, &self.right) {
+            stats.sah_cost += config.traversal_cost * area_ratio;
+            left.collect_stats(config, root_area, depth + 1, stats);
+            right.collect_stats(config, root_area, depth + 1, stats);
+        } else {
+            stats.leaf_count += 1;
+            stats.sah_cost += config.intersection_cost * self.primitive_count as f32 * area_ratio;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    use super::{BVHNode, BuildStrategy, SahConfig};
+    use crate::{
+        color::Color,
+        interval::Interval,
+        material::Material,
+        point::Point,
+        primitive::{Primitive, QuadParams, SphereParams, TriangleParams},
+        ray::Ray,
+        texture::Texture,
+        vec3::Vec3,
+    };
+
+    fn random_point(rng: &mut ChaCha8Rng, extent: f32) -> Point {
+        Point::new(
+            rng.random_range(-extent..extent),
+            rng.random_range(-extent..extent),
+            rng.random_range(-extent..extent),
+        )
+    }
+
+    // Spheres, quads, triangles and a box scattered through a cube around the origin
+    fn random_world(rng: &mut ChaCha8Rng) -> Vec<Primitive> {
+        let material = Arc::new(Material::Lambertian(Arc::new(Texture::from_color(
+            Color::new(0.5, 0.5, 0.5),
+        ))));
+        let mut world = Vec::new();
+
+        for _ in 0..40 {
+            let center = random_point(rng, 5.0);
+            let radius = rng.random_range(0.1..1.0);
+            world.push(Primitive::Sphere(SphereParams::new(
+                center,
+                radius,
+                material.clone(),
+            )));
+        }
+        for _ in 0..40 {
+            let q = random_point(rng, 5.0);
+            let u = Vec3::random_in_range(rng, -1.0, 1.0);
+            let v = Vec3::random_in_range(rng, -1.0, 1.0);
+            world.push(Primitive::Quad(QuadParams::new(q, u, v, material.clone())));
+        }
+        for _ in 0..40 {
+            let p0 = random_point(rng, 5.0);
+            let p1 = p0 + Vec3::random_in_range(rng, -1.0, 1.0);
+            let p2 = p0 + Vec3::random_in_range(rng, -1.0, 1.0);
+            world.push(Primitive::Triangle(TriangleParams::from_points(
+                p0,
+                p1,
+                p2,
+                material.clone(),
+            )));
+        }
+        world.extend(Primitive::new_box(
+            Point::new(-1.0, -1.0, -1.0),
+            Point::new(1.0, 1.0, 1.0),
+            material,
+        ));
+
+        world
+    }
+
+    // Origins inside and around the world, some intervals end before the nearest hit
+    fn random_rays(rng: &mut ChaCha8Rng, count: usize) -> Vec<(Ray, Interval)> {
+        (0..count)
+            .map(|_| {
+                let origin = random_point(rng, 8.0);
+                let direction = Vec3::random_unit_vector(rng);
+                let max = if rng.random_bool(0.5) {
+                    f32::INFINITY
+                } else {
+                    rng.random_range(0.01..10.0)
+                };
+                (Ray::new(origin, direction, 0.0), Interval::new(0.001, max))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn primitive_occluded_matches_hit() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let world = random_world(&mut rng);
+        let mut hits = 0;
+
+        for (ray, interval) in random_rays(&mut rng, 2000) {
+            for object in &world {
+                let hit = object.hit(&mut rng, ray, interval).is_some();
+                assert_eq!(
+                    object.occluded(&mut rng, ray, interval),
+                    hit,
+                    "{object:?} {ray:?}"
+                );
+                hits += hit as usize;
+            }
+        }
+
+        assert!(hits > 0);
+    }
+
+    #[test]
+    fn bvh_occluded_matches_hit() {
+        let strategies = [
+            BuildStrategy::Median,
+            BuildStrategy::Sah(SahConfig::default()),
+        ];
+
+        for strategy in strategies {
+            let mut rng = ChaCha8Rng::seed_from_u64(2);
+            let mut world = random_world(&mut rng);
+            let root = BVHNode::build(&mut world, strategy);
+            let flat = root.flatten();
+            let world = Arc::new(world);
+            let (mut hits, mut misses) = (0, 0);
+
+            for (ray, interval) in random_rays(&mut rng, 5000) {
+                let hit = root.hit(&mut rng, ray, interval, &world).is_some();
+                assert_eq!(
+                    root.occluded(&mut rng, ray, interval, &world),
+                    hit,
+                    "{ray:?}"
+                );
+                assert_eq!(
+                    flat.hit(&mut rng, ray, interval, &world).is_some(),
+                    hit,
+                    "{ray:?}"
+                );
+                assert_eq!(
+                    flat.occluded(&mut rng, ray, interval, &world),
+                    hit,
+                    "{ray:?}"
+                );
+
+                if hit {
+                    hits += 1;
+                } else {
+                    misses += 1;
+                }
+            }
+
+            assert!(hits > 0 && misses > 0, "{hits} hits, {misses} misses");
+        }
+    }
+
+    #[test]
+    fn flat_hits_match_tree() {
+        let strategies = [
+            BuildStrategy::Median,
+            BuildStrategy::Sah(SahConfig::default()),
+        ];
+
+        for strategy in strategies {
+            let mut rng = ChaCha8Rng::seed_from_u64(4);
+            let mut world = random_world(&mut rng);
+            let root = BVHNode::build(&mut world, strategy);
+            let flat = root.flatten();
+            let world = Arc::new(world);
+            let mut hits = 0;
+
+            for (ray, interval) in random_rays(&mut rng, 5000) {
+                let expected = root.hit(&mut rng, ray, interval, &world);
+                let hit = flat.hit(&mut rng, ray, interval, &world);
+                let (Some(expected), Some(hit)) = (expected.as_ref(), hit.as_ref()) else {
+                    assert_eq!(hit.is_some(), expected.is_some(), "{ray:?}");
+                    continue;
+                };
+
+                let distance = |a: Vec3, b: Vec3| (a - b).length();
+                assert!((hit.t - expected.t).abs() < 1e-5, "{ray:?}");
+                assert!(
+                    distance(Vec3::from(hit.position), Vec3::from(expected.position)) < 1e-4,
+                    "{ray:?}"
+                );
+                assert!(distance(hit.normal, expected.normal) < 1e-4, "{ray:?}");
+                hits += 1;
+            }
+
+            assert!(hits > 0);
+        }
+    }
+
+    // Both trees hold every primitive, and SAH splits are cheaper by the measure they optimize even
+    // when they take more nodes
+    #[test]
+    fn sah_beats_median_split() {
+        let config = SahConfig::default();
+        let mut rng = ChaCha8Rng::seed_from_u64(5);
+        let mut median_world = random_world(&mut rng);
+        let mut rng = ChaCha8Rng::seed_from_u64(5);
+        let mut sah_world = random_world(&mut rng);
+
+        let median = BVHNode::build(&mut median_world, BuildStrategy::Median).stats(&config);
+        let sah = BVHNode::build(&mut sah_world, BuildStrategy::Sah(config)).stats(&config);
+
+        for stats in [median, sah] {
+            assert_eq!(stats.node_count, 2 * stats.leaf_count - 1, "{stats}");
+            assert!(stats.leaf_count <= median_world.len(), "{stats}");
+            assert!(stats.depth <= stats.leaf_count, "{stats}");
+        }
+        // Median splits go down to pairs of primitives
+        assert!(2 * median.leaf_count >= median_world.len(), "{median}");
+        assert!(sah.sah_cost < median.sah_cost, "{sah} vs {median}");
+    }
+
+    #[test]
+    fn occluded_respects_interval() {
+        let material = Arc::new(Material::Dielectric(1.5));
+        let mut world = vec![Primitive::Sphere(SphereParams::new(
+            Point::new(0.0, 0.0, -5.0),
+            1.0,
+            material,
+        ))];
+        let root = BVHNode::build(&mut world, BuildStrategy::Median);
+        let flat = root.flatten();
+        let ray = Ray::new(Point::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+
+        // The near surface is at t = 4
+        for (max, expected) in [(3.9, false), (4.1, true), (f32::INFINITY, true)] {
+            let interval = Interval::new(0.001, max);
+            assert_eq!(root.occluded(&mut rng, ray, interval, &world), expected);
+            assert_eq!(flat.occluded(&mut rng, ray, interval, &world), expected);
+        }
+
+        // Starting inside the sphere still hits the far side
+        let inside = Ray::new(Point::new(0.0, 0.0, -5.0), Vec3::new(0.0, 1.0, 0.0), 0.0);
+        assert!(flat.occluded(&mut rng, inside, Interval::new(0.001, 2.0), &world));
+        assert!(!flat.occluded(&mut rng, inside, Interval::new(0.001, 0.5), &world));
+    }
 }
@@ -0,0 +1,223 @@
+use rand::Rng;
+
+use crate::{
+    aabb::Aabb, axis::Axis, hittable::HitRecord, interval::Interval, primitive::Primitive, ray::Ray,
+};
+
+use super::BVHNode;
+
+// Linear BVH in depth-first order, the first child of an interior node directly follows it
+// https://pbr-book.org/4ed/Primitives_and_Intersection_Acceleration/Bounding_Volume_Hierarchies#CompactBVHForTraversal
+
+// Traversal keeps its stack in place for trees up to this depth
+const STACK_SIZE: usize = 64;
+
+#[derive(Debug)]
+struct FlatNode {
+    aabb: Aabb,
+    // First primitive for leaves, index of the second child for interior nodes
+    offset: u32,
+    // Zero for interior nodes
+    primitive_count: u16,
+    // Axis along which the first child lies before the second
+    axis: Axis,
+}
+
+const _: () = assert!(std::mem::size_of::<FlatNode>() == 32);
+
+#[derive(Debug)]
+pub(crate) struct FlatBvh {
+    nodes: Vec<FlatNode>,
+    depth: usize,
+}
+
+impl FlatBvh {
+    pub(crate) fn new(root: &BVHNode) -> Self {
+        let mut bvh = FlatBvh {
+            nodes: Vec::new(),
+            depth: 0,
+        };
+
+        // An empty world has a single leaf without primitives, nothing to hit
+        if root.left.is_some() || root.primitive_count > 0 {
+            bvh.flatten(root, 1);
+        }
+        bvh
+    }
+
+    fn flatten(&mut self, node: &BVHNode, depth: usize) -> usize {
+        let idx = self.nodes.len();
+        self.depth = self.depth.max(depth);
+        self.nodes.push(FlatNode {
+            aabb: node.aabb,
+            offset: node.start_idx as u32,
+            primitive_count: u16::try_from(node.primitive_count)
+                .expect("leaf holds more primitives than a flat node can count"),
+            axis: Axis::X,
+        });
+
+        if let (Some(left), Some(right)) = (&node.left, &node.right) {
+            // Order the children along the axis their centers are furthest apart on
+            let left_center = left.aabb.centroid();
+            let right_center = right.aabb.centroid();
+            let axis = *Axis::iter()
+                .max_by(|a, b| {
+                    let a = (right_center.axis(**a) - left_center.axis(**a)).abs();
+                    let b = (right_center.axis(**b) - left_center.axis(**b)).abs();
+                    a.total_cmp(&b)
+                })
+                .unwrap();
+            let (first, second) = if left_center.axis(axis) <= right_center.axis(axis) {
+                (left, right)
+            } else {
+                (right, left)
+            };
+
+            self.flatten(first, depth + 1);
+            let second_idx = self.flatten(second, depth + 1);
+
+            let node = &mut self.nodes[idx];
+            node.offset = second_idx as u32;
+            node.axis = axis;
+        }
+
+        idx
+    }
+
+    pub(crate) fn hit<R: Rng>(
+        &self,
+        rng: &mut R,
+        ray: Ray,
+        ray_interval: Interval,
+        world: &[Primitive],
+    ) -> Option<HitRecord> {
+        self.hit_object(rng, ray, ray_interval, world)
+            .map(|(_, hit)| hit)
+    }
+
+    // Closest hit along with the index of the primitive in world
+    pub(crate) fn hit_object<R: Rng>(
+        &self,
+        rng: &mut R,
+        ray: Ray,
+        ray_interval: Interval,
+        world: &[Primitive],
+    ) -> Option<(usize, HitRecord)> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut heap = Vec::new();
+        let mut inline = [0u32; STACK_SIZE];
+        let stack = self.stack(&mut inline, &mut heap);
+        let mut stack_len = 0;
+
+        let mut potential_hit: Option<(usize, HitRecord)> = None;
+        let mut closest_so_far = ray_interval.max;
+        let mut idx = 0;
+
+        loop {
+            let node = &self.nodes[idx];
+
+            if node
+                .aabb
+                .hit(ray, Interval::new(ray_interval.min, closest_so_far))
+            {
+                if node.primitive_count > 0 {
+                    let start = node.offset as usize;
+                    let end = start + node.primitive_count as usize;
+
+                    for (object_idx, object) in (start..end).zip(&world[start..end]) {
+                        if let Some(hit) =
+                            object.hit(rng, ray, Interval::new(ray_interval.min, closest_so_far))
+                        {
+                            closest_so_far = hit.t;
+                            potential_hit = Some((object_idx, hit));
+                        }
+                    }
+                } else {
+                    // Near child first, the far one is only entered if it can still be closer
+                    let (near, far) = if ray.direction.axis(node.axis) < 0.0 {
+                        (node.offset as usize, idx + 1)
+                    } else {
+                        (idx + 1, node.offset as usize)
+                    };
+                    stack[stack_len] = far as u32;
+                    stack_len += 1;
+                    idx = near;
+                    continue;
+                }
+            }
+
+            if stack_len == 0 {
+                break;
+            }
+            stack_len -= 1;
+            idx = stack[stack_len] as usize;
+        }
+
+        potential_hit
+    }
+
+    // Stops at the first hit, children are visited in stored order since any hit will do
+    pub(crate) fn occluded<R: Rng>(
+        &self,
+        rng: &mut R,
+        ray: Ray,
+        ray_interval: Interval,
+        world: &[Primitive],
+    ) -> bool {
+        if self.nodes.is_empty() {
+            return false;
+        }
+
+        let mut heap = Vec::new();
+        let mut inline = [0u32; STACK_SIZE];
+        let stack = self.stack(&mut inline, &mut heap);
+        let mut stack_len = 0;
+        let mut idx = 0;
+
+        loop {
+            let node = &self.nodes[idx];
+
+            if node.aabb.hit(ray, ray_interval) {
+                if node.primitive_count > 0 {
+                    let start = node.offset as usize;
+                    let end = start + node.primitive_count as usize;
+
+                    if world[start..end]
+                        .iter()
+                        .any(|object| object.occluded(rng, ray, ray_interval))
+                    {
+                        return true;
+                    }
+                } else {
+                    stack[stack_len] = node.offset;
+                    stack_len += 1;
+                    idx += 1;
+                    continue;
+                }
+            }
+
+            if stack_len == 0 {
+                return false;
+            }
+            stack_len -= 1;
+            idx = stack[stack_len] as usize;
+        }
+    }
+
+    // Every level pushes at most one node, deeper trees than the inline stack spill to the heap
+    fn stack<'a>(
+        &self,
+        inline: &'a mut [u32; STACK_SIZE],
+        heap: &'a mut Vec<u32>,
+    ) -> &'a mut [u32] {
+        if self.depth <= STACK_SIZE {
+            inline
+        } else {
+            heap.resize(self.depth, 0);
+            heap
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use std::sync::Arc;
+
+use crate::{aabb::Aabb, axis::Axis, primitive::Primitive};
+
+use super::BVHNode;
+
+// Binned surface area heuristic builder
+// https://jacco.ompf2.com/2022/04/21/how-to-build-a-bvh-part-3-quick-builds/
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct SahConfig {
+    // Candidate split planes per axis are the edges between bins
+    pub(crate) bins: usize,
+    // Cost of visiting a node relative to intersecting a primitive
+    pub(crate) traversal_cost: f32,
+    pub(crate) intersection_cost: f32,
+    // Larger nodes are always split, smaller ones only when the heuristic says it pays off
+    pub(crate) max_leaf_size: usize,
+}
+
+impl Default for SahConfig {
+    fn default() -> Self {
+        SahConfig {
+            bins: 16,
+            traversal_cost: 1.0,
+            intersection_cost: 1.0,
+            max_leaf_size: 4,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Bin {
+    aabb: Aabb,
+    count: usize,
+}
+
+struct Split {
+    axis: Axis,
+    // Primitives in bins up to and including this one go left
+    bin: usize,
+    cost: f32,
+}
+
+pub(super) fn build(
+    objects: &mut [Primitive],
+    start: usize,
+    end: usize,
+    config: &SahConfig,
+) -> Arc<BVHNode> {
+    let mut aabb = Aabb::empty();
+    let mut centroid_bounds = Aabb::empty();
+    for object in &objects[start..end] {
+        aabb.expand(object.bounding_box());
+        let centroid = object.bounding_box().centroid();
+        centroid_bounds.expand(&Aabb::new_from_point(centroid));
+    }
+
+    let count = end - start;
+    if count <= 1 {
+        return BVHNode::leaf(start, count, aabb);
+    }
+
+    let leaf_cost = config.intersection_cost * count as f32;
+    let split = best_split(&objects[start..end], &aabb, &centroid_bounds, config);
+
+    let mid = match split {
+        Some(split) if split.cost < leaf_cost || count > config.max_leaf_size => {
+            let interval = centroid_bounds.axis_interval(split.axis);
+            let mid = start
+                + partition(&mut objects[start..end], |object| {
+                    bin_index(
+                        object,
+                        split.axis,
+                        interval.min,
+                        interval.size(),
+                        config.bins,
+                    ) <= split.bin
+                });
+
+            // Every centroid landed on one side, only possible through rounding
+            if mid == start || mid == end {
+                median_split(objects, start, end, &centroid_bounds)
+            } else {
+                mid
+            }
+        }
+        // All centroids coincide, the heuristic can't separate them
+        None if count > config.max_leaf_size => median_split(objects, start, end, &centroid_bounds),
+        _ => return BVHNode::leaf(start, count, aabb),
+    };
+
+    let left = build(objects, start, mid, config);
+    let right = build(objects, mid, end, config);
+    BVHNode::interior(left, right)
+}
+
+fn best_split(
+    objects: &[Primitive],
+    aabb: &Aabb,
+    centroid_bounds: &Aabb,
+    config: &SahConfig,
+) -> Option<Split> {
+    let bins = config.bins.max(2);
+    let area = aabb.surface_area().max(f32::MIN_POSITIVE);
+    let mut best: Option<Split> = None;
+
+    for &axis in Axis::iter() {
+        let interval = centroid_bounds.axis_interval(axis);
+        if interval.size() <= 0.0 {
+            continue;
+        }
+
+        let mut binned = vec![
+            Bin {
+                aabb: Aabb::empty(),
+                count: 0,
+            };
+            bins
+        ];
+        for object in objects {
+            let bin = &mut binned[bin_index(object, axis, interval.min, interval.size(), bins)];
+            bin.aabb.expand(object.bounding_box());
+            bin.count += 1;
+        }
+
+        // Sweep from the right to get the cost of everything past each plane
+        let mut right_area = vec![0.0; bins];
+        let mut right_count = vec![0; bins];
+        let mut accumulated = Aabb::empty();
+        let mut total = 0;
+        for idx in (1..bins).rev() {
+            accumulated.expand(&binned[idx].aabb);
+            total += binned[idx].count;
+            right_area[idx] = accumulated.surface_area();
+            right_count[idx] = total;
+        }
+
+        let mut accumulated = Aabb::empty();
+        let mut total = 0;
+        for idx in 0..(bins - 1) {
+            accumulated.expand(&binned[idx].aabb);
+            total += binned[idx].count;
+
+            if total == 0 || right_count[idx + 1] == 0 {
+                continue;
+            }
+
+            let cost = config.traversal_cost
+                + config.intersection_cost
+                    * (total as f32 * accumulated.surface_area()
+                        + right_count[idx + 1] as f32 * right_area[idx + 1])
+                    / area;
+
+            if best.as_ref().is_none_or(|best| cost < best.cost) {
+                best = Some(Split {
+                    axis,
+                    bin: idx,
+                    cost,
+                });
+            }
+        }
+    }
+
+    best
+}
+
+fn bin_index(object: &Primitive, axis: Axis, min: f32, extent: f32, bins: usize) -> usize {
+    let bins = bins.max(2);
+    let centroid = object.bounding_box().centroid().axis(axis);
+    (((centroid - min) / extent * bins as f32) as usize).min(bins - 1)
+}
+
+// Moves objects matching the predicate to the front, returns how many there are
+fn partition<F>(objects: &mut [Primitive], predicate: F) -> usize
+where
+    F: Fn(&Primitive) -> bool,
+{
+    let mut mid = 0;
+    for idx in 0..objects.len() {
+        if predicate(&objects[idx]) {
+            objects.swap(idx, mid);
+            mid += 1;
+        }
+    }
+    mid
+}
+
+fn median_split(
+    objects: &mut [Primitive],
+    start: usize,
+    end: usize,
+    centroid_bounds: &Aabb,
+) -> usize {
+    let axis = centroid_bounds.longest_axis();
+    objects[start..end].sort_by(|a, b| {
+        a.bounding_box()
+            .centroid()
+            .axis(axis)
+            .total_cmp(&b.bounding_box().centroid().axis(axis))
+    });
+    start + (end - start) / 2
+}
@@ -1,16 +1,62 @@
 use rand::prelude::*;
 use rand_chacha::ChaCha8Rng;
 use std::{
-    sync::{Arc, Mutex},
+    io::Write,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
     thread::{self},
     time::Instant,
 };
 
 use crate::{
-    bvh::BVHNode, color::Color, image::Image, interval::Interval, point::Point,
-    primitive::Primitive, ray::Ray, vec3::Vec3,
+    aov::{Aov, PathSample},
+    background::Background,
+    bvh::FlatBvh,
+    color::Color,
+    hittable::HitRecord,
+    image::{
+        Image, ImageFormat,
+        exr::{self, ExrCompression},
+        png::PngBitDepth,
+    },
+    interval::Interval,
+    material::Material,
+    pdf::{self, Pdf},
+    point::Point,
+    primitive::Primitive,
+    ray::Ray,
+    vec3::Vec3,
 };
 
+// One color per pixel, row by row from the top left
+pub(crate) type Pixels = Box<[Color]>;
+
+// Square tile edge in pixels
+const TILE_SIZE: u32 = 16;
+
+// How ray_color gathers light
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Integrator {
+    // Bsdf samples, half of the diffuse ones aimed at the lights instead
+    Path,
+
+    // Next event estimation: a shadow ray to one random light at every diffuse bounce, combined
+    // with the bsdf samples hitting lights by multiple importance sampling
+    Nee,
+}
+
+// Pixel rectangle [x0, x1) x [y0, y1)
+#[derive(Clone, Copy, Debug)]
+struct Tile {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+}
+
 #[allow(dead_code)]
 pub(crate) struct Camera {
     pub(crate) position: Point,
@@ -37,83 +83,235 @@ pub(crate) struct Camera {
     pub(crate) pixel_delta_u: Vec3,
     pub(crate) pixel_delta_v: Vec3,
     pub(crate) samples_per_pixel: u32,
-    pub(crate) max_depth: u32,
+    // Bounces before Russian roulette can end a path, and an optional hard limit on top of it
+    pub(crate) min_depth: u32,
+    pub(crate) max_depth: Option<u32>,
+    pub(crate) integrator: Integrator,
+    // Render passes to produce next to the beauty image
+    pub(crate) aovs: Vec<Aov>,
+
+    // Radiance of rays leaving the scene, the sky gradient unless a scene overrides it
+    pub(crate) background: Background,
+
+    // Open and close times, each ray gets a random time in between so moving primitives blur.
+    // Closed at time 0 by default, which draws no extra random numbers
+    pub(crate) shutter: Interval,
+
+    // Render threads, all available cores when unset
+    pub(crate) threads: Option<usize>,
+    pub(crate) seed: u64,
 }
 
 impl Camera {
+    // Beauty image and the selected passes in the order of aovs
     pub(crate) fn render(
         &self,
-        bvh_root: Arc<BVHNode>,
+        bvh: &FlatBvh,
         world: Arc<Vec<Primitive>>,
-    ) -> std::io::Result<Box<[Color]>> {
-        // Render
-
+    ) -> std::io::Result<(Pixels, Vec<Pixels>)> {
         let start_of_render = Instant::now();
 
-        let num_threads = usize::from(thread::available_parallelism()?);
+        let num_threads = match self.threads {
+            Some(threads) => threads,
+            None => usize::from(thread::available_parallelism()?),
+        };
 
-        let samples_per_pixel_per_thread = (self.samples_per_pixel / num_threads as u32).max(1);
+        let tiles = self.tiles();
+        let next_tile = AtomicUsize::new(0);
+        let (sender, receiver) = mpsc::channel::<(Tile, Vec<Color>)>();
 
-        println!("Rendering on {num_threads} thread(s)");
+        println!("Rendering {} tiles on {num_threads} thread(s)", tiles.len());
 
-        let images = Arc::new(Mutex::new(Vec::<Vec<Color>>::new()));
+        let black =
+            vec![Color::new(0.0, 0.0, 0.0); (self.image_width * self.image_height) as usize];
+        let mut pixels = black.clone().into_boxed_slice();
+        let mut passes = vec![black.into_boxed_slice(); self.aovs.len()];
 
-        thread::scope(|s| {
-            for idx in 0..num_threads {
-                let images_clone = images.clone();
-                let world_pointer = world.clone();
-                let bvh_pointer = bvh_root.clone();
-                let max_depth = self.max_depth;
+        let lights = Primitive::lights(&world);
+        let materials = if self.aovs.contains(&Aov::Material) {
+            Primitive::materials(&world)
+        } else {
+            Vec::new()
+        };
 
+        thread::scope(|s| {
+            for _ in 0..num_threads {
+                let sender = sender.clone();
+                let tiles = &tiles;
+                let next_tile = &next_tile;
+                let world = &world;
+                let lights = &lights;
+                let materials = &materials;
+
+                // Threads take the next unrendered tile until none are left
                 s.spawn(move || {
-                    let mut rng = ChaCha8Rng::seed_from_u64(idx as u64);
-                    let mut output = Vec::<Color>::new();
-                    for i in 0..self.image_height {
-                        for j in 0..self.image_width {
-                            let pixel_color = (0..samples_per_pixel_per_thread).fold(
-                                Color::new(0.0, 0.0, 0.0),
-                                |acc, _| {
-                                    let ray = Self::get_ray(self, &mut rng, j, i);
-
-                                    acc + Self::ray_color(
-                                        &mut rng,
-                                        ray,
-                                        max_depth,
-                                        &bvh_pointer,
-                                        &world_pointer,
-                                    )
-                                },
-                            ) / samples_per_pixel_per_thread as f32;
-
-                            output.push(pixel_color);
+                    loop {
+                        let idx = next_tile.fetch_add(1, Ordering::Relaxed);
+                        let Some(tile) = tiles.get(idx) else {
+                            break;
+                        };
+                        let output = self.render_tile(tile, bvh, world, lights, materials);
+                        if sender.send((*tile, output)).is_err() {
+                            break;
                         }
                     }
-                    {
-                        images_clone.lock().unwrap().push(output);
-                    }
                 });
             }
-        });
-
-        let images = images.lock().unwrap();
+            drop(sender);
+
+            // Tiles arrive in completion order, each one only covers its own pixels
+            for (done, (tile, output)) in receiver.iter().enumerate() {
+                let width = (tile.x1 - tile.x0) as usize;
+                let stride = 1 + passes.len();
+                for (row, colors) in (tile.y0..tile.y1).zip(output.chunks(width * stride)) {
+                    let start = (row * self.image_width + tile.x0) as usize;
+                    for (x, pixel) in colors.chunks(stride).enumerate() {
+                        pixels[start + x] = pixel[0];
+                        for (pass, value) in passes.iter_mut().zip(&pixel[1..]) {
+                            pass[start + x] = *value;
+                        }
+                    }
+                }
 
-        let avg = (0..(self.image_height * self.image_width) as usize)
-            .map(|idx| {
-                (0..images.len()).fold(Color::new(0.0, 0.0, 0.0), |acc, img| acc + images[img][idx])
-                    / images.len() as f32
-            })
-            .collect::<Box<[Color]>>();
+                print!("\rRendered {}/{} tiles", done + 1, tiles.len());
+                let _ = std::io::stdout().flush();
+            }
+            println!();
+        });
 
         println!(
             "Rendered in {:.2} seconds",
             start_of_render.elapsed().as_secs_f32()
         );
 
-        Ok(avg)
+        Ok((pixels, passes))
+    }
+
+    // Row major tiles covering the image, edge tiles are cut to size
+    fn tiles(&self) -> Vec<Tile> {
+        let mut tiles = Vec::new();
+        for y0 in (0..self.image_height).step_by(TILE_SIZE as usize) {
+            for x0 in (0..self.image_width).step_by(TILE_SIZE as usize) {
+                tiles.push(Tile {
+                    x0,
+                    y0,
+                    x1: (x0 + TILE_SIZE).min(self.image_width),
+                    y1: (y0 + TILE_SIZE).min(self.image_height),
+                });
+            }
+        }
+        tiles
+    }
+
+    fn render_tile(
+        &self,
+        tile: &Tile,
+        bvh: &FlatBvh,
+        world: &Arc<Vec<Primitive>>,
+        lights: &[&Primitive],
+        materials: &[&Arc<Material>],
+    ) -> Vec<Color> {
+        let pixel_count = ((tile.x1 - tile.x0) * (tile.y1 - tile.y0)) as usize;
+        let mut output = Vec::with_capacity(pixel_count * (1 + self.aovs.len()));
+        for i in tile.y0..tile.y1 {
+            for j in tile.x0..tile.x1 {
+                output.extend(self.render_pixel(j, i, bvh, world, lights, materials));
+            }
+        }
+        output
+    }
+
+    // Average of all samples for pixel (x, y) followed by the selected passes, only depends on
+    // the seed and the pixel so it can be re-rendered on its own
+    pub(crate) fn render_pixel(
+        &self,
+        x: u32,
+        y: u32,
+        bvh: &FlatBvh,
+        world: &Arc<Vec<Primitive>>,
+        lights: &[&Primitive],
+        materials: &[&Arc<Material>],
+    ) -> Vec<Color> {
+        let mut rng = self.pixel_rng(x, y);
+        let mut output = vec![Color::new(0.0, 0.0, 0.0); 1 + self.aovs.len()];
+
+        for sample in 0..self.samples_per_pixel {
+            // Each sample starts at its own block of 2^32 words in the pixel's stream
+            rng.set_word_pos((sample as u128) << 32);
+
+            let ray = Self::get_ray(self, &mut rng, x, y);
+            let path = self.ray_color(&mut rng, ray, bvh, world, lights);
+
+            output[0] += path.color;
+            for (aov, value) in self.aovs.iter().zip(&mut output[1..]) {
+                if !aov.is_id() || sample == 0 {
+                    *value += path.value(*aov, materials);
+                }
+            }
+        }
+
+        for (idx, value) in output.iter_mut().enumerate() {
+            if idx == 0 || !self.aovs[idx - 1].is_id() {
+                *value /= self.samples_per_pixel as f32;
+            }
+        }
+        output
     }
 
-    pub(crate) fn write_img(&self, pixels: &[Color]) -> std::io::Result<()> {
-        Image::write_p6(self.image_width, self.image_height, pixels)
+    // One ChaCha stream per pixel, keyed by the render seed
+    fn pixel_rng(&self, x: u32, y: u32) -> ChaCha8Rng {
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        rng.set_stream(((y as u64) << 32) | x as u64);
+        rng
+    }
+
+    // Passes in the order of aovs become extra exr channels, stored as full floats since depth
+    // and position need the precision. Png only takes the alpha pass, as its alpha channel. The bit
+    // depth only applies to png, the compression to exr
+    pub(crate) fn write_img(
+        &self,
+        path: &str,
+        format: ImageFormat,
+        png_depth: PngBitDepth,
+        exr_compression: ExrCompression,
+        pixels: &[Color],
+        passes: &[Pixels],
+    ) -> std::io::Result<()> {
+        let (width, height) = (self.image_width, self.image_height);
+        match format {
+            ImageFormat::Png => {
+                let alpha = self
+                    .aovs
+                    .iter()
+                    .zip(passes)
+                    .find(|(aov, _)| **aov == Aov::Alpha)
+                    .map(|(_, pass)| pass.iter().map(|color| color.r).collect::<Vec<_>>());
+                Image::write_png(path, width, height, pixels, alpha.as_deref(), png_depth)
+            }
+            ImageFormat::Exr => {
+                let pixel_type = if passes.is_empty() {
+                    exr::ExrPixelType::Half
+                } else {
+                    exr::ExrPixelType::Float
+                };
+                let channels = self
+                    .aovs
+                    .iter()
+                    .zip(passes)
+                    .flat_map(|(aov, pass)| aov.channels(pass))
+                    .collect();
+                Image::write_exr(
+                    path,
+                    width,
+                    height,
+                    pixels,
+                    channels,
+                    pixel_type,
+                    exr_compression,
+                )
+            }
+            _ => Image::write_as(path, format, width, height, pixels),
+        }
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -127,7 +325,7 @@ impl Camera {
         image_width: u32,
         vertical_fov: f32,
         samples_per_pixel: u32,
-        max_depth: u32,
+        max_depth: Option<u32>,
     ) -> Self {
         // Image
         let desired_height = image_width as f32 / aspect_ratio;
@@ -188,7 +386,41 @@ impl Camera {
             pixel_delta_u,
             pixel_delta_v,
             samples_per_pixel,
+            min_depth: 3,
             max_depth,
+            integrator: Integrator::Nee,
+            aovs: Vec::new(),
+            background: Background::sky(),
+            shutter: Interval::new(0.0, 0.0),
+            threads: None,
+            seed: 0,
+        }
+    }
+
+    // Same view with a different image size, keeping scene level settings
+    pub(crate) fn resized(self, image_width: u32, aspect_ratio: f32) -> Self {
+        let resized = Camera::new(
+            self.position,
+            self.position - self.w,
+            self.v,
+            self.focal_length,
+            self.defocus_angle,
+            aspect_ratio,
+            image_width,
+            self.vertical_fov,
+            self.samples_per_pixel,
+            self.max_depth,
+        );
+
+        Camera {
+            min_depth: self.min_depth,
+            integrator: self.integrator,
+            aovs: self.aovs,
+            background: self.background,
+            shutter: self.shutter,
+            threads: self.threads,
+            seed: self.seed,
+            ..resized
         }
     }
 
@@ -210,7 +442,13 @@ impl Camera {
         };
         let ray_direction = pixel_sample - ray_origin;
 
-        Ray::new(ray_origin, ray_direction)
+        let ray_time = if self.shutter.size() > 0.0 {
+            rng.random_range(self.shutter.min..self.shutter.max)
+        } else {
+            self.shutter.min
+        };
+
+        Ray::new(ray_origin, ray_direction, ray_time)
     }
 
     fn sample_square<T>(&self, rng: &mut T) -> Vec3
@@ -230,31 +468,345 @@ impl Camera {
         self.position + (p.x * self.defocus_disk_u) + (p.y * self.defocus_disk_v)
     }
 
+    // Follows one path, adding the light it finds weighted by the throughput of the bounces so
+    // far. Russian roulette ends dim paths once they're past min_depth, the survivors are
+    // weighted up so the estimate stays unbiased
+    // https://pbr-book.org/4ed/Monte_Carlo_Integration/Improving_Efficiency#RussianRoulette
     fn ray_color(
+        &self,
         rng: &mut ChaCha8Rng,
-        ray: Ray,
-        depth: u32,
-        bvh_root: &Arc<BVHNode>,
+        mut ray: Ray,
+        bvh: &FlatBvh,
         world: &Arc<Vec<Primitive>>,
+        lights: &[&Primitive],
+    ) -> PathSample {
+        let ray_interval = Interval::new(0.001, f32::INFINITY);
+        let light_chance = 1.0 / self.light_count(lights).max(1) as f32;
+
+        let mut sample = PathSample::new();
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        // Density of the diffuse sample the ray came from, when next event estimation could have
+        // found the same light. None for camera rays and specular bounces
+        let mut bsdf_pdf: Option<f32> = None;
+
+        for bounce in 0.. {
+            if self.max_depth.is_some_and(|max_depth| bounce >= max_depth) {
+                break;
+            }
+
+            let Some((object, hit)) = bvh.hit_object(rng, ray, ray_interval, world) else {
+                let mut radiance = self.background.sample(ray.direction);
+                if let (Some(bsdf_pdf), Some(map)) = (bsdf_pdf, self.background.light()) {
+                    let light_pdf = light_chance * map.pdf_value(ray.direction);
+                    radiance *= pdf::power_heuristic(bsdf_pdf, light_pdf);
+                }
+                sample.add(throughput * radiance, bounce);
+                break;
+            };
+
+            let mut emitted = hit.material.emitted(hit.u, hit.v, hit.position);
+            if let Some(bsdf_pdf) = bsdf_pdf
+                && matches!(*hit.material, Material::DiffuseLight(_))
+            {
+                // Only the light that was hit could have been sampled in this direction, emitters
+                // that aren't in lights are never sampled and keep all of their light
+                let at_hit = Interval::new(hit.t * (1.0 - 1e-4), hit.t * (1.0 + 1e-4));
+                let light_pdf = light_chance
+                    * lights
+                        .iter()
+                        .map(|light| light.pdf_value(ray.origin, ray.direction, ray.time, at_hit))
+                        .sum::<f32>();
+                emitted *= pdf::power_heuristic(bsdf_pdf, light_pdf);
+            }
+            sample.add(throughput * emitted, bounce);
+            if bounce == 0 && !self.aovs.is_empty() {
+                sample.first_hit = Some((object, ray, hit.clone()));
+            }
+
+            let Some(scatter) = hit.material.scatter(rng, ray, &hit) else {
+                break;
+            };
+            let material_pdf = hit.material.pdf(&hit).filter(|_| !scatter.specular);
+            sample.first_specular = sample.first_specular.or(Some(scatter.specular));
+
+            let (direction, bsdf, pdf) = match (self.integrator, &material_pdf) {
+                // Half the diffuse bounces head for a light instead, weighted by the density of
+                // the mixture. The material's own sample stands in for its half
+                (Integrator::Path, Some(material_pdf)) if !lights.is_empty() => {
+                    let light_pdf = Pdf::Primitives {
+                        primitives: lights,
+                        origin: hit.position,
+                        time: ray.time,
+                    };
+                    let mixture = Pdf::Mixture(&light_pdf, material_pdf);
+
+                    if rng.random_bool(0.5) {
+                        (
+                            scatter.direction,
+                            scatter.bsdf,
+                            mixture.value(scatter.direction),
+                        )
+                    } else {
+                        let direction = light_pdf.generate(rng);
+                        (
+                            direction,
+                            hit.material.bsdf(&hit, direction),
+                            mixture.value(direction),
+                        )
+                    }
+                }
+                // Light reached by sampling is one bounce further, like the bsdf sample that
+                // could have found it, so it has to fit under the cap too
+                (Integrator::Nee, Some(material_pdf)) => {
+                    if self
+                        .max_depth
+                        .is_none_or(|max_depth| bounce + 1 < max_depth)
+                    {
+                        let light = self.sample_light(
+                            rng,
+                            &hit,
+                            ray.time,
+                            material_pdf,
+                            bvh,
+                            world,
+                            lights,
+                        );
+                        sample.add(throughput * light, bounce + 1);
+                    }
+                    (scatter.direction, scatter.bsdf, scatter.pdf)
+                }
+                _ => (scatter.direction, scatter.bsdf, scatter.pdf),
+            };
+
+            // Directions the sample can't have come from carry no light
+            if pdf <= 0.0 {
+                break;
+            }
+
+            throughput = throughput * bsdf / pdf;
+            bsdf_pdf = match self.integrator {
+                Integrator::Path => None,
+                Integrator::Nee => material_pdf.map(|_| pdf),
+            };
+            ray = Ray::new(hit.position, direction, ray.time);
+
+            if bounce + 1 >= self.min_depth {
+                let survival = throughput.r.max(throughput.g).max(throughput.b).min(1.0);
+                if rng.random::<f32>() >= survival {
+                    break;
+                }
+                throughput /= survival;
+            }
+        }
+
+        sample
+    }
+
+    // Light from one random light or the environment reaching hit, weighted against the
+    // material sampling the same direction
+    #[allow(clippy::too_many_arguments)]
+    fn sample_light(
+        &self,
+        rng: &mut ChaCha8Rng,
+        hit: &HitRecord,
+        time: f32,
+        material_pdf: &Pdf,
+        bvh: &FlatBvh,
+        world: &Arc<Vec<Primitive>>,
+        lights: &[&Primitive],
     ) -> Color {
-        if depth == 0 {
-            return Color::new(0.0, 0.0, 0.0);
+        let black = Color::new(0.0, 0.0, 0.0);
+        let count = self.light_count(lights);
+        let environment = self.background.light();
+        if lights.is_empty() && environment.is_none() {
+            return black;
         }
 
         let ray_interval = Interval::new(0.001, f32::INFINITY);
+        let (direction, radiance, distance, light_pdf) =
+            match lights.get(rng.random_range(0..count)) {
+                Some(light) => {
+                    let direction = light.random(rng, hit.position, time);
+                    let ray = Ray::new(hit.position, direction, time);
+                    let Some(light_hit) = light.hit(rng, ray, ray_interval) else {
+                        return black;
+                    };
+                    let radiance =
+                        light_hit
+                            .material
+                            .emitted(light_hit.u, light_hit.v, light_hit.position);
+                    let light_pdf = light.pdf_value(hit.position, direction, time, ray_interval);
+                    (direction, radiance, light_hit.t * (1.0 - 1e-4), light_pdf)
+                }
+                None => match environment {
+                    Some(map) => {
+                        let direction = map.random(rng);
+                        (
+                            direction,
+                            map.radiance(direction),
+                            f32::INFINITY,
+                            map.pdf_value(direction),
+                        )
+                    }
+                    None => return black,
+                },
+            };
+
+        let light_pdf = light_pdf / count as f32;
+        let bsdf_pdf = material_pdf.value(direction);
+        if light_pdf <= 0.0 || bsdf_pdf <= 0.0 {
+            return black;
+        }
+
+        let shadow_ray = Ray::new(hit.position, direction, time);
+        if bvh.occluded(rng, shadow_ray, Interval::new(0.001, distance), world) {
+            return black;
+        }
+
+        let weight = pdf::power_heuristic(light_pdf, bsdf_pdf);
+        hit.material.bsdf(hit, direction) * radiance * weight / light_pdf
+    }
 
-        let potential_hit = bvh_root.hit(ray, ray_interval, world);
+    // Primitive lights and the environment if it can be sampled
+    fn light_count(&self, lights: &[&Primitive]) -> usize {
+        lights.len() + self.background.light().is_some() as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::{Camera, Integrator, Pixels};
+    use crate::{
+        bvh::{BuildStrategy, FlatBvh},
+        color::Color,
+        examples,
+        primitive::Primitive,
+    };
+
+    struct Scene {
+        bvh: FlatBvh,
+        world: Arc<Vec<Primitive>>,
+    }
+
+    // Small Cornell box, lit only by its ceiling light
+    fn cornell(image_width: u32, samples_per_pixel: u32) -> (Camera, Scene) {
+        let (camera, bvh_root, world) = examples::cornell_box(BuildStrategy::Median);
+        let mut camera = camera.resized(image_width, 1.0);
+        camera.samples_per_pixel = samples_per_pixel;
+        let bvh = bvh_root.flatten();
+        (camera, Scene { bvh, world })
+    }
+
+    fn render(camera: &Camera, scene: &Scene) -> Pixels {
+        camera.render(&scene.bvh, scene.world.clone()).unwrap().0
+    }
+
+    fn mean(pixels: &[Color]) -> f32 {
+        let sum = pixels.iter().map(|c| c.r + c.g + c.b).sum::<f32>();
+        sum / (3 * pixels.len()) as f32
+    }
+
+    fn bits(pixels: &[Color]) -> Vec<[u32; 3]> {
+        pixels
+            .iter()
+            .map(|c| [c.r.to_bits(), c.g.to_bits(), c.b.to_bits()])
+            .collect()
+    }
+
+    #[test]
+    fn renders_are_independent_of_thread_count() {
+        // Tiles are 16 pixels, so threads split the image differently on every run
+        let (mut camera, scene) = cornell(40, 4);
+        camera.seed = 7;
+        camera.threads = Some(1);
+        let single = render(&camera, &scene);
+        camera.threads = Some(4);
+        let multiple = render(&camera, &scene);
+        assert_eq!(bits(&single), bits(&multiple));
+
+        // A pixel on its own is the same as in the full render
+        let lights = Primitive::lights(&scene.world);
+        let (x, y) = (21, 13);
+        let pixel = camera.render_pixel(x, y, &scene.bvh, &scene.world, &lights, &[]);
+        let idx = (y * camera.image_width + x) as usize;
+        assert_eq!(bits(&pixel), bits(&single[idx..=idx]));
+
+        // Every sample can be re-rendered from its pixel stream and word position alone
+        let sample = |rng: &mut ChaCha8Rng, idx: u32| {
+            rng.set_word_pos((idx as u128) << 32);
+            let ray = camera.get_ray(rng, x, y);
+            let path = camera.ray_color(rng, ray, &scene.bvh, &scene.world, &lights);
+            bits(&[path.color])
+        };
+        let mut rng = camera.pixel_rng(x, y);
+        let in_order = (0..4).map(|idx| sample(&mut rng, idx)).collect::<Vec<_>>();
+        for idx in (0..4).rev() {
+            let mut rng = camera.pixel_rng(x, y);
+            assert_eq!(sample(&mut rng, idx), in_order[idx as usize], "{idx}");
+        }
+        assert_ne!(in_order[0], in_order[1]);
+
+        // Another seed gives other noise
+        let mut reseeded = ChaCha8Rng::seed_from_u64(8);
+        reseeded.set_stream(camera.pixel_rng(x, y).get_stream());
+        assert_ne!(sample(&mut reseeded, 0), in_order[0]);
+    }
+
+    #[test]
+    fn integrators_converge_to_the_same_image() {
+        let (mut camera, scene) = cornell(12, 256);
+        camera.integrator = Integrator::Path;
+        let path = mean(&render(&camera, &scene));
+        camera.integrator = Integrator::Nee;
+        let nee = mean(&render(&camera, &scene));
+
+        assert!(nee > 0.0);
+        assert!((path - nee).abs() < 0.02 * nee, "{path} {nee}");
+    }
+
+    #[test]
+    fn russian_roulette_is_unbiased() {
+        // Without roulette, capped deep enough for the walls to have absorbed nearly all light
+        let (mut camera, scene) = cornell(12, 256);
+        camera.min_depth = 64;
+        camera.max_depth = Some(64);
+        let reference = mean(&render(&camera, &scene));
+
+        // Unbounded paths still end
+        camera.min_depth = 3;
+        camera.max_depth = None;
+        let unbounded = mean(&render(&camera, &scene));
+        assert!(
+            (unbounded - reference).abs() < 0.02 * reference,
+            "{unbounded} {reference}"
+        );
+    }
+
+    #[test]
+    fn russian_roulette_waits_for_min_depth() {
+        let (mut camera, scene) = cornell(12, 8);
+        let without_roulette = |camera: &mut Camera, max_depth| {
+            camera.max_depth = Some(max_depth);
+            camera.min_depth = u32::MAX;
+            bits(&render(camera, &scene))
+        };
 
-        if let Some(hit) = potential_hit {
-            let (scattered_ray, attenuation) =
-                hit.material
-                    .scatter(rng, ray, hit.t, hit.u, hit.v, hit.normal, hit.front_face);
-            return attenuation * Self::ray_color(rng, scattered_ray, depth - 1, bvh_root, world);
+        // Paths capped at min_depth bounces end before any roulette could, so the image is
+        // exactly the one without roulette
+        for depth in [1, 2, 5] {
+            let expected = without_roulette(&mut camera, depth);
+            camera.min_depth = depth;
+            assert_eq!(bits(&render(&camera, &scene)), expected, "{depth}");
         }
 
-        // Background gradient
-        let unit_direction = ray.direction.unit();
-        let a = (unit_direction.y + 1.0) * 0.5;
-        (1.0 - a) * Color::new(1.0, 1.0, 1.0) + a * Color::new(0.5, 0.7, 1.0)
+        // Roulette one bounce earlier ends some of the paths
+        let expected = without_roulette(&mut camera, 5);
+        camera.min_depth = 4;
+        assert_ne!(bits(&render(&camera, &scene)), expected);
     }
 }
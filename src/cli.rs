@@ -0,0 +1,454 @@
+use std::str::FromStr;
+
+use crate::{
+    aov::Aov,
+    bvh::{BuildStrategy, SahConfig},
+    camera::Integrator,
+    image::{ImageFormat, exr::ExrCompression, png::PngBitDepth},
+};
+
+pub(crate) const USAGE: &str = "Usage: weekend-rt [OPTIONS] [SCENE]
+
+Renders a built-in scene by name or a scene file by path, picked from a menu when no
+scene is given.
+
+Options:
+  -o, --output <PATH>        Output image, format from the extension (ppm, png, hdr, exr)
+                             [default: render.png]
+  -f, --format <FORMAT>      Output format, overriding the extension
+      --png-depth <BITS>     Bits per channel of png outputs, 8 or 16 [default: 8]
+      --exr-compression <NAME>
+                             Compression of exr outputs, none, rle, zips (one scanline per
+                             chunk) or zip (16 scanlines) [default: zip]
+  -w, --width <PIXELS>       Image width
+  -a, --aspect-ratio <RATIO> Width over height, as a number or W:H
+  -s, --spp <COUNT>          Samples per pixel
+  -d, --max-depth <COUNT>    Maximum ray bounces, or none to leave it to Russian roulette
+      --min-depth <COUNT>    Bounces before Russian roulette can end a path [default: 3]
+      --integrator <NAME>    Light transport, path or nee (next event estimation)
+                             [default: nee]
+  -t, --threads <COUNT>      Render threads [default: all cores]
+      --seed <SEED>          Random number generator seed [default: 0]
+      --pixel <X,Y>          Render only this pixel and print its color, for debugging
+      --aov <PASSES>         Render passes as a comma separated list, or all: depth, normal,
+                             albedo, object, material, uv, position, alpha, direct,
+                             indirect, diffuse, specular. Added to exr outputs as extra
+                             channels, alpha also to png outputs
+      --aov-files            Write each pass as its own image next to every output instead
+      --bvh <STRATEGY>       BVH build strategy for the world and instanced geometry, median
+                             or sah, optionally with sah:bins=16,leaf=4,traversal=1,
+                             intersection=1 [default: median]
+      --bvh-stats            Print top-level BVH node count, depth and SAH cost instead of
+                             rendering
+  -l, --list                 List the built-in scenes
+  -h, --help                 Print this message";
+
+// Settings from the command line, unset values keep the scene defaults
+#[derive(Debug, Default)]
+pub(crate) struct Options {
+    pub(crate) scene: Option<String>,
+    pub(crate) output: Option<String>,
+    pub(crate) format: Option<ImageFormat>,
+    pub(crate) png_depth: Option<PngBitDepth>,
+    pub(crate) exr_compression: Option<ExrCompression>,
+    pub(crate) image_width: Option<u32>,
+    pub(crate) aspect_ratio: Option<f32>,
+    pub(crate) samples_per_pixel: Option<u32>,
+    pub(crate) min_depth: Option<u32>,
+    // Some(None) lifts the scene's limit
+    pub(crate) max_depth: Option<Option<u32>>,
+    pub(crate) integrator: Option<Integrator>,
+    pub(crate) threads: Option<usize>,
+    pub(crate) seed: Option<u64>,
+    pub(crate) pixel: Option<(u32, u32)>,
+    pub(crate) aovs: Vec<Aov>,
+    pub(crate) aov_files: bool,
+    pub(crate) bvh: Option<BuildStrategy>,
+    pub(crate) bvh_stats: bool,
+    pub(crate) list: bool,
+    pub(crate) help: bool,
+}
+
+impl Options {
+    pub(crate) fn parse<I>(args: I) -> Result<Self, String>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            if !arg.starts_with('-') || arg == "-" {
+                if options.scene.is_some() {
+                    return Err(format!("unexpected argument '{arg}'"));
+                }
+                options.scene = Some(arg);
+                continue;
+            }
+
+            // Both "--flag value" and "--flag=value"
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => {
+                    (flag.to_string(), Some(value.to_string()))
+                }
+                _ => (arg.clone(), None),
+            };
+            let mut value = |name: &str| {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("missing value for {name}"))
+            };
+
+            match flag.as_str() {
+                "-o" | "--output" => options.output = Some(value(&flag)?),
+                "-f" | "--format" => {
+                    let format = value(&flag)?;
+                    options.format =
+                        Some(ImageFormat::from_extension(&format).ok_or_else(|| {
+                            format!("unknown format '{format}', expected ppm, png, hdr or exr")
+                        })?);
+                }
+                "--png-depth" => options.png_depth = Some(png_depth(&value(&flag)?)?),
+                "--exr-compression" => {
+                    options.exr_compression = Some(exr_compression(&value(&flag)?)?)
+                }
+                "-w" | "--width" => options.image_width = Some(positive(&flag, &value(&flag)?)?),
+                "-a" | "--aspect-ratio" => {
+                    options.aspect_ratio = Some(aspect_ratio(&value(&flag)?)?)
+                }
+                "-s" | "--spp" => {
+                    options.samples_per_pixel = Some(positive(&flag, &value(&flag)?)?)
+                }
+                "-d" | "--max-depth" => {
+                    options.max_depth = match value(&flag)?.as_str() {
+                        "none" => Some(None),
+                        depth => Some(Some(positive(&flag, depth)?)),
+                    }
+                }
+                "--min-depth" => options.min_depth = Some(number(&flag, &value(&flag)?)?),
+                "--integrator" => options.integrator = Some(integrator(&value(&flag)?)?),
+                "-t" | "--threads" => options.threads = Some(positive(&flag, &value(&flag)?)?),
+                "--seed" => options.seed = Some(number(&flag, &value(&flag)?)?),
+                "--pixel" => options.pixel = Some(pixel(&value(&flag)?)?),
+                "--aov" => {
+                    for aov in aovs(&value(&flag)?)? {
+                        if !options.aovs.contains(&aov) {
+                            options.aovs.push(aov);
+                        }
+                    }
+                }
+                "--aov-files" => options.aov_files = true,
+                "--bvh" => options.bvh = Some(strategy(&value(&flag)?)?),
+                "--bvh-stats" => options.bvh_stats = true,
+                "-l" | "--list" => options.list = true,
+                "-h" | "--help" => options.help = true,
+                _ => return Err(format!("unknown option '{arg}'")),
+            }
+        }
+
+        Ok(options)
+    }
+
+    // Output path with its format, an explicit format replaces the extension
+    pub(crate) fn output(&self) -> Result<(String, ImageFormat), String> {
+        let output = match (&self.output, self.format) {
+            (Some(path), Some(format)) => (path.clone(), format),
+            (Some(path), None) => ImageFormat::from_path(path)
+                .map(|format| (path.clone(), format))
+                .ok_or_else(|| {
+                    format!("can't tell the format of '{path}', add an extension or use --format")
+                })?,
+            (None, Some(format)) => (format!("render.{}", format.extension()), format),
+            (None, None) => ("render.png".to_string(), ImageFormat::Png),
+        };
+
+        if self.png_depth.is_some() && output.1 != ImageFormat::Png {
+            return Err("--png-depth needs a png output".to_string());
+        }
+        if self.exr_compression.is_some() && output.1 != ImageFormat::Exr {
+            return Err("--exr-compression needs an exr output".to_string());
+        }
+        // Png outputs only have room for alpha
+        let channels = match output.1 {
+            ImageFormat::Exr => Aov::ALL.as_slice(),
+            ImageFormat::Png => &[Aov::Alpha],
+            _ => &[],
+        };
+        if !self.aov_files && self.aovs.iter().any(|aov| !channels.contains(aov)) {
+            return Err(
+                "passes are written as exr channels, png only takes alpha, use an exr output or \
+                 --aov-files"
+                    .to_string(),
+            );
+        }
+        Ok(output)
+    }
+}
+
+fn number<T: FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{value}' for {flag}"))
+}
+
+fn positive<T: FromStr + Default + PartialOrd>(flag: &str, value: &str) -> Result<T, String> {
+    number(flag, value)
+        .ok()
+        .filter(|number| *number > T::default())
+        .ok_or_else(|| format!("{flag} must be a positive whole number, got '{value}'"))
+}
+
+fn png_depth(value: &str) -> Result<PngBitDepth, String> {
+    match value {
+        "8" => Ok(PngBitDepth::Eight),
+        "16" => Ok(PngBitDepth::Sixteen),
+        _ => Err(format!("unsupported png depth '{value}', expected 8 or 16")),
+    }
+}
+
+fn exr_compression(value: &str) -> Result<ExrCompression, String> {
+    match value {
+        "none" => Ok(ExrCompression::None),
+        "rle" => Ok(ExrCompression::Rle),
+        "zips" => Ok(ExrCompression::Zips),
+        "zip" => Ok(ExrCompression::Zip),
+        _ => Err(format!(
+            "unknown exr compression '{value}', expected none, rle, zips or zip"
+        )),
+    }
+}
+
+fn aspect_ratio(value: &str) -> Result<f32, String> {
+    let ratio = match value.split_once(':') {
+        Some((width, height)) => width
+            .parse::<f32>()
+            .ok()
+            .zip(height.parse::<f32>().ok())
+            .map(|(width, height)| width / height),
+        None => value.parse::<f32>().ok(),
+    };
+
+    ratio
+        .filter(|ratio| ratio.is_finite() && *ratio > 0.0)
+        .ok_or_else(|| format!("invalid aspect ratio '{value}', expected a number or W:H"))
+}
+
+fn pixel(value: &str) -> Result<(u32, u32), String> {
+    value
+        .split_once(',')
+        .and_then(|(x, y)| x.trim().parse().ok().zip(y.trim().parse().ok()))
+        .ok_or_else(|| format!("invalid pixel '{value}', expected X,Y"))
+}
+
+fn integrator(value: &str) -> Result<Integrator, String> {
+    match value {
+        "path" => Ok(Integrator::Path),
+        "nee" => Ok(Integrator::Nee),
+        _ => Err(format!(
+            "unknown integrator '{value}', expected path or nee"
+        )),
+    }
+}
+
+fn aovs(value: &str) -> Result<Vec<Aov>, String> {
+    if value == "all" {
+        return Ok(Aov::ALL.to_vec());
+    }
+    value
+        .split(',')
+        .map(|name| {
+            Aov::from_name(name.trim()).ok_or_else(|| {
+                let names = Aov::ALL.map(|aov| aov.name()).join(", ");
+                format!(
+                    "unknown pass '{}', expected all or one of {names}",
+                    name.trim()
+                )
+            })
+        })
+        .collect()
+}
+
+fn strategy(value: &str) -> Result<BuildStrategy, String> {
+    let (name, params) = match value.split_once(':') {
+        Some((name, params)) => (name, Some(params)),
+        None => (value, None),
+    };
+    match (name, params) {
+        ("median", None) => Ok(BuildStrategy::Median),
+        ("sah", params) => {
+            let mut config = SahConfig::default();
+            for param in params.into_iter().flat_map(|params| params.split(',')) {
+                let (key, value) = param.split_once('=').ok_or_else(|| {
+                    format!("invalid SAH parameter '{param}', expected KEY=VALUE")
+                })?;
+                let flag = format!("--bvh sah:{}", key.trim());
+                let value = value.trim();
+                match key.trim() {
+                    "bins" => config.bins = positive(&flag, value)?,
+                    "leaf" => config.max_leaf_size = positive(&flag, value)?,
+                    "traversal" => config.traversal_cost = cost(&flag, value)?,
+                    "intersection" => config.intersection_cost = cost(&flag, value)?,
+                    key => {
+                        return Err(format!(
+                            "unknown SAH parameter '{key}', expected bins, leaf, traversal or \
+                             intersection"
+                        ));
+                    }
+                }
+            }
+            if config.bins < 2 {
+                return Err("--bvh sah:bins needs at least 2 bins".to_string());
+            }
+            Ok(BuildStrategy::Sah(config))
+        }
+        _ => Err(format!(
+            "unknown BVH strategy '{value}', expected median or sah[:bins=N,leaf=N,traversal=C,\
+             intersection=C]"
+        )),
+    }
+}
+
+fn cost(flag: &str, value: &str) -> Result<f32, String> {
+    number::<f32>(flag, value)
+        .ok()
+        .filter(|cost| cost.is_finite() && *cost > 0.0)
+        .ok_or_else(|| format!("{flag} must be a positive number, got '{value}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Options;
+    use crate::{
+        aov::Aov,
+        bvh::BuildStrategy,
+        image::{ImageFormat, exr::ExrCompression, png::PngBitDepth},
+    };
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_flags_and_values() {
+        let options = parse(&[
+            "--spp=16", "-w", "320", "-d", "none", "quads", "--aov", "depth",
+        ])
+        .unwrap();
+        assert_eq!(options.scene.as_deref(), Some("quads"));
+        assert_eq!(options.samples_per_pixel, Some(16));
+        assert_eq!(options.image_width, Some(320));
+        assert_eq!(options.max_depth, Some(None));
+        assert_eq!(options.aovs, [Aov::Depth]);
+
+        assert_eq!(
+            parse(&["--frobnicate"]).unwrap_err(),
+            "unknown option '--frobnicate'"
+        );
+        assert_eq!(
+            parse(&["quads", "cornell-box"]).unwrap_err(),
+            "unexpected argument 'cornell-box'"
+        );
+        assert_eq!(parse(&["-s"]).unwrap_err(), "missing value for -s");
+        assert_eq!(
+            parse(&["--output"]).unwrap_err(),
+            "missing value for --output"
+        );
+        assert!(parse(&["--spp", "0"]).is_err());
+        assert!(parse(&["--aspect-ratio", "16:0"]).is_err());
+    }
+
+    #[test]
+    fn sah_parameters_override_the_defaults() {
+        let config = |value: &str| match parse(&["--bvh", value]).map(|options| options.bvh) {
+            Ok(Some(BuildStrategy::Sah(config))) => Ok(config),
+            Ok(strategy) => panic!("{strategy:?}"),
+            Err(message) => Err(message),
+        };
+
+        assert!(matches!(
+            parse(&["--bvh", "median"]).unwrap().bvh,
+            Some(BuildStrategy::Median)
+        ));
+        let default = config("sah").unwrap();
+        assert_eq!((default.bins, default.max_leaf_size), (16, 4));
+
+        let custom = config("sah:bins=8, leaf=2,traversal=0.5,intersection=2").unwrap();
+        assert_eq!((custom.bins, custom.max_leaf_size), (8, 2));
+        assert_eq!(
+            (custom.traversal_cost, custom.intersection_cost),
+            (0.5, 2.0)
+        );
+        // Unnamed parameters keep their defaults
+        let bins = config("sah:bins=32").unwrap();
+        assert_eq!((bins.bins, bins.max_leaf_size), (32, 4));
+
+        assert_eq!(
+            config("sah:bins=1").unwrap_err(),
+            "--bvh sah:bins needs at least 2 bins"
+        );
+        assert_eq!(
+            config("sah:leaf=0").unwrap_err(),
+            "--bvh sah:leaf must be a positive whole number, got '0'"
+        );
+        assert_eq!(
+            config("sah:traversal=-1").unwrap_err(),
+            "--bvh sah:traversal must be a positive number, got '-1'"
+        );
+        assert!(config("sah:depth=3").is_err());
+        assert!(config("sah:bins").is_err());
+        assert!(config("median:bins=8").is_err());
+    }
+
+    #[test]
+    fn output_format_follows_the_extension() {
+        let output = |args: &[&str]| parse(args).unwrap().output();
+
+        assert_eq!(
+            output(&[]).unwrap(),
+            ("render.png".to_string(), ImageFormat::Png)
+        );
+        for (path, format) in [
+            ("out.ppm", ImageFormat::Ppm),
+            ("out.png", ImageFormat::Png),
+            ("dir/out.hdr", ImageFormat::Hdr),
+            ("out.EXR", ImageFormat::Exr),
+        ] {
+            assert_eq!(output(&["-o", path]).unwrap(), (path.to_string(), format));
+        }
+
+        // An explicit format wins over the extension, or names the default output
+        assert_eq!(
+            output(&["-o", "out.png", "-f", "exr"]).unwrap(),
+            ("out.png".to_string(), ImageFormat::Exr)
+        );
+        assert_eq!(
+            output(&["--format", "hdr"]).unwrap(),
+            ("render.hdr".to_string(), ImageFormat::Hdr)
+        );
+        assert!(output(&["-o", "render"]).is_err());
+        assert!(parse(&["-f", "gif"]).is_err());
+
+        // Bit depth is a png setting
+        let options = parse(&["-o", "out.png", "--png-depth", "16"]).unwrap();
+        assert_eq!(options.png_depth, Some(PngBitDepth::Sixteen));
+        assert!(options.output().is_ok());
+        assert!(output(&["-o", "out.exr", "--png-depth", "16"]).is_err());
+        assert!(parse(&["--png-depth", "12"]).is_err());
+
+        // And compression an exr one
+        let options = parse(&["-o", "out.exr", "--exr-compression", "rle"]).unwrap();
+        assert_eq!(options.exr_compression, Some(ExrCompression::Rle));
+        assert!(options.output().is_ok());
+        assert!(output(&["--exr-compression", "zips"]).is_err());
+        assert!(parse(&["--exr-compression", "piz"]).is_err());
+
+        // Passes need an exr output to go in unless they get their own files
+        assert!(output(&["--aov", "normal"]).is_err());
+        assert!(output(&["--aov", "normal", "-o", "out.exr"]).is_ok());
+        assert!(output(&["--aov", "normal", "--aov-files"]).is_ok());
+        assert!(output(&["--aov", "alpha"]).is_ok());
+        assert!(output(&["--aov", "alpha", "-o", "out.hdr"]).is_err());
+        assert!(output(&["--aov", "alpha,depth", "-o", "out.png"]).is_err());
+    }
+}
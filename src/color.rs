@@ -29,6 +29,11 @@ impl Color {
         Color { r, g, b }
     }
 
+    // Perceived brightness of a linear color, Rec. 709 weights
+    pub(crate) fn luminance(&self) -> f32 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
     fn linear_to_gamma(linear_component: f32) -> f32 {
         if linear_component > 0.0 {
             linear_component.sqrt()
@@ -50,6 +55,13 @@ impl Color {
         let bbyte = (256.0 * INTENSITY.clamp(b_corrected)) as u8;
         [rbyte, gbyte, bbyte]
     }
+
+    // Gamma corrected like bytes, for 16 bit output
+    pub(crate) fn words(&self) -> [u16; 3] {
+        const INTENSITY: Interval = Interval::new(0.0, 1.0);
+        [self.r, self.g, self.b]
+            .map(|c| (65535.0 * INTENSITY.clamp(Self::linear_to_gamma(c))).round() as u16)
+    }
 }
 
 impl ops::Add<Color> for Color {
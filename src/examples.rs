@@ -4,19 +4,56 @@ use rand::prelude::*;
 use rand_chacha::ChaCha8Rng;
 
 use crate::{
-    bvh::BVHNode,
+    aabb::Aabb,
+    background::{Background, EnvironmentMap},
+    bvh::{BVHNode, BuildStrategy},
     camera::Camera,
     color::Color,
     image::Image,
+    instance::Geometry,
+    interval::Interval,
     material::Material,
+    medium::{Density, VoxelGrid},
+    mesh::Mesh,
     noise::Perlin,
+    obj,
     point::Point,
     primitive::{Primitive, QuadParams, SphereParams},
     texture::Texture,
+    transform::Transform,
     vec3::Vec3,
 };
 
-pub(crate) fn three_spheres() -> (Camera, Arc<BVHNode>, Arc<Vec<Primitive>>) {
+pub(crate) type Scene = (Camera, Arc<BVHNode>, Arc<Vec<Primitive>>);
+pub(crate) type SceneBuilder = fn(BuildStrategy) -> Scene;
+
+// Built-in scenes by command line name, in menu order
+pub(crate) const SCENES: [(&str, SceneBuilder); 15] = [
+    ("three-spheres", three_spheres),
+    ("many-spheres", many_spheres),
+    ("bouncing-spheres", bouncing_spheres),
+    ("checkers", checkers),
+    ("earth", earth),
+    ("perlin", perlin),
+    ("quads", quads),
+    ("meshes", meshes),
+    ("obj-model", obj_model),
+    ("cornell-box", cornell_box),
+    ("cornell-instances", cornell_instances),
+    ("cornell-smoke", cornell_smoke),
+    ("volumes", volumes),
+    ("forest", forest),
+    ("environment", environment),
+];
+
+pub(crate) fn by_name(name: &str) -> Option<SceneBuilder> {
+    SCENES
+        .iter()
+        .find(|(scene, _)| *scene == name)
+        .map(|(_, build)| *build)
+}
+
+pub(crate) fn three_spheres(strategy: BuildStrategy) -> Scene {
     let position = Point::new(-2.0, 2.0, 1.0);
     let look_at = Point::new(0.0, 0.0, -1.0);
     let view_up = Vec3::new(0.0, 1.0, 0.0);
@@ -26,7 +63,7 @@ pub(crate) fn three_spheres() -> (Camera, Arc<BVHNode>, Arc<Vec<Primitive>>) {
     let image_width = 400u32;
     let vertical_fov = 25.0;
     let samples_per_pixel = 100u32;
-    let max_depth = 50u32;
+    let max_depth = Some(50);
 
     let camera = Camera::new(
         position,
@@ -82,14 +119,21 @@ pub(crate) fn three_spheres() -> (Camera, Arc<BVHNode>, Arc<Vec<Primitive>>) {
         )))),
     )));
 
-    let world_count = world.len();
-
-    let bvh_root = BVHNode::new(&mut world, 0, world_count);
+    let bvh_root = BVHNode::build(&mut world, strategy);
 
     (camera, bvh_root, Arc::new(world))
 }
 
-pub(crate) fn many_spheres() -> (Camera, Arc<BVHNode>, Arc<Vec<Primitive>>) {
+pub(crate) fn many_spheres(strategy: BuildStrategy) -> Scene {
+    random_spheres(false, strategy)
+}
+
+// The book's motion blur scene, diffuse spheres bounce upwards while the shutter is open
+pub(crate) fn bouncing_spheres(strategy: BuildStrategy) -> Scene {
+    random_spheres(true, strategy)
+}
+
+fn random_spheres(bouncing: bool, strategy: BuildStrategy) -> Scene {
     let position = Point::new(13.0, 2.0, 3.0);
     let look_at = Point::new(0.0, 0.0, 0.0);
     let view_up = Vec3::new(0.0, 1.0, 0.0);
@@ -99,9 +143,9 @@ pub(crate) fn many_spheres() -> (Camera, Arc<BVHNode>, Arc<Vec<Primitive>>) {
     let image_width = 1200u32;
     let vertical_fov = 25.0;
     let samples_per_pixel = 500u32;
-    let max_depth = 50u32;
+    let max_depth = Some(50);
 
-    let camera = Camera::new(
+    let mut camera = Camera::new(
         position,
         look_at,
         view_up,
@@ -113,6 +157,9 @@ pub(crate) fn many_spheres() -> (Camera, Arc<BVHNode>, Arc<Vec<Primitive>>) {
         samples_per_pixel,
         max_depth,
     );
+    if bouncing {
+        camera.shutter = Interval::new(0.0, 1.0);
+    }
 
     let mut rng = ChaCha8Rng::seed_from_u64(1);
 
@@ -156,11 +203,13 @@ pub(crate) fn many_spheres() -> (Camera, Arc<BVHNode>, Arc<Vec<Primitive>>) {
                     Arc::new(Material::Dielectric(1.5))
                 };
 
-                world.push(Primitive::Sphere(SphereParams::new(
-                    center,
-                    0.2,
-                    sphere_material,
-                )));
+                let sphere = if bouncing && choose_material < 0.8 {
+                    let end = center + Vec3::new(0.0, rng.random_range(0.0..0.5), 0.0);
+                    SphereParams::moving(center, end, 0.2, sphere_material)
+                } else {
+                    SphereParams::new(center, 0.2, sphere_material)
+                };
+                world.push(Primitive::Sphere(sphere));
             }
         }
     }
@@ -188,14 +237,12 @@ pub(crate) fn many_spheres() -> (Camera, Arc<BVHNode>, Arc<Vec<Primitive>>) {
         material3,
     )));
 
-    let world_count = world.len();
-
-    let bvh_root = BVHNode::new(&mut world, 0, world_count);
+    let bvh_root = BVHNode::build(&mut world, strategy);
 
     (camera, bvh_root, Arc::new(world))
 }
 
-pub(crate) fn checkers() -> (Camera, Arc<BVHNode>, Arc<Vec<Primitive>>) {
+pub(crate) fn checkers(strategy: BuildStrategy) -> Scene {
     let position = Point::new(13.0, 2.0, 3.0);
     let look_at = Point::new(0.0, 0.0, 0.0);
     let view_up = Vec3::new(0.0, 1.0, 0.0);
@@ -205,7 +252,7 @@ pub(crate) fn checkers() -> (Camera, Arc<BVHNode>, Arc<Vec<Primitive>>) {
     let image_width = 400;
     let vertical_fov = 20.0;
     let samples_per_pixel = 100;
-    let max_depth = 50;
+    let max_depth = Some(50);
 
     let camera = Camera::new(
         position,
@@ -240,13 +287,12 @@ pub(crate) fn checkers() -> (Camera, Arc<BVHNode>, Arc<Vec<Primitive>>) {
 
     let mut world = vec![sphere1, sphere2];
 
-    let world_count = world.len();
-    let bvh_root = BVHNode::new(&mut world, 0, world_count);
+    let bvh_root = BVHNode::build(&mut world, strategy);
 
     (camera, bvh_root, Arc::new(world))
 }
 
-pub(crate) fn earth() -> (Camera, Arc<BVHNode>, Arc<Vec<Primitive>>) {
+pub(crate) fn earth(strategy: BuildStrategy) -> Scene {
     let position = Point::new(1.0, 1.0, 12.0);
     let look_at = Point::new(0.0, 0.0, 0.0);
     let view_up = Vec3::new(0.0, 1.0, 0.0);
@@ -256,7 +302,7 @@ pub(crate) fn earth() -> (Camera, Arc<BVHNode>, Arc<Vec<Primitive>>) {
     let image_width = 400;
     let vertical_fov = 20.0;
     let samples_per_pixel = 100;
-    let max_depth = 50;
+    let max_depth = Some(50);
 
     let camera = Camera::new(
         position,
@@ -283,13 +329,12 @@ pub(crate) fn earth() -> (Camera, Arc<BVHNode>, Arc<Vec<Primitive>>) {
 
     let mut world = vec![globe];
 
-    let world_count = world.len();
-    let bvh_root = BVHNode::new(&mut world, 0, world_count);
+    let bvh_root = BVHNode::build(&mut world, strategy);
 
     (camera, bvh_root, Arc::new(world))
 }
 
-pub(crate) fn perlin() -> (Camera, Arc<BVHNode>, Arc<Vec<Primitive>>) {
+pub(crate) fn perlin(strategy: BuildStrategy) -> Scene {
     let position = Point::new(13.0, 2.0, 3.0);
     let look_at = Point::new(0.0, 0.0, 0.0);
     let view_up = Vec3::new(0.0, 1.0, 0.0);
@@ -299,7 +344,7 @@ pub(crate) fn perlin() -> (Camera, Arc<BVHNode>, Arc<Vec<Primitive>>) {
     let image_width = 400;
     let vertical_fov = 20.0;
     let samples_per_pixel = 100;
-    let max_depth = 50;
+    let max_depth = Some(50);
 
     let camera = Camera::new(
         position,
@@ -330,13 +375,12 @@ pub(crate) fn perlin() -> (Camera, Arc<BVHNode>, Arc<Vec<Primitive>>) {
 
     let mut world = vec![sphere, ground];
 
-    let world_count = world.len();
-    let bvh_root = BVHNode::new(&mut world, 0, world_count);
+    let bvh_root = BVHNode::build(&mut world, strategy);
 
     (camera, bvh_root, Arc::new(world))
 }
 
-pub(crate) fn quads() -> (Camera, Arc<BVHNode>, Arc<Vec<Primitive>>) {
+pub(crate) fn quads(strategy: BuildStrategy) -> Scene {
     let position = Point::new(0.0, 0.0, 9.0);
     let look_at = Point::new(0.0, 0.0, 0.0);
     let view_up = Vec3::new(0.0, 1.0, 0.0);
@@ -346,7 +390,7 @@ pub(crate) fn quads() -> (Camera, Arc<BVHNode>, Arc<Vec<Primitive>>) {
     let image_width = 400;
     let vertical_fov = 80.0;
     let samples_per_pixel = 100;
-    let max_depth = 50;
+    let max_depth = Some(50);
 
     let camera = Camera::new(
         position,
@@ -410,8 +454,557 @@ pub(crate) fn quads() -> (Camera, Arc<BVHNode>, Arc<Vec<Primitive>>) {
         )),
     ];
 
-    let world_count = world.len();
-    let bvh_root = BVHNode::new(&mut world, 0, world_count);
+    let bvh_root = BVHNode::build(&mut world, strategy);
+
+    (camera, bvh_root, Arc::new(world))
+}
+
+pub(crate) fn meshes(strategy: BuildStrategy) -> Scene {
+    let position = Point::new(0.0, 2.0, 9.0);
+    let look_at = Point::new(0.0, 0.5, 0.0);
+    let view_up = Vec3::new(0.0, 1.0, 0.0);
+    let focal_length = 1.0;
+    let defocus_angle = 0.0;
+    let aspect_ratio = 16.0 / 9.0;
+    let image_width = 400;
+    let vertical_fov = 30.0;
+    let samples_per_pixel = 100;
+    let max_depth = Some(50);
+
+    let camera = Camera::new(
+        position,
+        look_at,
+        view_up,
+        focal_length,
+        defocus_angle,
+        aspect_ratio,
+        image_width,
+        vertical_fov,
+        samples_per_pixel,
+        max_depth,
+    );
+
+    let ground_material = Arc::new(Material::Lambertian(Arc::new(Texture::Checker(
+        1.0,
+        Color::new(0.2, 0.3, 0.1),
+        Color::new(0.9, 0.9, 0.9),
+    ))));
+    let pyramid_material = Arc::new(Material::Lambertian(Arc::new(Texture::from_color(
+        Color::new(0.8, 0.3, 0.2),
+    ))));
+    let sphere_material = Arc::new(Material::Metal(Color::new(0.7, 0.7, 0.8), 0.05));
+
+    // Ground plane made of two triangles, slightly below y = 0 to keep the checker pattern stable
+    let ground = Arc::new(Mesh::new(
+        vec![
+            Point::new(-20.0, -0.001, -20.0),
+            Point::new(-20.0, -0.001, 20.0),
+            Point::new(20.0, -0.001, 20.0),
+            Point::new(20.0, -0.001, -20.0),
+        ],
+        vec![[0, 1, 2], [0, 2, 3]],
+        None,
+        None,
+    ));
+
+    // Flat shaded square pyramid
+    let pyramid = Arc::new(Mesh::new(
+        vec![
+            Point::new(-3.0, 0.0, -1.0),
+            Point::new(-1.0, 0.0, -1.0),
+            Point::new(-1.0, 0.0, 1.0),
+            Point::new(-3.0, 0.0, 1.0),
+            Point::new(-2.0, 2.0, 0.0),
+        ],
+        vec![[0, 4, 1], [1, 4, 2], [2, 4, 3], [3, 4, 0]],
+        None,
+        None,
+    ));
+
+    let mut world = Mesh::primitives(&ground, ground_material);
+    world.append(&mut Mesh::primitives(&pyramid, pyramid_material));
+    world.append(&mut Mesh::primitives(
+        &Arc::new(uv_sphere(Point::new(1.5, 1.0, 0.0), 1.0, 32, 16)),
+        sphere_material,
+    ));
+
+    let bvh_root = BVHNode::build(&mut world, strategy);
+
+    (camera, bvh_root, Arc::new(world))
+}
+
+pub(crate) fn obj_model(strategy: BuildStrategy) -> Scene {
+    let position = Point::new(-1.0, 3.0, 8.0);
+    let look_at = Point::new(0.0, 0.5, 0.0);
+    let view_up = Vec3::new(0.0, 1.0, 0.0);
+    let focal_length = 1.0;
+    let defocus_angle = 0.0;
+    let aspect_ratio = 16.0 / 9.0;
+    let image_width = 400;
+    let vertical_fov = 30.0;
+    let samples_per_pixel = 100;
+    let max_depth = Some(50);
+
+    let camera = Camera::new(
+        position,
+        look_at,
+        view_up,
+        focal_length,
+        defocus_angle,
+        aspect_ratio,
+        image_width,
+        vertical_fov,
+        samples_per_pixel,
+        max_depth,
+    );
+
+    let mut world = obj::load("./resources/shapes.obj").unwrap();
+
+    let bvh_root = BVHNode::build(&mut world, strategy);
+
+    (camera, bvh_root, Arc::new(world))
+}
+
+pub(crate) fn cornell_box(strategy: BuildStrategy) -> Scene {
+    let (camera, mut world, white) = cornell_room();
+
+    world.append(&mut Primitive::new_box(
+        Point::new(130.0, 0.0, 65.0),
+        Point::new(295.0, 165.0, 230.0),
+        white.clone(),
+    ));
+    world.append(&mut Primitive::new_box(
+        Point::new(265.0, 0.0, 295.0),
+        Point::new(430.0, 330.0, 460.0),
+        white,
+    ));
+
+    let bvh_root = BVHNode::build(&mut world, strategy);
+
+    (camera, bvh_root, Arc::new(world))
+}
+
+// The book's final Cornell box, both boxes are instances of one shared unit cube
+pub(crate) fn cornell_instances(strategy: BuildStrategy) -> Scene {
+    let (camera, mut world, white) = cornell_room();
+
+    let cube = Geometry::new(
+        Primitive::new_box(Point::new(0.0, 0.0, 0.0), Point::new(1.0, 1.0, 1.0), white),
+        strategy,
+    );
+    let tall_box = Transform::scale(Vec3::new(165.0, 330.0, 165.0))
+        .then(&Transform::rotate(Vec3::new(0.0, 1.0, 0.0), 15.0))
+        .then(&Transform::translate(Vec3::new(265.0, 0.0, 295.0)));
+    let short_box = Transform::scale(Vec3::new(165.0, 165.0, 165.0))
+        .then(&Transform::rotate(Vec3::new(0.0, 1.0, 0.0), -18.0))
+        .then(&Transform::translate(Vec3::new(130.0, 0.0, 65.0)));
+    world.push(Primitive::new_instance(cube.clone(), tall_box));
+    world.push(Primitive::new_instance(cube, short_box));
+
+    // Non-uniformly scaled unit sphere resting on the short box
+    let sphere = Geometry::new(
+        vec![Primitive::Sphere(SphereParams::new(
+            Point::new(0.0, 0.0, 0.0),
+            1.0,
+            Arc::new(Material::Dielectric(1.5)),
+        ))],
+        strategy,
+    );
+    let ellipsoid = Transform::scale(Vec3::new(50.0, 25.0, 50.0))
+        .then(&Transform::translate(Vec3::new(183.0, 190.0, 169.0)));
+    world.push(Primitive::new_instance(sphere, ellipsoid));
+
+    let bvh_root = BVHNode::build(&mut world, strategy);
+
+    (camera, bvh_root, Arc::new(world))
+}
+
+// The two boxes of the Cornell box filled with black and white smoke
+pub(crate) fn cornell_smoke(strategy: BuildStrategy) -> Scene {
+    let (camera, mut world, white) = cornell_room();
+
+    // The boundaries only shape the smoke, their material is never seen
+    let cube = Geometry::new(
+        Primitive::new_box(Point::new(0.0, 0.0, 0.0), Point::new(1.0, 1.0, 1.0), white),
+        strategy,
+    );
+    let tall_box = Transform::scale(Vec3::new(165.0, 330.0, 165.0))
+        .then(&Transform::rotate(Vec3::new(0.0, 1.0, 0.0), 15.0))
+        .then(&Transform::translate(Vec3::new(265.0, 0.0, 295.0)));
+    let short_box = Transform::scale(Vec3::new(165.0, 165.0, 165.0))
+        .then(&Transform::rotate(Vec3::new(0.0, 1.0, 0.0), -18.0))
+        .then(&Transform::translate(Vec3::new(130.0, 0.0, 65.0)));
+
+    let black_smoke = Arc::new(Material::Isotropic(Arc::new(Texture::from_color(
+        Color::new(0.0, 0.0, 0.0),
+    ))));
+    let white_smoke = Arc::new(Material::Isotropic(Arc::new(Texture::from_color(
+        Color::new(1.0, 1.0, 1.0),
+    ))));
+
+    world.push(Primitive::new_medium(
+        Geometry::new(
+            vec![Primitive::new_instance(cube.clone(), tall_box)],
+            strategy,
+        ),
+        Density::Constant(0.01),
+        black_smoke,
+    ));
+    world.push(Primitive::new_medium(
+        Geometry::new(vec![Primitive::new_instance(cube, short_box)], strategy),
+        Density::Constant(0.01),
+        white_smoke,
+    ));
+
+    let bvh_root = BVHNode::build(&mut world, strategy);
+
+    (camera, bvh_root, Arc::new(world))
+}
+
+// A cloud from a voxel grid next to a ball of turbulent smoke with a varying albedo
+pub(crate) fn volumes(strategy: BuildStrategy) -> Scene {
+    let position = Point::new(0.0, 3.0, 14.0);
+    let look_at = Point::new(0.0, 2.0, 0.0);
+    let view_up = Vec3::new(0.0, 1.0, 0.0);
+    let focal_length = 1.0;
+    let defocus_angle = 0.0;
+    let aspect_ratio = 16.0 / 9.0;
+    let image_width = 400;
+    let vertical_fov = 30.0;
+    let samples_per_pixel = 100;
+    let max_depth = Some(50);
+
+    let camera = Camera::new(
+        position,
+        look_at,
+        view_up,
+        focal_length,
+        defocus_angle,
+        aspect_ratio,
+        image_width,
+        vertical_fov,
+        samples_per_pixel,
+        max_depth,
+    );
+
+    let ground = Arc::new(Material::Lambertian(Arc::new(Texture::from_color(
+        Color::new(0.5, 0.5, 0.5),
+    ))));
+    let mut world = vec![Primitive::Sphere(SphereParams::new(
+        Point::new(0.0, -1000.0, 0.0),
+        1000.0,
+        ground,
+    ))];
+
+    // Overlapping soft blobs eaten away by noise, fading out towards the edges of the grid
+    let bounds = Aabb::new_between(Point::new(-6.0, 1.0, -2.0), Point::new(0.0, 4.0, 2.0));
+    let resolution = [48, 24, 32];
+    let blobs = [
+        (Point::new(-4.2, 2.2, 0.0), 1.2),
+        (Point::new(-2.8, 2.7, 0.3), 1.3),
+        (Point::new(-1.5, 2.1, -0.2), 1.0),
+    ];
+    let perlin = Perlin::new(1.5, 3);
+    let mut values = Vec::with_capacity(resolution.iter().product());
+    for z in 0..resolution[2] {
+        for y in 0..resolution[1] {
+            for x in 0..resolution[0] {
+                let p = Point::new(
+                    bounds.x.min + (x as f32 + 0.5) / resolution[0] as f32 * bounds.x.size(),
+                    bounds.y.min + (y as f32 + 0.5) / resolution[1] as f32 * bounds.y.size(),
+                    bounds.z.min + (z as f32 + 0.5) / resolution[2] as f32 * bounds.z.size(),
+                );
+                let shape = blobs
+                    .iter()
+                    .map(|(center, radius)| 1.0 - (p - *center).length() / radius)
+                    .fold(0.0, f32::max);
+                values.push((shape - 0.3 * perlin.density(&p)).max(0.0));
+            }
+        }
+    }
+
+    let cloud = Arc::new(Material::Isotropic(Arc::new(Texture::from_color(
+        Color::new(0.95, 0.95, 0.95),
+    ))));
+    world.push(Primitive::new_medium(
+        Geometry::new(
+            Primitive::new_box(
+                Point::new(bounds.x.min, bounds.y.min, bounds.z.min),
+                Point::new(bounds.x.max, bounds.y.max, bounds.z.max),
+                cloud.clone(),
+            ),
+            strategy,
+        ),
+        Density::Grid(VoxelGrid::new(bounds, resolution, values), 4.0),
+        cloud,
+    ));
+
+    // Albedo follows the noise texture wherever the smoke scatters
+    let smoke = Arc::new(Material::Isotropic(Arc::new(Texture::Noise(Perlin::new(
+        2.0, 1,
+    )))));
+    world.push(Primitive::new_medium(
+        Geometry::new(
+            vec![Primitive::Sphere(SphereParams::new(
+                Point::new(3.0, 2.0, 0.0),
+                2.0,
+                smoke.clone(),
+            ))],
+            strategy,
+        ),
+        Density::Turbulence(Perlin::new(1.0, 2), 2.0),
+        smoke,
+    ));
+
+    let bvh_root = BVHNode::build(&mut world, strategy);
+
+    (camera, bvh_root, Arc::new(world))
+}
+
+// Ten thousand instances of one tree, the tree's triangles exist once in a shared bottom-level BVH
+// and only the instances go into the top-level BVH
+pub(crate) fn forest(strategy: BuildStrategy) -> Scene {
+    let position = Point::new(0.0, 8.0, 120.0);
+    let look_at = Point::new(0.0, 0.0, 60.0);
+    let view_up = Vec3::new(0.0, 1.0, 0.0);
+    let focal_length = 1.0;
+    let defocus_angle = 0.0;
+    let aspect_ratio = 16.0 / 9.0;
+    let image_width = 400;
+    let vertical_fov = 30.0;
+    let samples_per_pixel = 100;
+    let max_depth = Some(50);
+
+    let camera = Camera::new(
+        position,
+        look_at,
+        view_up,
+        focal_length,
+        defocus_angle,
+        aspect_ratio,
+        image_width,
+        vertical_fov,
+        samples_per_pixel,
+        max_depth,
+    );
+
+    let ground_material = Arc::new(Material::Lambertian(Arc::new(Texture::from_color(
+        Color::new(0.4, 0.5, 0.2),
+    ))));
+    let trunk_material = Arc::new(Material::Lambertian(Arc::new(Texture::from_color(
+        Color::new(0.4, 0.25, 0.1),
+    ))));
+    let leaf_material = Arc::new(Material::Lambertian(Arc::new(Texture::from_color(
+        Color::new(0.1, 0.4, 0.1),
+    ))));
+
+    let mut tree = Primitive::new_box(
+        Point::new(-0.1, 0.0, -0.1),
+        Point::new(0.1, 1.0, 0.1),
+        trunk_material,
+    );
+    tree.append(&mut Mesh::primitives(
+        &Arc::new(uv_sphere(Point::new(0.0, 1.5, 0.0), 0.7, 24, 12)),
+        leaf_material,
+    ));
+    let tree = Geometry::new(tree, strategy);
+
+    let mut world = vec![Primitive::Sphere(SphereParams::new(
+        Point::new(0.0, -1000.0, 0.0),
+        1000.0,
+        ground_material,
+    ))];
+
+    // A jittered 100 by 100 grid of trees two units apart with their own turn and size
+    let mut rng = ChaCha8Rng::seed_from_u64(1);
+    for row in 0..100 {
+        for column in 0..100 {
+            let width = rng.random_range(0.7..1.3);
+            let transform =
+                Transform::scale(Vec3::new(width, width * rng.random_range(0.8..1.6), width))
+                    .then(&Transform::rotate(
+                        Vec3::new(0.0, 1.0, 0.0),
+                        rng.random_range(0.0..360.0),
+                    ))
+                    .then(&Transform::translate(Vec3::new(
+                        (column as f32 - 49.5 + rng.random_range(-0.3..0.3)) * 2.0,
+                        0.0,
+                        (row as f32 - 49.5 + rng.random_range(-0.3..0.3)) * 2.0,
+                    )));
+            world.push(Primitive::new_instance(tree.clone(), transform));
+        }
+    }
+
+    let bvh_root = BVHNode::build(&mut world, strategy);
+
+    (camera, bvh_root, Arc::new(world))
+}
+
+// Camera, light and walls of the Cornell box, with the white material for the contents
+fn cornell_room() -> (Camera, Vec<Primitive>, Arc<Material>) {
+    let position = Point::new(278.0, 278.0, -800.0);
+    let look_at = Point::new(278.0, 278.0, 0.0);
+    let view_up = Vec3::new(0.0, 1.0, 0.0);
+    let focal_length = 1.0;
+    let defocus_angle = 0.0;
+    let aspect_ratio = 1.0;
+    let image_width = 600;
+    let vertical_fov = 40.0;
+    let samples_per_pixel = 200;
+    let max_depth = Some(50);
+
+    let mut camera = Camera::new(
+        position,
+        look_at,
+        view_up,
+        focal_length,
+        defocus_angle,
+        aspect_ratio,
+        image_width,
+        vertical_fov,
+        samples_per_pixel,
+        max_depth,
+    );
+
+    // Closed room, all light comes from the ceiling lamp
+    camera.background = Background::Solid(Color::new(0.0, 0.0, 0.0));
+
+    let red = Arc::new(Material::Lambertian(Arc::new(Texture::from_color(
+        Color::new(0.65, 0.05, 0.05),
+    ))));
+    let white = Arc::new(Material::Lambertian(Arc::new(Texture::from_color(
+        Color::new(0.73, 0.73, 0.73),
+    ))));
+    let green = Arc::new(Material::Lambertian(Arc::new(Texture::from_color(
+        Color::new(0.12, 0.45, 0.15),
+    ))));
+    let light = Arc::new(Material::DiffuseLight(Arc::new(Texture::from_color(
+        Color::new(15.0, 15.0, 15.0),
+    ))));
+
+    let world = vec![
+        Primitive::Quad(QuadParams::new(
+            Point::new(555.0, 0.0, 0.0),
+            Vec3::new(0.0, 555.0, 0.0),
+            Vec3::new(0.0, 0.0, 555.0),
+            green,
+        )),
+        Primitive::Quad(QuadParams::new(
+            Point::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 555.0, 0.0),
+            Vec3::new(0.0, 0.0, 555.0),
+            red,
+        )),
+        Primitive::Quad(QuadParams::new(
+            Point::new(343.0, 554.0, 332.0),
+            Vec3::new(-130.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -105.0),
+            light,
+        )),
+        Primitive::Quad(QuadParams::new(
+            Point::new(0.0, 0.0, 0.0),
+            Vec3::new(555.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 555.0),
+            white.clone(),
+        )),
+        Primitive::Quad(QuadParams::new(
+            Point::new(555.0, 555.0, 555.0),
+            Vec3::new(-555.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -555.0),
+            white.clone(),
+        )),
+        Primitive::Quad(QuadParams::new(
+            Point::new(0.0, 0.0, 555.0),
+            Vec3::new(555.0, 0.0, 0.0),
+            Vec3::new(0.0, 555.0, 0.0),
+            white.clone(),
+        )),
+    ];
+
+    (camera, world, white)
+}
+
+pub(crate) fn environment(strategy: BuildStrategy) -> Scene {
+    let position = Point::new(0.0, 1.0, 6.0);
+    let look_at = Point::new(0.0, 0.5, 0.0);
+    let view_up = Vec3::new(0.0, 1.0, 0.0);
+    let focal_length = 1.0;
+    let defocus_angle = 0.0;
+    let aspect_ratio = 16.0 / 9.0;
+    let image_width = 400;
+    let vertical_fov = 40.0;
+    let samples_per_pixel = 100;
+    let max_depth = Some(50);
+
+    let mut camera = Camera::new(
+        position,
+        look_at,
+        view_up,
+        focal_length,
+        defocus_angle,
+        aspect_ratio,
+        image_width,
+        vertical_fov,
+        samples_per_pixel,
+        max_depth,
+    );
+
+    // Any equirectangular image works, the earth map makes the mapping easy to check
+    camera.background = Background::Environment(EnvironmentMap::new(
+        Image::load("./resources/nasa_bmng.ppm").unwrap(),
+    ));
+
+    let mut world = vec![
+        Primitive::Sphere(SphereParams::new(
+            Point::new(-1.1, 0.5, 0.0),
+            1.0,
+            Arc::new(Material::Metal(Color::new(0.9, 0.9, 0.9), 0.0)),
+        )),
+        Primitive::Sphere(SphereParams::new(
+            Point::new(1.1, 0.5, 0.0),
+            1.0,
+            Arc::new(Material::Dielectric(1.5)),
+        )),
+    ];
+
+    let bvh_root = BVHNode::build(&mut world, strategy);
 
     (camera, bvh_root, Arc::new(world))
 }
+
+// Latitude/longitude tessellated sphere with smooth normals and uvs
+fn uv_sphere(center: Point, radius: f32, segments: u32, rings: u32) -> Mesh {
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+
+    for ring in 0..=rings {
+        let v = ring as f32 / rings as f32;
+        let theta = v * std::f32::consts::PI;
+        for segment in 0..=segments {
+            let u = segment as f32 / segments as f32;
+            let phi = u * 2.0 * std::f32::consts::PI;
+            let normal = Vec3::new(
+                -theta.sin() * phi.cos(),
+                -theta.cos(),
+                theta.sin() * phi.sin(),
+            );
+            positions.push(center + radius * normal);
+            normals.push(normal);
+            uvs.push((u, v));
+        }
+    }
+
+    let stride = segments + 1;
+    let mut indices = Vec::new();
+    for ring in 0..rings {
+        for segment in 0..segments {
+            let a = ring * stride + segment;
+            let b = a + stride;
+            indices.push([a, a + 1, b]);
+            indices.push([a + 1, b + 1, b]);
+        }
+    }
+
+    Mesh::new(positions, indices, Some(normals), Some(uvs))
+}
@@ -4,7 +4,6 @@ use crate::{material::Material, point::Point, ray::Ray, vec3::Vec3};
 
 #[derive(Clone)]
 pub(crate) struct HitRecord {
-    #[allow(dead_code)]
     pub(crate) position: Point,
     pub(crate) normal: Vec3,
     pub(crate) material: Arc<Material>,
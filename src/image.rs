@@ -1,10 +1,56 @@
 use std::{
-    io::{self, BufWriter, ErrorKind, Write},
-    str::{FromStr, from_utf8},
+    io::{self, BufWriter, Write},
+    path::Path,
 };
 
 use crate::color::Color;
 
+pub(crate) mod exr;
+mod hdr;
+mod jpeg;
+mod netpbm;
+pub(crate) mod png;
+mod zlib;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ImageFormat {
+    Ppm,
+    Png,
+    Hdr,
+    Exr,
+}
+
+impl ImageFormat {
+    pub(crate) const ALL: [ImageFormat; 4] = [
+        ImageFormat::Ppm,
+        ImageFormat::Png,
+        ImageFormat::Hdr,
+        ImageFormat::Exr,
+    ];
+
+    pub(crate) fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Ppm => "ppm",
+            ImageFormat::Png => "png",
+            ImageFormat::Hdr => "hdr",
+            ImageFormat::Exr => "exr",
+        }
+    }
+
+    // Case insensitive extension without the dot
+    pub(crate) fn from_extension(extension: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|format| format.extension().eq_ignore_ascii_case(extension))
+    }
+
+    pub(crate) fn from_path(path: &str) -> Option<Self> {
+        Path::new(path)
+            .extension()
+            .and_then(|ext| Self::from_extension(&ext.to_string_lossy()))
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct Image {
     pub(crate) width: usize,
@@ -14,111 +60,65 @@ pub(crate) struct Image {
 
 impl Image {
     pub(crate) fn load(name: &str) -> Result<Self, io::Error> {
-        let error = Err(io::Error::from(ErrorKind::InvalidInput));
-
         let contents = std::fs::read(name)?;
 
-        let mut header = contents.as_slice().splitn(4, |&b| b == b'\n');
-
-        // assuming color depth is 256 for now
-        if let (Some(file_type), Some(resolution), Some(_color_depth), Some(data)) =
-            (header.next(), header.next(), header.next(), header.next())
-        {
-            let width;
-            let height;
-
-            let mut res_it = resolution.split(|&b| b == b' ');
-            if let (Some(width_bytes), Some(height_bytes)) = (res_it.next(), res_it.next()) {
-                if let (Ok(width_parse), Ok(height_parse)) =
-                    (str::from_utf8(width_bytes), str::from_utf8(height_bytes))
-                {
-                    if let (Ok(width_parse), Ok(height_parse)) =
-                        (usize::from_str(width_parse), usize::from_str(height_parse))
-                    {
-                        width = width_parse;
-                        height = height_parse;
-                    } else {
-                        return error;
-                    }
-                } else {
-                    return error;
-                };
-            } else {
-                return error;
-            }
-
-            // I'm lazy, going to assume the color depth is 255 per color every time
-
-            match file_type {
-                b"P6" => Self::read_p6(width, height, data),
-                b"P3" => Self::read_p3(width, height, data),
-                _ => error,
-            }
-        } else {
-            error
+        // Formats with a signature are detected from it, anything else is treated as Netpbm
+        if contents.starts_with(&png::MAGIC) {
+            return png::decode(&contents);
         }
+        if contents.starts_with(&jpeg::MAGIC) {
+            return jpeg::decode(&contents);
+        }
+        if hdr::MAGIC.iter().any(|magic| contents.starts_with(magic)) {
+            return hdr::decode(&contents);
+        }
+
+        Ok(netpbm::decode(&contents)?)
     }
 
-    fn read_p3(width: usize, height: usize, bytes: &[u8]) -> Result<Self, io::Error> {
-        let error = Err(io::Error::from(ErrorKind::InvalidInput));
-
-        let mut px = Vec::with_capacity(width * height);
-
-        if let Ok(data) = from_utf8(bytes) {
-            let mut data = data.lines();
-            for _ in 0..(width * height) {
-                if let (Some(r), Some(g), Some(b)) = (data.next(), data.next(), data.next()) {
-                    if let (Ok(r), Ok(g), Ok(b)) =
-                        (r.parse::<f32>(), g.parse::<f32>(), b.parse::<f32>())
-                    {
-                        px.push(Color::new(
-                            ((r) / 256.0).min(1.0),
-                            ((g) / 256.0).min(1.0),
-                            ((b) / 256.0).min(1.0),
-                        ));
-                    }
-                } else {
-                    return Err(io::Error::from(ErrorKind::InvalidInput));
-                }
+    pub(crate) fn write_as(
+        path: &str,
+        format: ImageFormat,
+        width: u32,
+        height: u32,
+        pixels: &[Color],
+    ) -> io::Result<()> {
+        match format {
+            ImageFormat::Ppm => Self::write_p6(path, width, height, pixels),
+            ImageFormat::Png => {
+                Self::write_png(path, width, height, pixels, None, png::PngBitDepth::Eight)
             }
-
-            Ok(Image {
+            ImageFormat::Hdr => Self::write_hdr(path, width, height, pixels),
+            ImageFormat::Exr => Self::write_exr(
+                path,
                 width,
                 height,
-                pixels: px,
-            })
-        } else {
-            error
+                pixels,
+                Vec::new(),
+                exr::ExrPixelType::Half,
+                exr::ExrCompression::Zip,
+            ),
         }
     }
 
-    fn read_p6(width: usize, height: usize, bytes: &[u8]) -> Result<Self, io::Error> {
-        let error = Err(io::Error::from(ErrorKind::InvalidInput));
-
-        let mut px = Vec::with_capacity(width * height);
-
-        let mut bytes = bytes.iter();
-        for _ in 0..(width * height) {
-            if let (Some(r), Some(g), Some(b)) = (bytes.next(), bytes.next(), bytes.next()) {
-                px.push(Color::new(
-                    ((*r as f32) / 256.0).min(1.0),
-                    ((*g as f32) / 256.0).min(1.0),
-                    ((*b as f32) / 256.0).min(1.0),
-                ));
-            } else {
-                return error;
-            }
-        }
-
-        Ok(Image {
-            width,
-            height,
-            pixels: px,
-        })
+    pub(crate) fn write_png(
+        path: &str,
+        width: u32,
+        height: u32,
+        pixels: &[Color],
+        alpha: Option<&[f32]>,
+        bit_depth: png::PngBitDepth,
+    ) -> io::Result<()> {
+        png::write(path, width, height, pixels, alpha, bit_depth)
     }
 
-    pub(crate) fn write_p6(width: u32, height: u32, pixels: &[Color]) -> std::io::Result<()> {
-        let mut out = BufWriter::new(std::fs::File::create("render.ppm")?);
+    pub(crate) fn write_p6(
+        path: &str,
+        width: u32,
+        height: u32,
+        pixels: &[Color],
+    ) -> std::io::Result<()> {
+        let mut out = BufWriter::new(std::fs::File::create(path)?);
         writeln!(out, "P6")?;
         writeln!(out, "{} {}", width, height)?;
         writeln!(out, "255")?;
@@ -134,8 +134,13 @@ impl Image {
     }
 
     #[allow(dead_code)]
-    pub(crate) fn write_p3(width: u32, height: u32, pixels: &[Color]) -> std::io::Result<()> {
-        let mut out = BufWriter::new(std::fs::File::create("render.ppm")?);
+    pub(crate) fn write_p3(
+        path: &str,
+        width: u32,
+        height: u32,
+        pixels: &[Color],
+    ) -> std::io::Result<()> {
+        let mut out = BufWriter::new(std::fs::File::create(path)?);
         writeln!(out, "P3")?;
         writeln!(out, "{} {}", width, height)?;
         writeln!(out, "255")?;
@@ -152,8 +157,44 @@ impl Image {
         Ok(())
     }
 
+    // Linear radiance without clamping or gamma correction, for later tone mapping
+    pub(crate) fn write_hdr(
+        path: &str,
+        width: u32,
+        height: u32,
+        pixels: &[Color],
+    ) -> std::io::Result<()> {
+        hdr::write(path, width, height, pixels)
+    }
+
+    // Linear RGB plus any extra named channels (depth, normals, albedo) in the same file
+    pub(crate) fn write_exr(
+        path: &str,
+        width: u32,
+        height: u32,
+        pixels: &[Color],
+        extra_channels: Vec<exr::ExrChannel>,
+        pixel_type: exr::ExrPixelType,
+        compression: exr::ExrCompression,
+    ) -> std::io::Result<()> {
+        let mut channels = Vec::from(exr::ExrChannel::from_colors("", pixels));
+        channels.extend(extra_channels);
+        exr::write(path, width, height, &channels, pixel_type, compression)
+    }
+
+    // Nearest pixel lookup with (0, 0) at the bottom left, magenta outside the image
+    pub(crate) fn sample_uv(&self, u: f32, v: f32) -> Color {
+        // flip v to image coordinates
+        let u = u.clamp(0.0, 1.0);
+        let v = 1.0 - v.clamp(0.0, 1.0);
+
+        let i = ((u * self.width as f32) as usize).min(self.width.saturating_sub(1));
+        let j = ((v * self.height as f32) as usize).min(self.height.saturating_sub(1));
+        self.sample(i, j).unwrap_or(Color::new(1.0, 0.0, 1.0))
+    }
+
     pub(crate) fn sample(&self, w: usize, h: usize) -> Option<Color> {
-        if h * self.width + w <= self.pixels.len() - 1 {
+        if h * self.width + w < self.pixels.len() {
             Some(self.pixels[h * self.width + w])
         } else {
             None
@@ -0,0 +1,610 @@
+use std::io::{self, BufWriter, Write};
+
+use crate::color::Color;
+
+use super::zlib;
+
+// Single part scanline OpenEXR writer
+// https://openexr.com/en/latest/OpenEXRFileLayout.html
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum ExrPixelType {
+    Half,
+    Float,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ExrCompression {
+    None,
+    Rle,
+    // zlib, one scanline per chunk
+    Zips,
+    // zlib, 16 scanlines per chunk
+    Zip,
+}
+
+#[derive(Debug)]
+pub(crate) struct ExrChannel {
+    pub(crate) name: String,
+    pub(crate) data: Vec<f32>,
+}
+
+impl ExrChannel {
+    pub(crate) fn new(name: &str, data: Vec<f32>) -> Self {
+        ExrChannel {
+            name: name.to_string(),
+            data,
+        }
+    }
+
+    // Splits colors into "<prefix>R", "<prefix>G" and "<prefix>B" channels
+    pub(crate) fn from_colors(prefix: &str, pixels: &[Color]) -> [Self; 3] {
+        [
+            ExrChannel::new(&format!("{prefix}R"), pixels.iter().map(|c| c.r).collect()),
+            ExrChannel::new(&format!("{prefix}G"), pixels.iter().map(|c| c.g).collect()),
+            ExrChannel::new(&format!("{prefix}B"), pixels.iter().map(|c| c.b).collect()),
+        ]
+    }
+}
+
+impl ExrPixelType {
+    fn id(&self) -> i32 {
+        match self {
+            ExrPixelType::Half => 1,
+            ExrPixelType::Float => 2,
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            ExrPixelType::Half => 2,
+            ExrPixelType::Float => 4,
+        }
+    }
+}
+
+impl ExrCompression {
+    fn id(&self) -> u8 {
+        match self {
+            ExrCompression::None => 0,
+            ExrCompression::Rle => 1,
+            ExrCompression::Zips => 2,
+            ExrCompression::Zip => 3,
+        }
+    }
+
+    fn lines_per_chunk(&self) -> usize {
+        match self {
+            ExrCompression::Zip => 16,
+            _ => 1,
+        }
+    }
+}
+
+pub(crate) fn write(
+    path: &str,
+    width: u32,
+    height: u32,
+    channels: &[ExrChannel],
+    pixel_type: ExrPixelType,
+    compression: ExrCompression,
+) -> io::Result<()> {
+    let width = width as usize;
+    let height = height as usize;
+
+    for channel in channels {
+        if channel.data.len() != width * height {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "exr: channel '{}' has the wrong number of pixels",
+                    channel.name
+                ),
+            ));
+        }
+    }
+
+    // Channels are stored in alphabetical order
+    let mut channels = channels.iter().collect::<Vec<_>>();
+    channels.sort_by(|a, b| a.name.as_bytes().cmp(b.name.as_bytes()));
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01]);
+    header.extend_from_slice(&2u32.to_le_bytes());
+
+    let mut channel_list = Vec::new();
+    for channel in &channels {
+        channel_list.extend_from_slice(channel.name.as_bytes());
+        channel_list.push(0);
+        channel_list.extend_from_slice(&pixel_type.id().to_le_bytes());
+        // pLinear and reserved bytes, then x and y sampling
+        channel_list.extend_from_slice(&[0, 0, 0, 0]);
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channel_list.push(0);
+
+    let mut window = Vec::new();
+    for value in [0, 0, width as i32 - 1, height as i32 - 1] {
+        window.extend_from_slice(&value.to_le_bytes());
+    }
+
+    write_attribute(&mut header, "channels", "chlist", &channel_list);
+    write_attribute(
+        &mut header,
+        "compression",
+        "compression",
+        &[compression.id()],
+    );
+    write_attribute(&mut header, "dataWindow", "box2i", &window);
+    write_attribute(&mut header, "displayWindow", "box2i", &window);
+    write_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    write_attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    write_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    write_attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    header.push(0);
+
+    let lines_per_chunk = compression.lines_per_chunk();
+    let chunks = (0..height)
+        .step_by(lines_per_chunk)
+        .map(|y| {
+            let lines = y..(y + lines_per_chunk).min(height);
+
+            // Scanlines one after another, each holding every channel for the whole row
+            let mut raw = Vec::with_capacity(lines.len() * width * channels.len() * 4);
+            for line in lines {
+                for channel in &channels {
+                    for &value in &channel.data[(line * width)..((line + 1) * width)] {
+                        match pixel_type {
+                            ExrPixelType::Half => {
+                                raw.extend_from_slice(&f32_to_f16(value).to_le_bytes())
+                            }
+                            ExrPixelType::Float => raw.extend_from_slice(&value.to_le_bytes()),
+                        }
+                    }
+                }
+            }
+            debug_assert_eq!(raw.len() % pixel_type.size(), 0);
+
+            (y, compress(raw, compression))
+        })
+        .collect::<Vec<_>>();
+
+    let mut out = BufWriter::new(std::fs::File::create(path)?);
+    out.write_all(&header)?;
+
+    // Offset table, pointing at the start of every chunk from the start of the file
+    let mut offset = (header.len() + chunks.len() * 8) as u64;
+    for (_, data) in &chunks {
+        out.write_all(&offset.to_le_bytes())?;
+        offset += 8 + data.len() as u64;
+    }
+
+    for (y, data) in &chunks {
+        out.write_all(&(*y as i32).to_le_bytes())?;
+        out.write_all(&(data.len() as i32).to_le_bytes())?;
+        out.write_all(data)?;
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+fn write_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+// Chunks that don't shrink are stored uncompressed, readers detect this from the size
+fn compress(raw: Vec<u8>, compression: ExrCompression) -> Vec<u8> {
+    let compressed = match compression {
+        ExrCompression::None => return raw,
+        ExrCompression::Rle => rle(&predict(&raw)),
+        ExrCompression::Zips | ExrCompression::Zip => zlib::compress(&predict(&raw)),
+    };
+
+    if compressed.len() < raw.len() {
+        compressed
+    } else {
+        raw
+    }
+}
+
+// Splits even and odd bytes into two halves, then delta encodes
+fn predict(raw: &[u8]) -> Vec<u8> {
+    let half = raw.len().div_ceil(2);
+    let mut reordered = vec![0u8; raw.len()];
+    for (idx, &byte) in raw.iter().enumerate() {
+        if idx % 2 == 0 {
+            reordered[idx / 2] = byte;
+        } else {
+            reordered[half + idx / 2] = byte;
+        }
+    }
+
+    let mut previous = reordered.first().copied().unwrap_or_default();
+    for byte in reordered.iter_mut().skip(1) {
+        let current = *byte;
+        *byte = current.wrapping_sub(previous).wrapping_add(128);
+        previous = current;
+    }
+
+    reordered
+}
+
+// Runs of 3 to 128 bytes as (count - 1, value), literals as (-count, bytes...)
+fn rle(data: &[u8]) -> Vec<u8> {
+    const MIN_RUN: usize = 3;
+    const MAX_RUN: usize = 127;
+
+    let mut out = Vec::with_capacity(data.len());
+    let mut run_start = 0;
+    let mut run_end = 1;
+
+    while run_start < data.len() {
+        while run_end < data.len()
+            && data[run_start] == data[run_end]
+            && run_end - run_start - 1 < MAX_RUN
+        {
+            run_end += 1;
+        }
+
+        if run_end - run_start >= MIN_RUN {
+            out.push((run_end - run_start - 1) as u8);
+            out.push(data[run_start]);
+            run_start = run_end;
+        } else {
+            // Extend the literal until the next run of three starts
+            while run_end < data.len()
+                && (run_end + 2 >= data.len()
+                    || data[run_end] != data[run_end + 1]
+                    || data[run_end + 1] != data[run_end + 2])
+                && run_end - run_start < MAX_RUN
+            {
+                run_end += 1;
+            }
+            out.push((run_start as isize - run_end as isize) as i8 as u8);
+            out.extend_from_slice(&data[run_start..run_end]);
+            run_start = run_end;
+        }
+
+        run_end += 1;
+    }
+
+    out
+}
+
+// IEEE 754 binary16 with round to nearest even, out of range values become infinity
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        // Infinity or NaN, keep NaNs quiet
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+
+    let half_exponent = exponent - 127 + 15;
+
+    if half_exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    if half_exponent <= 0 {
+        // Subnormal or zero
+        if half_exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - half_exponent) as u32;
+        let half_mantissa = mantissa >> shift;
+        let remainder = mantissa & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        let round_up = remainder > halfway || (remainder == halfway && half_mantissa & 1 == 1);
+        return sign | (half_mantissa + round_up as u32) as u16;
+    }
+
+    let half_mantissa = mantissa >> 13;
+    let remainder = mantissa & 0x1fff;
+    let round_up = remainder > 0x1000 || (remainder == 0x1000 && half_mantissa & 1 == 1);
+
+    // Rounding may carry into the exponent, which correctly produces infinity at the top
+    let half = ((half_exponent as u32) << 10 | half_mantissa) + round_up as u32;
+    sign | half as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ExrChannel, ExrCompression, ExrPixelType, f32_to_f16, write};
+    use crate::image::zlib;
+
+    // Header attributes as (name, type, value) and the position after the header
+    fn read_attributes(bytes: &[u8]) -> (Vec<(String, String, Vec<u8>)>, usize) {
+        let mut pos = 8;
+        let string = |pos: &mut usize| {
+            let end = *pos + bytes[*pos..].iter().position(|&b| b == 0).unwrap();
+            let value = String::from_utf8(bytes[*pos..end].to_vec()).unwrap();
+            *pos = end + 1;
+            value
+        };
+
+        let mut attributes = Vec::new();
+        while bytes[pos] != 0 {
+            let name = string(&mut pos);
+            let kind = string(&mut pos);
+            let size = i32::from_le_bytes(bytes[pos..(pos + 4)].try_into().unwrap()) as usize;
+            attributes.push((name, kind, bytes[(pos + 4)..(pos + 4 + size)].to_vec()));
+            pos += 4 + size;
+        }
+        (attributes, pos + 1)
+    }
+
+    fn le_u64(bytes: &[u8], pos: usize) -> u64 {
+        u64::from_le_bytes(bytes[pos..(pos + 8)].try_into().unwrap())
+    }
+
+    fn le_i32(bytes: &[u8], pos: usize) -> i32 {
+        i32::from_le_bytes(bytes[pos..(pos + 4)].try_into().unwrap())
+    }
+
+    fn write_bytes(
+        width: u32,
+        height: u32,
+        channels: &[ExrChannel],
+        pixel_type: ExrPixelType,
+        compression: ExrCompression,
+    ) -> Vec<u8> {
+        let path = std::env::temp_dir().join(format!(
+            "weekend-rt-{}-{pixel_type:?}-{compression:?}.exr",
+            std::process::id()
+        ));
+        let path = path.to_string_lossy().into_owned();
+        write(&path, width, height, channels, pixel_type, compression).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        bytes
+    }
+
+    // 2x3 pixels, given out of order to check the alphabetical channel order
+    fn small_bytes(compression: ExrCompression) -> Vec<u8> {
+        let channels = [
+            ExrChannel::new("Z", vec![10.0, 11.0, 12.0, 13.0, 14.0, 15.0]),
+            ExrChannel::new("A", vec![0.0, 0.5, 1.0, 1.5, 2.0, 2.5]),
+        ];
+        write_bytes(2, 3, &channels, ExrPixelType::Float, compression)
+    }
+
+    // Inverse of rle, counts below zero are literals
+    fn unrle(data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut pos = 0;
+        while pos < data.len() {
+            let count = data[pos] as i8;
+            if count < 0 {
+                let end = pos + 1 + (-(count as isize)) as usize;
+                out.extend_from_slice(&data[(pos + 1)..end]);
+                pos = end;
+            } else {
+                out.extend(std::iter::repeat_n(data[pos + 1], count as usize + 1));
+                pos += 2;
+            }
+        }
+        out
+    }
+
+    // Inverse of predict, sums the deltas then interleaves the two halves again
+    fn unpredict(data: &[u8]) -> Vec<u8> {
+        let mut summed = data.to_vec();
+        for idx in 1..summed.len() {
+            summed[idx] = summed[idx - 1].wrapping_add(summed[idx]).wrapping_sub(128);
+        }
+
+        let half = summed.len().div_ceil(2);
+        (0..summed.len())
+            .map(|idx| {
+                if idx % 2 == 0 {
+                    summed[idx / 2]
+                } else {
+                    summed[half + idx / 2]
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn header_and_offset_table() {
+        let bytes = small_bytes(ExrCompression::None);
+        assert_eq!(bytes[..8], [0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+
+        let (attributes, end) = read_attributes(&bytes);
+        let names = attributes
+            .iter()
+            .map(|(name, kind, _)| format!("{name}:{kind}"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                "channels:chlist",
+                "compression:compression",
+                "dataWindow:box2i",
+                "displayWindow:box2i",
+                "lineOrder:lineOrder",
+                "pixelAspectRatio:float",
+                "screenWindowCenter:v2f",
+                "screenWindowWidth:float",
+            ]
+        );
+
+        // Float channels sorted by name, each with linear flag, reserved bytes and sampling
+        let channel =
+            |name: &[u8]| [name, &[0, 2, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0]].concat();
+        assert_eq!(
+            attributes[0].2,
+            [channel(b"A"), channel(b"Z"), vec![0]].concat()
+        );
+        assert_eq!(attributes[1].2, [0]);
+        let window = [0i32, 0, 1, 2].map(i32::to_le_bytes).concat();
+        assert_eq!((&attributes[2].2, &attributes[3].2), (&window, &window));
+
+        // One uncompressed scanline per chunk, each row holds every channel in turn
+        let row_size = 2 * 2 * 4;
+        for y in 0..3 {
+            let offset = le_u64(&bytes, end + y * 8) as usize;
+            assert_eq!(offset, end + 3 * 8 + y * (8 + row_size));
+            assert_eq!(le_i32(&bytes, offset), y as i32);
+            assert_eq!(le_i32(&bytes, offset + 4), row_size as i32);
+
+            let values = bytes[(offset + 8)..(offset + 8 + row_size)]
+                .chunks(4)
+                .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+                .collect::<Vec<_>>();
+            let (a, z) = (y as f32, 10.0 + 2.0 * y as f32);
+            assert_eq!(values, [a, a + 0.5, z, z + 1.0]);
+        }
+        assert_eq!(bytes.len(), end + 3 * 8 + 3 * (8 + row_size));
+
+        // Zip keeps 16 scanlines per chunk, so the whole image is one
+        let bytes = small_bytes(ExrCompression::Zip);
+        let (attributes, end) = read_attributes(&bytes);
+        assert_eq!(attributes[1].2, [3]);
+        let offset = le_u64(&bytes, end) as usize;
+        assert_eq!(offset, end + 8);
+        assert_eq!(le_i32(&bytes, offset), 0);
+        assert_eq!(
+            bytes.len(),
+            offset + 8 + le_i32(&bytes, offset + 4) as usize
+        );
+    }
+
+    // Every chunk decodes back to its scanlines of half or float bytes, compressed chunks are the
+    // ones smaller than that
+    #[test]
+    fn compressed_chunks_hold_the_scanlines() {
+        let (width, height) = (32, 20);
+        let pixels = |value: fn(usize, usize) -> f32| {
+            (0..width * height)
+                .map(|idx| value(idx % width, idx / width))
+                .collect::<Vec<_>>()
+        };
+        let channels = [
+            ExrChannel::new("A", pixels(|x, _| (x / 8) as f32)),
+            ExrChannel::new("Z", pixels(|x, y| 0.25 * y as f32 + 0.01 * x as f32)),
+        ];
+
+        for pixel_type in [ExrPixelType::Half, ExrPixelType::Float] {
+            let scanline = |y: usize| {
+                let mut bytes = Vec::new();
+                for channel in &channels {
+                    for &value in &channel.data[(y * width)..((y + 1) * width)] {
+                        match pixel_type {
+                            ExrPixelType::Half => {
+                                bytes.extend_from_slice(&f32_to_f16(value).to_le_bytes())
+                            }
+                            ExrPixelType::Float => bytes.extend_from_slice(&value.to_le_bytes()),
+                        }
+                    }
+                }
+                bytes
+            };
+
+            for (compression, id, lines) in [
+                (ExrCompression::None, 0, 1),
+                (ExrCompression::Rle, 1, 1),
+                (ExrCompression::Zips, 2, 1),
+                (ExrCompression::Zip, 3, 16),
+            ] {
+                let bytes = write_bytes(
+                    width as u32,
+                    height as u32,
+                    &channels,
+                    pixel_type,
+                    compression,
+                );
+                let (attributes, end) = read_attributes(&bytes);
+                assert_eq!(attributes[1].2, [id]);
+
+                let mut compressed = 0;
+                for (chunk, y) in (0..height).step_by(lines).enumerate() {
+                    let offset = le_u64(&bytes, end + chunk * 8) as usize;
+                    assert_eq!(le_i32(&bytes, offset), y as i32);
+                    let size = le_i32(&bytes, offset + 4) as usize;
+                    let data = &bytes[(offset + 8)..(offset + 8 + size)];
+
+                    let expected = (y..(y + lines).min(height))
+                        .flat_map(scanline)
+                        .collect::<Vec<_>>();
+                    let decoded = if size == expected.len() {
+                        data.to_vec()
+                    } else {
+                        compressed += 1;
+                        match compression {
+                            ExrCompression::Rle => unpredict(&unrle(data)),
+                            _ => unpredict(&zlib::decompress(data).unwrap()),
+                        }
+                    };
+                    assert_eq!(decoded, expected, "{pixel_type:?} {compression:?} {y}");
+                }
+
+                let chunks = height.div_ceil(lines);
+                let expected = if compression == ExrCompression::None {
+                    0
+                } else {
+                    chunks
+                };
+                assert_eq!(compressed, expected, "{pixel_type:?} {compression:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn halves_round_to_nearest_even() {
+        for (value, expected) in [
+            (0.0, 0x0000),
+            (-0.0, 0x8000),
+            (1.0, 0x3c00),
+            (-2.0, 0xc000),
+            // Halfway between 1 and the next half goes to the even mantissa, above it rounds up
+            (1.0 + f32::powi(2.0, -11), 0x3c00),
+            (1.0 + 3.0 * f32::powi(2.0, -11), 0x3c02),
+            (1.0 + 1.01 * f32::powi(2.0, -11), 0x3c01),
+            // Largest half, then values rounding past it and beyond the range become infinity
+            (65504.0, 0x7bff),
+            (65519.0, 0x7bff),
+            (65520.0, 0x7c00),
+            (1e6, 0x7c00),
+            (-1e6, 0xfc00),
+            (f32::INFINITY, 0x7c00),
+            (f32::NEG_INFINITY, 0xfc00),
+            // Smallest normal, largest and smallest subnormals, and rounding below them
+            (f32::powi(2.0, -14), 0x0400),
+            (f32::powi(2.0, -14) - f32::powi(2.0, -24), 0x03ff),
+            (f32::powi(2.0, -24), 0x0001),
+            (-f32::powi(2.0, -24), 0x8001),
+            (f32::powi(2.0, -25), 0x0000),
+            (1.5 * f32::powi(2.0, -25), 0x0001),
+            (3.0 * f32::powi(2.0, -25), 0x0002),
+            (f32::powi(2.0, -30), 0x0000),
+        ] {
+            assert_eq!(f32_to_f16(value), expected, "{value:e}");
+        }
+
+        assert_eq!(f32_to_f16(f32::NAN) & 0x7e00, 0x7e00);
+    }
+}
@@ -0,0 +1,425 @@
+use std::io::{self, BufWriter, ErrorKind, Write};
+
+use crate::color::Color;
+
+use super::Image;
+
+// Radiance RGBE (.hdr) reading and writing
+// https://paulbourke.net/dataformats/pic/
+
+pub(super) const MAGIC: [&[u8]; 2] = [b"#?RADIANCE", b"#?RGBE"];
+
+// Scanlines outside this width range can't use the run length encoded format
+const RLE_MIN_WIDTH: usize = 8;
+const RLE_MAX_WIDTH: usize = 0x7fff;
+
+pub(super) fn decode(bytes: &[u8]) -> Result<Image, io::Error> {
+    let mut pos = 0;
+
+    // Header is a list of lines terminated by an empty line
+    let mut format_ok = true;
+    loop {
+        let line = next_line(bytes, &mut pos).ok_or_else(|| error("unterminated header"))?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix(b"FORMAT=") {
+            format_ok = format == b"32-bit_rle_rgbe";
+        }
+    }
+    if !format_ok {
+        return Err(error("only the 32-bit_rle_rgbe format is supported"));
+    }
+
+    let resolution = next_line(bytes, &mut pos).ok_or_else(|| error("missing resolution"))?;
+    let resolution =
+        std::str::from_utf8(resolution).map_err(|_| error("resolution line is not text"))?;
+    let (height, width, flip_y, flip_x) = parse_resolution(resolution)?;
+
+    // Every scanline starts with at least one 4 byte pixel. Repeats can make a scanline very wide
+    // for its size, so the buffers grow with the decoded rows instead of the resolution line
+    let count = width
+        .checked_mul(height)
+        .filter(|count| count.checked_mul(size_of::<Color>()).is_some())
+        .ok_or_else(|| error("image size overflows"))?;
+    if height > (bytes.len() - pos) / 4 {
+        return Err(error(&format!(
+            "{height} scanlines don't fit in the {} bytes of pixel data",
+            bytes.len() - pos
+        )));
+    }
+
+    let mut pixels = Vec::new();
+    let mut scanline = Vec::new();
+    scanline
+        .try_reserve_exact(width)
+        .map_err(|_| error("not enough memory for a scanline"))?;
+    scanline.resize(width, [0u8; 4]);
+
+    for _ in 0..height {
+        read_scanline(bytes, &mut pos, &mut scanline)?;
+
+        pixels
+            .try_reserve(width)
+            .map_err(|_| error("not enough memory for the image"))?;
+        if flip_x {
+            pixels.extend(scanline.iter().rev().map(|rgbe| rgbe_to_color(*rgbe)));
+        } else {
+            pixels.extend(scanline.iter().map(|rgbe| rgbe_to_color(*rgbe)));
+        }
+    }
+    debug_assert_eq!(pixels.len(), count);
+
+    // Rows were stored in file order
+    if flip_y && width > 0 {
+        let (top, bottom) = pixels.split_at_mut(height / 2 * width);
+        let bottom_rows = bottom.rchunks_exact_mut(width);
+        for (row, flipped) in top.chunks_exact_mut(width).zip(bottom_rows) {
+            row.swap_with_slice(flipped);
+        }
+    }
+
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+pub(super) fn write(path: &str, width: u32, height: u32, pixels: &[Color]) -> io::Result<()> {
+    let mut out = BufWriter::new(std::fs::File::create(path)?);
+    writeln!(out, "#?RADIANCE")?;
+    writeln!(out, "FORMAT=32-bit_rle_rgbe")?;
+    writeln!(out)?;
+    writeln!(out, "-Y {height} +X {width}")?;
+
+    let width = width as usize;
+    let mut components = vec![0u8; width];
+
+    for i in 0..height as usize {
+        let row = &pixels[(i * width)..((i + 1) * width)];
+
+        if !(RLE_MIN_WIDTH..=RLE_MAX_WIDTH).contains(&width) {
+            for pixel in row {
+                out.write_all(&color_to_rgbe(*pixel))?;
+            }
+            continue;
+        }
+
+        out.write_all(&[2, 2, (width >> 8) as u8, (width & 0xff) as u8])?;
+
+        // Each component is run length encoded separately
+        for component in 0..4 {
+            for (byte, pixel) in components.iter_mut().zip(row) {
+                *byte = color_to_rgbe(*pixel)[component];
+            }
+            write_rle(&mut out, &components)?;
+        }
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+fn parse_resolution(line: &str) -> Result<(usize, usize, bool, bool), io::Error> {
+    let tokens = line.split_whitespace().collect::<Vec<_>>();
+    let [y_axis, height, x_axis, width] = tokens.as_slice() else {
+        return Err(error("malformed resolution line"));
+    };
+
+    let flip_y = match *y_axis {
+        "-Y" => false,
+        "+Y" => true,
+        _ => return Err(error("only row major (Y X) orientations are supported")),
+    };
+    let flip_x = match *x_axis {
+        "+X" => false,
+        "-X" => true,
+        _ => return Err(error("only row major (Y X) orientations are supported")),
+    };
+
+    let height = height
+        .parse::<usize>()
+        .map_err(|_| error("invalid image height"))?;
+    let width = width
+        .parse::<usize>()
+        .map_err(|_| error("invalid image width"))?;
+
+    Ok((height, width, flip_y, flip_x))
+}
+
+fn read_scanline(bytes: &[u8], pos: &mut usize, scanline: &mut [[u8; 4]]) -> Result<(), io::Error> {
+    let width = scanline.len();
+
+    let start = bytes
+        .get(*pos..(*pos + 4))
+        .ok_or_else(|| error("unexpected end of pixel data"))?;
+
+    let is_rle = (RLE_MIN_WIDTH..=RLE_MAX_WIDTH).contains(&width)
+        && start[0] == 2
+        && start[1] == 2
+        && start[2] & 0x80 == 0;
+
+    if !is_rle {
+        return read_flat_scanline(bytes, pos, scanline);
+    }
+
+    let encoded_width = ((start[2] as usize) << 8) | start[3] as usize;
+    if encoded_width != width {
+        return Err(error("scanline width doesn't match image width"));
+    }
+    *pos += 4;
+
+    for component in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = *bytes
+                .get(*pos)
+                .ok_or_else(|| error("unexpected end of pixel data"))?
+                as usize;
+            *pos += 1;
+
+            if count > 128 {
+                // Run of a single value
+                let count = count - 128;
+                let value = *bytes
+                    .get(*pos)
+                    .ok_or_else(|| error("unexpected end of pixel data"))?;
+                *pos += 1;
+                if count > width - x {
+                    return Err(error("run overflows scanline"));
+                }
+                for pixel in &mut scanline[x..(x + count)] {
+                    pixel[component] = value;
+                }
+                x += count;
+            } else {
+                // Literal values
+                if count == 0 || count > width - x {
+                    return Err(error("invalid literal count in scanline"));
+                }
+                let values = bytes
+                    .get(*pos..(*pos + count))
+                    .ok_or_else(|| error("unexpected end of pixel data"))?;
+                *pos += count;
+                for (pixel, value) in scanline[x..(x + count)].iter_mut().zip(values) {
+                    pixel[component] = *value;
+                }
+                x += count;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Uncompressed pixels, optionally with the original (1, 1, 1, n) repeat encoding
+fn read_flat_scanline(
+    bytes: &[u8],
+    pos: &mut usize,
+    scanline: &mut [[u8; 4]],
+) -> Result<(), io::Error> {
+    let width = scanline.len();
+    let mut x = 0;
+    let mut shift = 0;
+
+    while x < width {
+        let rgbe: [u8; 4] = bytes
+            .get(*pos..(*pos + 4))
+            .ok_or_else(|| error("unexpected end of pixel data"))?
+            .try_into()
+            .unwrap();
+        *pos += 4;
+
+        if rgbe[0] == 1 && rgbe[1] == 1 && rgbe[2] == 1 {
+            if x == 0 {
+                return Err(error("repeat without a previous pixel"));
+            }
+            // Each repeat in a row is the next byte of the count, four bytes already cover any width
+            if shift >= 32 {
+                return Err(error("run overflows scanline"));
+            }
+            let count = (rgbe[3] as usize) << shift;
+            if count > width - x {
+                return Err(error("run overflows scanline"));
+            }
+            let previous = scanline[x - 1];
+            scanline[x..(x + count)].fill(previous);
+            x += count;
+            shift += 8;
+        } else {
+            scanline[x] = rgbe;
+            x += 1;
+            shift = 0;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_rle<W: Write>(out: &mut W, data: &[u8]) -> io::Result<()> {
+    const MIN_RUN: usize = 4;
+
+    let mut cur = 0;
+    while cur < data.len() {
+        // Find the next run long enough to be worth encoding
+        let mut begin_run = cur;
+        let mut run_count = 0;
+        while run_count < MIN_RUN && begin_run < data.len() {
+            begin_run += run_count;
+            run_count = 1;
+            while begin_run + run_count < data.len()
+                && run_count < 127
+                && data[begin_run] == data[begin_run + run_count]
+            {
+                run_count += 1;
+            }
+        }
+
+        // Short run right before the long one is cheaper as a run than as literals
+        if begin_run - cur > 1 && begin_run - cur < MIN_RUN {
+            let mut short_run = cur + 1;
+            while short_run < begin_run && data[short_run] == data[cur] {
+                short_run += 1;
+            }
+            if short_run == begin_run {
+                out.write_all(&[(128 + begin_run - cur) as u8, data[cur]])?;
+                cur = begin_run;
+            }
+        }
+
+        // Literals up to the start of the run
+        while cur < begin_run {
+            let count = (begin_run - cur).min(128);
+            out.write_all(&[count as u8])?;
+            out.write_all(&data[cur..(cur + count)])?;
+            cur += count;
+        }
+
+        if run_count >= MIN_RUN {
+            out.write_all(&[(128 + run_count) as u8, data[begin_run]])?;
+            cur += run_count;
+        }
+    }
+
+    Ok(())
+}
+
+fn rgbe_to_color(rgbe: [u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+
+    // 2^(e - 128) scaled by 1/256 for the 8 bit mantissas
+    let scale = f32::powi(2.0, rgbe[3] as i32 - (128 + 8));
+    Color::new(
+        (rgbe[0] as f32 + 0.5) * scale,
+        (rgbe[1] as f32 + 0.5) * scale,
+        (rgbe[2] as f32 + 0.5) * scale,
+    )
+}
+
+fn color_to_rgbe(color: Color) -> [u8; 4] {
+    let r = color.r.max(0.0);
+    let g = color.g.max(0.0);
+    let b = color.b.max(0.0);
+    let max = r.max(g).max(b);
+
+    if !max.is_finite() || max < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+
+    // max = mantissa * 2^exponent with mantissa in [0.5, 1)
+    let exponent = max.log2().floor() as i32 + 1;
+    let scale = f32::powi(2.0, 8 - exponent);
+    let exponent = exponent + 128;
+    if !(1..=255).contains(&exponent) {
+        return [0, 0, 0, 0];
+    }
+
+    [
+        (r * scale).min(255.0) as u8,
+        (g * scale).min(255.0) as u8,
+        (b * scale).min(255.0) as u8,
+        exponent as u8,
+    ]
+}
+
+fn next_line<'a>(bytes: &'a [u8], pos: &mut usize) -> Option<&'a [u8]> {
+    let rest = bytes.get(*pos..)?;
+    let end = rest.iter().position(|&b| b == b'\n')?;
+    *pos += end + 1;
+    Some(rest[..end].strip_suffix(b"\r").unwrap_or(&rest[..end]))
+}
+
+fn error(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, format!("hdr: {message}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, read_flat_scanline};
+
+    fn file(resolution: &str, pixels: &[[u8; 4]]) -> Vec<u8> {
+        let mut bytes =
+            format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n{resolution}\n").into_bytes();
+        bytes.extend(pixels.iter().flatten());
+        bytes
+    }
+
+    #[test]
+    fn flips_rows_and_columns() {
+        // Exponent 137 scales the mantissas to 2 * (value + 0.5)
+        let pixels = [
+            [0, 0, 0, 137],
+            [1, 0, 0, 137],
+            [2, 0, 0, 137],
+            [3, 0, 0, 137],
+        ];
+        for (resolution, order) in [
+            ("-Y 2 +X 2", [0, 1, 2, 3]),
+            ("+Y 2 +X 2", [2, 3, 0, 1]),
+            ("-Y 2 -X 2", [1, 0, 3, 2]),
+            ("+Y 2 -X 2", [3, 2, 1, 0]),
+        ] {
+            let image = decode(&file(resolution, &pixels)).unwrap();
+            let red = image
+                .pixels
+                .iter()
+                .map(|c| c.r / 2.0 - 0.5)
+                .collect::<Vec<_>>();
+            assert_eq!(red, order.map(|v| v as f32), "{resolution}");
+        }
+    }
+
+    #[test]
+    fn rejects_resolutions_larger_than_the_data() {
+        let pixels = [[1, 2, 3, 128]; 4];
+        let error = decode(&file("-Y 3 +X 2", &pixels)).unwrap_err();
+        assert_eq!(error.to_string(), "hdr: unexpected end of pixel data");
+
+        let huge = format!("-Y {} +X {}", usize::MAX / 2, usize::MAX / 2);
+        let error = decode(&file(&huge, &pixels)).unwrap_err();
+        assert_eq!(error.to_string(), "hdr: image size overflows");
+
+        let error = decode(&file("-Y 1000000 +X 1000000", &pixels)).unwrap_err();
+        assert!(error.to_string().contains("16 bytes"), "{error}");
+    }
+
+    #[test]
+    fn repeats_are_bounded() {
+        let mut scanline = [[0; 4]; 8];
+        let mut bytes = vec![10, 20, 30, 128];
+        // Empty repeats never advance the scanline, only the count's shift
+        for _ in 0..5 {
+            bytes.extend([1, 1, 1, 0]);
+        }
+        let error = read_flat_scanline(&bytes, &mut 0, &mut scanline).unwrap_err();
+        assert_eq!(error.to_string(), "hdr: run overflows scanline");
+
+        // A short run is expanded from the pixel before it
+        let bytes = [10, 20, 30, 128, 1, 1, 1, 7];
+        read_flat_scanline(&bytes, &mut 0, &mut scanline).unwrap();
+        assert_eq!(scanline, [[10, 20, 30, 128]; 8]);
+    }
+}
@@ -0,0 +1,744 @@
+use std::io::{self, ErrorKind};
+
+use crate::color::Color;
+
+use super::Image;
+
+// Baseline (sequential Huffman) JPEG decoding
+// https://www.w3.org/Graphics/JPEG/itu-t81.pdf
+
+pub(super) const MAGIC: [u8; 3] = [0xff, 0xd8, 0xff];
+
+// Position of the k-th zigzag coefficient in a row major 8x8 block
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+pub(super) fn decode(bytes: &[u8]) -> Result<Image, io::Error> {
+    if !bytes.starts_with(&MAGIC[..2]) {
+        return Err(error("missing start of image marker"));
+    }
+
+    let mut decoder = Decoder {
+        quantization: [None; 4],
+        dc_tables: [None, None, None, None],
+        ac_tables: [None, None, None, None],
+        frame: None,
+        restart_interval: 0,
+    };
+
+    let mut pos = 2;
+    loop {
+        let marker = next_marker(bytes, &mut pos)?;
+
+        // Markers without a length field
+        if marker == 0xd9 {
+            break;
+        }
+        if marker == 0x00 || marker == 0x01 || (0xd0..=0xd7).contains(&marker) {
+            continue;
+        }
+
+        let length = read_u16(bytes, pos)? as usize;
+        if length < 2 {
+            return Err(error(&format!("invalid length in marker 0x{marker:02x}")));
+        }
+        let segment = bytes
+            .get((pos + 2)..(pos + length))
+            .ok_or_else(|| error("unexpected end of file"))?;
+        pos += length;
+
+        match marker {
+            0xc0 | 0xc1 => decoder.read_frame(segment, bytes.len() - pos)?,
+            0xc2 | 0xc6 | 0xca | 0xce => {
+                return Err(error("progressive JPEGs are not supported"));
+            }
+            0xc3 | 0xc5 | 0xc7 | 0xcb | 0xcd | 0xcf => {
+                return Err(error("lossless and hierarchical JPEGs are not supported"));
+            }
+            0xc9 | 0xcc => return Err(error("arithmetic coded JPEGs are not supported")),
+            0xc4 => decoder.read_huffman_tables(segment)?,
+            0xdb => decoder.read_quantization_tables(segment)?,
+            0xdd => {
+                if segment.len() != 2 {
+                    return Err(error("invalid restart interval segment"));
+                }
+                decoder.restart_interval = read_u16(segment, 0)? as usize;
+            }
+            0xda => pos = decoder.read_scan(segment, bytes, pos)?,
+            0xdc => return Err(error("DNL markers are not supported")),
+            // APPn, COM and anything else informational
+            _ => {}
+        }
+    }
+
+    decoder.into_image()
+}
+
+struct Component {
+    id: u8,
+    h: usize,
+    v: usize,
+    quantization: usize,
+    // Samples padded to whole MCUs
+    width: usize,
+    height: usize,
+    samples: Vec<u8>,
+    dc_prediction: i32,
+}
+
+struct Frame {
+    width: usize,
+    height: usize,
+    max_h: usize,
+    max_v: usize,
+    mcus_x: usize,
+    mcus_y: usize,
+    components: Vec<Component>,
+}
+
+struct Decoder {
+    quantization: [Option<[u16; 64]>; 4],
+    dc_tables: [Option<Huffman>; 4],
+    ac_tables: [Option<Huffman>; 4],
+    frame: Option<Frame>,
+    restart_interval: usize,
+}
+
+impl Decoder {
+    // Remaining is the number of bytes after the frame header, which bounds the image size
+    fn read_frame(&mut self, segment: &[u8], remaining: usize) -> Result<(), io::Error> {
+        if self.frame.is_some() {
+            return Err(error("more than one frame header"));
+        }
+        if segment.len() < 6 {
+            return Err(error("frame header too short"));
+        }
+        if segment[0] != 8 {
+            return Err(error(&format!(
+                "{} bit samples are not supported",
+                segment[0]
+            )));
+        }
+
+        let height = read_u16(segment, 1)? as usize;
+        let width = read_u16(segment, 3)? as usize;
+        let count = segment[5] as usize;
+
+        if height == 0 {
+            return Err(error("image height defined by DNL is not supported"));
+        }
+        if width == 0 {
+            return Err(error("image has zero width"));
+        }
+        if count != 1 && count != 3 {
+            return Err(error(&format!(
+                "{count} color components are not supported"
+            )));
+        }
+        if segment.len() != 6 + count * 3 {
+            return Err(error("frame header has the wrong size"));
+        }
+
+        let mut components = Vec::with_capacity(count);
+        for spec in segment[6..].chunks(3) {
+            let h = (spec[1] >> 4) as usize;
+            let v = (spec[1] & 0xf) as usize;
+            if !(1..=4).contains(&h) || !(1..=4).contains(&v) {
+                return Err(error("invalid sampling factor"));
+            }
+            if spec[2] > 3 {
+                return Err(error("invalid quantization table index"));
+            }
+            components.push(Component {
+                id: spec[0],
+                h,
+                v,
+                quantization: spec[2] as usize,
+                width: 0,
+                height: 0,
+                samples: Vec::new(),
+                dc_prediction: 0,
+            });
+        }
+
+        let max_h = components.iter().map(|c| c.h).max().unwrap();
+        let max_v = components.iter().map(|c| c.v).max().unwrap();
+        let mcus_x = width.div_ceil(8 * max_h);
+        let mcus_y = height.div_ceil(8 * max_v);
+
+        for component in &mut components {
+            component.width = mcus_x * component.h * 8;
+            component.height = mcus_y * component.v * 8;
+        }
+
+        // Every 8x8 block takes at least two bits of entropy coded data, a DC code and an end of
+        // block, so the frame size is checked against what is left of the file before allocating
+        let blocks = components
+            .iter()
+            .try_fold(0usize, |total, c| {
+                total.checked_add(c.width.checked_mul(c.height)? / 64)
+            })
+            .ok_or_else(|| error("frame size overflows"))?;
+        if blocks.div_ceil(4) > remaining {
+            return Err(error(&format!(
+                "frame of {width}x{height} needs more data than the {remaining} bytes left"
+            )));
+        }
+        for component in &mut components {
+            component.samples = vec![0; component.width * component.height];
+        }
+
+        self.frame = Some(Frame {
+            width,
+            height,
+            max_h,
+            max_v,
+            mcus_x,
+            mcus_y,
+            components,
+        });
+        Ok(())
+    }
+
+    fn read_huffman_tables(&mut self, mut segment: &[u8]) -> Result<(), io::Error> {
+        while !segment.is_empty() {
+            if segment.len() < 17 {
+                return Err(error("huffman table segment too short"));
+            }
+            let class = segment[0] >> 4;
+            let idx = (segment[0] & 0xf) as usize;
+            if class > 1 || idx > 3 {
+                return Err(error("invalid huffman table class or index"));
+            }
+
+            let counts: [u8; 16] = segment[1..17].try_into().unwrap();
+            let total = counts.iter().map(|&c| c as usize).sum::<usize>();
+            let symbols = segment
+                .get(17..(17 + total))
+                .ok_or_else(|| error("huffman table segment too short"))?;
+
+            let table = Huffman::new(&counts, symbols)?;
+            if class == 0 {
+                self.dc_tables[idx] = Some(table);
+            } else {
+                self.ac_tables[idx] = Some(table);
+            }
+
+            segment = &segment[(17 + total)..];
+        }
+        Ok(())
+    }
+
+    fn read_quantization_tables(&mut self, mut segment: &[u8]) -> Result<(), io::Error> {
+        while !segment.is_empty() {
+            let precision = segment[0] >> 4;
+            let idx = (segment[0] & 0xf) as usize;
+            if precision > 1 || idx > 3 {
+                return Err(error("invalid quantization table precision or index"));
+            }
+
+            // Values are in zigzag order, 8 or 16 bits each
+            let size = if precision == 0 { 64 } else { 128 };
+            let values = segment
+                .get(1..(1 + size))
+                .ok_or_else(|| error("quantization table segment too short"))?;
+            let mut table = [0u16; 64];
+            for (k, value) in table.iter_mut().enumerate() {
+                *value = if precision == 0 {
+                    values[k] as u16
+                } else {
+                    u16::from_be_bytes([values[2 * k], values[2 * k + 1]])
+                };
+            }
+
+            self.quantization[idx] = Some(table);
+            segment = &segment[(1 + size)..];
+        }
+        Ok(())
+    }
+
+    // Decodes the entropy coded data after the header, returns the position after it
+    fn read_scan(&mut self, segment: &[u8], bytes: &[u8], pos: usize) -> Result<usize, io::Error> {
+        let frame = self
+            .frame
+            .as_mut()
+            .ok_or_else(|| error("scan before frame header"))?;
+
+        let count = *segment.first().ok_or_else(|| error("empty scan header"))? as usize;
+        if count == 0 || count > 4 || segment.len() != 4 + count * 2 {
+            return Err(error("scan header has the wrong size"));
+        }
+
+        // (component index, dc table, ac table, quantization table)
+        let mut scan = Vec::with_capacity(count);
+        for spec in segment[1..(1 + count * 2)].chunks(2) {
+            let component = frame
+                .components
+                .iter()
+                .position(|c| c.id == spec[0])
+                .ok_or_else(|| error(&format!("scan refers to unknown component {}", spec[0])))?;
+
+            let dc = self.dc_tables[(spec[1] >> 4) as usize & 3]
+                .as_ref()
+                .ok_or_else(|| error("scan uses an undefined DC huffman table"))?;
+            let ac = self.ac_tables[(spec[1] & 0xf) as usize & 3]
+                .as_ref()
+                .ok_or_else(|| error("scan uses an undefined AC huffman table"))?;
+            let quantization = self.quantization[frame.components[component].quantization]
+                .as_ref()
+                .ok_or_else(|| error("component uses an undefined quantization table"))?;
+
+            scan.push((component, dc, ac, quantization));
+        }
+
+        let selection = &segment[(1 + count * 2)..];
+        if selection != [0, 63, 0] {
+            return Err(error(
+                "spectral selection or successive approximation in a baseline scan",
+            ));
+        }
+
+        for component in &mut frame.components {
+            component.dc_prediction = 0;
+        }
+
+        // Single component scans cover only the blocks inside the image, not whole MCUs
+        let (units_x, units_y) = if count == 1 {
+            let component = &frame.components[scan[0].0];
+            (
+                (frame.width * component.h).div_ceil(frame.max_h * 8),
+                (frame.height * component.v).div_ceil(frame.max_v * 8),
+            )
+        } else {
+            (frame.mcus_x, frame.mcus_y)
+        };
+
+        let mut reader = BitReader::new(bytes, pos);
+        let mut block = [0i32; 64];
+        let cosines = idct_cosines();
+
+        for unit in 0..(units_x * units_y) {
+            if self.restart_interval > 0 && unit > 0 && unit % self.restart_interval == 0 {
+                reader.restart()?;
+                for component in &mut frame.components {
+                    component.dc_prediction = 0;
+                }
+            }
+
+            let (unit_x, unit_y) = (unit % units_x, unit / units_x);
+
+            for &(idx, dc, ac, quantization) in &scan {
+                let component = &mut frame.components[idx];
+                let (blocks_h, blocks_v) = if count == 1 {
+                    (1, 1)
+                } else {
+                    (component.h, component.v)
+                };
+
+                for block_y in 0..blocks_v {
+                    for block_x in 0..blocks_h {
+                        decode_block(&mut reader, dc, ac, quantization, component, &mut block)?;
+
+                        let x = (unit_x * blocks_h + block_x) * 8;
+                        let y = (unit_y * blocks_v + block_y) * 8;
+                        idct(
+                            &block,
+                            &cosines,
+                            &mut component.samples,
+                            component.width,
+                            x,
+                            y,
+                        );
+                    }
+                }
+            }
+        }
+
+        Ok(reader.position())
+    }
+
+    fn into_image(self) -> Result<Image, io::Error> {
+        let frame = self.frame.ok_or_else(|| error("missing frame header"))?;
+
+        let mut pixels = Vec::with_capacity(frame.width * frame.height);
+        for y in 0..frame.height {
+            for x in 0..frame.width {
+                // Subsampled components are upsampled by repeating samples
+                let sample = |component: &Component| {
+                    let cx = x * component.h / frame.max_h;
+                    let cy = y * component.v / frame.max_v;
+                    component.samples[cy * component.width + cx] as f32
+                };
+
+                let color = match frame.components.as_slice() {
+                    [gray] => {
+                        let gray = sample(gray) / 255.0;
+                        Color::new(gray, gray, gray)
+                    }
+                    [y, cb, cr] => {
+                        let (y, cb, cr) = (sample(y), sample(cb) - 128.0, sample(cr) - 128.0);
+                        let r = y + 1.402 * cr;
+                        let g = y - 0.344136 * cb - 0.714136 * cr;
+                        let b = y + 1.772 * cb;
+                        Color::new(
+                            r.clamp(0.0, 255.0) / 255.0,
+                            g.clamp(0.0, 255.0) / 255.0,
+                            b.clamp(0.0, 255.0) / 255.0,
+                        )
+                    }
+                    _ => unreachable!(),
+                };
+                pixels.push(color);
+            }
+        }
+
+        Ok(Image {
+            width: frame.width,
+            height: frame.height,
+            pixels,
+        })
+    }
+}
+
+fn decode_block(
+    reader: &mut BitReader,
+    dc: &Huffman,
+    ac: &Huffman,
+    quantization: &[u16; 64],
+    component: &mut Component,
+    block: &mut [i32; 64],
+) -> Result<(), io::Error> {
+    block.fill(0);
+
+    let size = dc.decode(reader)?;
+    if size > 11 {
+        return Err(error("invalid DC coefficient size"));
+    }
+    // Baseline DC coefficients fit in 11 bits, so corrupt differences can't pile up and overflow
+    // once multiplied by a 16 bit quantization value
+    component.dc_prediction += reader.receive_extend(size)?;
+    if component.dc_prediction.abs() > 2047 {
+        return Err(error(&format!(
+            "DC coefficient {} out of range",
+            component.dc_prediction
+        )));
+    }
+    block[0] = component.dc_prediction * quantization[0] as i32;
+
+    let mut k = 1;
+    while k < 64 {
+        let symbol = ac.decode(reader)?;
+        let (run, size) = ((symbol >> 4) as usize, symbol & 0xf);
+
+        if size == 0 {
+            if run == 15 {
+                // Sixteen zeros
+                k += 16;
+                continue;
+            }
+            // End of block
+            break;
+        }
+
+        k += run;
+        if k > 63 {
+            return Err(error("AC coefficient index out of range"));
+        }
+        block[ZIGZAG[k]] = reader.receive_extend(size)? * quantization[k] as i32;
+        k += 1;
+    }
+
+    Ok(())
+}
+
+// Basis functions scaled by C(u) / 2, indexed by sample position then frequency
+fn idct_cosines() -> [[f32; 8]; 8] {
+    let mut cosines = [[0f32; 8]; 8];
+    for (pos, row) in cosines.iter_mut().enumerate() {
+        for (freq, value) in row.iter_mut().enumerate() {
+            let scale = if freq == 0 {
+                std::f32::consts::FRAC_1_SQRT_2
+            } else {
+                1.0
+            };
+            *value = scale
+                * 0.5
+                * ((2 * pos + 1) as f32 * freq as f32 * std::f32::consts::PI / 16.0).cos();
+        }
+    }
+    cosines
+}
+
+// Separable float IDCT, level shifted and clamped into the sample plane
+fn idct(
+    block: &[i32; 64],
+    cosines: &[[f32; 8]; 8],
+    samples: &mut [u8],
+    stride: usize,
+    x: usize,
+    y: usize,
+) {
+    let mut rows = [0f32; 64];
+    for v in 0..8 {
+        for (col, cosine) in cosines.iter().enumerate() {
+            rows[v * 8 + col] = (0..8).map(|u| cosine[u] * block[v * 8 + u] as f32).sum();
+        }
+    }
+
+    for (row, cosine) in cosines.iter().enumerate() {
+        for col in 0..8 {
+            let value: f32 = (0..8).map(|v| cosine[v] * rows[v * 8 + col]).sum();
+            samples[(y + row) * stride + x + col] = (value + 128.0).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+// Canonical Huffman table decoded a bit at a time as in figure F.16
+struct Huffman {
+    max_code: [i32; 17],
+    offsets: [i32; 17],
+    symbols: Vec<u8>,
+}
+
+impl Huffman {
+    fn new(counts: &[u8; 16], symbols: &[u8]) -> Result<Self, io::Error> {
+        let mut max_code = [-1i32; 17];
+        let mut offsets = [0i32; 17];
+
+        let mut code = 0i32;
+        let mut idx = 0i32;
+        for len in 1..=16 {
+            let count = counts[len - 1] as i32;
+            // Offset from a code of this length to its symbol index
+            offsets[len] = idx - code;
+            if count > 0 {
+                code += count;
+                idx += count;
+                max_code[len] = code - 1;
+            }
+            if code > 1 << len {
+                return Err(error("oversubscribed huffman table"));
+            }
+            code <<= 1;
+        }
+
+        Ok(Huffman {
+            max_code,
+            offsets,
+            symbols: symbols.to_vec(),
+        })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u8, io::Error> {
+        let mut code = 0i32;
+        for len in 1..=16 {
+            code = (code << 1) | reader.read_bit()? as i32;
+            if code <= self.max_code[len] {
+                return Ok(self.symbols[(code + self.offsets[len]) as usize]);
+            }
+        }
+        Err(error("invalid huffman code"))
+    }
+}
+
+// Reads entropy coded bits, skipping stuffed zero bytes after 0xff
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    buffer: u8,
+    count: u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8], position: usize) -> Self {
+        BitReader {
+            bytes,
+            position,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    fn read_bit(&mut self) -> Result<u8, io::Error> {
+        if self.count == 0 {
+            let byte = *self
+                .bytes
+                .get(self.position)
+                .ok_or_else(|| error("unexpected end of scan data"))?;
+
+            if byte == 0xff {
+                match self.bytes.get(self.position + 1) {
+                    Some(0) => self.position += 2,
+                    // A marker ends the data, decoders pad with ones
+                    Some(_) => {
+                        self.buffer = 0xff;
+                        self.count = 8;
+                        return self.read_bit();
+                    }
+                    None => return Err(error("unexpected end of scan data")),
+                }
+            } else {
+                self.position += 1;
+            }
+
+            self.buffer = byte;
+            self.count = 8;
+        }
+
+        self.count -= 1;
+        Ok((self.buffer >> self.count) & 1)
+    }
+
+    // Reads a size bit value and maps it to its signed range as in figure F.12
+    fn receive_extend(&mut self, size: u8) -> Result<i32, io::Error> {
+        let mut value = 0i32;
+        for _ in 0..size {
+            value = (value << 1) | self.read_bit()? as i32;
+        }
+        if size > 0 && value < 1 << (size - 1) {
+            value -= (1 << size) - 1;
+        }
+        Ok(value)
+    }
+
+    fn restart(&mut self) -> Result<(), io::Error> {
+        self.count = 0;
+        match self.bytes.get(self.position..(self.position + 2)) {
+            Some([0xff, marker]) if (0xd0..=0xd7).contains(marker) => {
+                self.position += 2;
+                Ok(())
+            }
+            _ => Err(error("missing restart marker")),
+        }
+    }
+
+    fn position(&self) -> usize {
+        self.position
+    }
+}
+
+// Skips to the next marker and returns its code, fill bytes are allowed before it
+fn next_marker(bytes: &[u8], pos: &mut usize) -> Result<u8, io::Error> {
+    while *pos < bytes.len() && bytes[*pos] != 0xff {
+        *pos += 1;
+    }
+    while *pos < bytes.len() && bytes[*pos] == 0xff {
+        *pos += 1;
+    }
+    let marker = *bytes
+        .get(*pos)
+        .ok_or_else(|| error("missing end of image marker"))?;
+    *pos += 1;
+    Ok(marker)
+}
+
+fn read_u16(bytes: &[u8], pos: usize) -> Result<u16, io::Error> {
+    bytes
+        .get(pos..(pos + 2))
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+        .ok_or_else(|| error("unexpected end of file"))
+}
+
+fn error(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, format!("jpeg: {message}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::decode;
+
+    // 16x8 grayscale baseline image of two flat blocks, only DC coefficients
+    fn baseline_grayscale() -> Vec<u8> {
+        let mut bytes = vec![0xff, 0xd8];
+        // Quantization table 0, all ones
+        bytes.extend([0xff, 0xdb, 0x00, 0x43, 0x00]);
+        bytes.extend([1; 64]);
+        // Frame, 8 rows of 16 samples with one component
+        bytes.extend([
+            0xff, 0xc0, 0x00, 0x0b, 0x08, 0x00, 0x08, 0x00, 0x10, 0x01, 0x01, 0x11, 0x00,
+        ]);
+        // DC table 0 has two 2 bit codes for categories 10 and 11, AC table 0 only end of block
+        bytes.extend([0xff, 0xc4, 0x00, 0x15, 0x00, 0x00, 0x02]);
+        bytes.extend([0; 14]);
+        bytes.extend([0x0a, 0x0b]);
+        bytes.extend([0xff, 0xc4, 0x00, 0x14, 0x10, 0x01]);
+        bytes.extend([0; 15]);
+        bytes.push(0x00);
+        // Scan header
+        bytes.extend([0xff, 0xda, 0x00, 0x08, 0x01, 0x01, 0x00, 0x00, 0x3f, 0x00]);
+        // DC differences of 512 and -1024 for levels 192 and 64, each block ends right away.
+        // The 0xff data byte is stuffed with a zero
+        bytes.extend([0x20, 0x02, 0xff, 0x00, 0xdf]);
+        bytes.extend([0xff, 0xd9]);
+        bytes
+    }
+
+    #[test]
+    fn decodes_baseline_grayscale() {
+        let image = decode(&baseline_grayscale()).unwrap();
+        assert_eq!((image.width, image.height), (16, 8));
+        for (x, expected) in [(0, 192.0), (7, 192.0), (8, 64.0), (15, 64.0)] {
+            for y in [0, 7] {
+                let color = image.sample(x, y).unwrap();
+                assert!(
+                    (color.r * 255.0 - expected).abs() < 0.5,
+                    "{x} {y} {color:?}"
+                );
+                assert_eq!((color.r, color.r), (color.g, color.b));
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_dc_coefficients_out_of_range() {
+        // Two blocks with a DC difference of 1024 each, the second is past 11 bits
+        let mut bytes = baseline_grayscale();
+        let scan = bytes.len() - 7;
+        bytes.splice(scan..(scan + 5), [0x60, 0x01, 0x80, 0x0f]);
+        let err = decode(&bytes).unwrap_err();
+        assert_eq!(err.to_string(), "jpeg: DC coefficient 2048 out of range");
+    }
+
+    #[test]
+    fn rejects_frames_larger_than_the_data() {
+        // The frame height and width follow the quantization table
+        let frame = 2 + 69 + 5;
+        let mut bytes = baseline_grayscale();
+        bytes[frame..(frame + 4)].copy_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+        let err = decode(&bytes).unwrap_err();
+        assert!(err.to_string().contains("65535x65535"), "{err}");
+
+        let mut bytes = baseline_grayscale();
+        bytes.truncate(frame + 10);
+        let err = decode(&bytes).unwrap_err();
+        assert!(err.to_string().starts_with("jpeg: "), "{err}");
+    }
+
+    // 24x20 color gradient written by a separate encoder: 4:2:0 chroma, quality 90 tables and a
+    // restart marker after every 16x16 MCU, so the last row and column of MCUs are cropped
+    #[test]
+    fn decodes_subsampled_color_with_restarts() {
+        let image = decode(include_bytes!("../../resources/gradient.jpg")).unwrap();
+        assert_eq!((image.width, image.height), (24, 20));
+
+        // Luma changes much faster along x than y, a transposed block would be far off
+        let mut max_error = 0.0f32;
+        for y in 0..20 {
+            for x in 0..24 {
+                let color = image.sample(x, y).unwrap();
+                let expected = [
+                    20.0 + 8.0 * x as f32,
+                    30.0 + 7.0 * x as f32,
+                    60.0 + 4.0 * x as f32 + 6.0 * y as f32,
+                ];
+                for (value, expected) in [color.r, color.g, color.b].into_iter().zip(expected) {
+                    max_error = max_error.max((value * 255.0 - expected).abs());
+                }
+            }
+        }
+        assert!(max_error <= 12.0, "{max_error}");
+    }
+}
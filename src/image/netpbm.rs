@@ -0,0 +1,536 @@
+use std::{error::Error, fmt, io};
+
+use crate::color::Color;
+
+use super::Image;
+
+// Netpbm (PBM, PGM, PPM in plain and raw form) and PFM reading
+// https://netpbm.sourceforge.net/doc/pnm.html
+// https://netpbm.sourceforge.net/doc/pfm.html
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum NetpbmError {
+    UnknownMagic {
+        offset: usize,
+    },
+    UnexpectedEof {
+        offset: usize,
+        expected: &'static str,
+    },
+    InvalidToken {
+        offset: usize,
+        expected: &'static str,
+    },
+    ZeroSize {
+        offset: usize,
+    },
+    MaxvalOutOfRange {
+        offset: usize,
+        maxval: u32,
+    },
+    SampleOutOfRange {
+        offset: usize,
+        value: u32,
+        maxval: u32,
+    },
+    MissingSeparator {
+        offset: usize,
+    },
+}
+
+impl fmt::Display for NetpbmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetpbmError::UnknownMagic { offset } => write!(
+                f,
+                "netpbm: byte {offset}: unknown magic number, expected P1 to P6, PF or Pf"
+            ),
+            NetpbmError::UnexpectedEof { offset, expected } => {
+                write!(f, "netpbm: byte {offset}: file ends before {expected}")
+            }
+            NetpbmError::InvalidToken { offset, expected } => {
+                write!(f, "netpbm: byte {offset}: expected {expected}")
+            }
+            NetpbmError::ZeroSize { offset } => {
+                write!(f, "netpbm: byte {offset}: image has zero width or height")
+            }
+            NetpbmError::MaxvalOutOfRange { offset, maxval } => write!(
+                f,
+                "netpbm: byte {offset}: maxval {maxval} is outside 1 to 65535"
+            ),
+            NetpbmError::SampleOutOfRange {
+                offset,
+                value,
+                maxval,
+            } => write!(
+                f,
+                "netpbm: byte {offset}: sample {value} is larger than maxval {maxval}"
+            ),
+            NetpbmError::MissingSeparator { offset } => write!(
+                f,
+                "netpbm: byte {offset}: expected a single whitespace byte before the raster"
+            ),
+        }
+    }
+}
+
+impl Error for NetpbmError {}
+
+// Kept as the inner error so callers can downcast to the typed error
+impl From<NetpbmError> for io::Error {
+    fn from(error: NetpbmError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, error)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    PlainBitmap,
+    PlainGraymap,
+    PlainPixmap,
+    RawBitmap,
+    RawGraymap,
+    RawPixmap,
+    FloatGray,
+    FloatColor,
+}
+
+pub(super) fn decode(bytes: &[u8]) -> Result<Image, NetpbmError> {
+    let mut reader = Reader { bytes, pos: 0 };
+
+    let format = match bytes.get(0..2) {
+        Some(b"P1") => Format::PlainBitmap,
+        Some(b"P2") => Format::PlainGraymap,
+        Some(b"P3") => Format::PlainPixmap,
+        Some(b"P4") => Format::RawBitmap,
+        Some(b"P5") => Format::RawGraymap,
+        Some(b"P6") => Format::RawPixmap,
+        Some(b"Pf") => Format::FloatGray,
+        Some(b"PF") => Format::FloatColor,
+        _ => return Err(NetpbmError::UnknownMagic { offset: 0 }),
+    };
+    reader.pos = 2;
+
+    let size_offset = reader.pos;
+    let width = reader.unsigned("image width")? as usize;
+    let height = reader.unsigned("image height")? as usize;
+    if width == 0 || height == 0 {
+        return Err(NetpbmError::ZeroSize {
+            offset: size_offset,
+        });
+    }
+
+    // Every format needs at least a bit per pixel, this also stops huge allocations
+    if width
+        .checked_mul(height)
+        .is_none_or(|count| count / 8 > bytes.len())
+    {
+        return Err(NetpbmError::UnexpectedEof {
+            offset: bytes.len(),
+            expected: "the end of the raster",
+        });
+    }
+
+    let pixels = match format {
+        Format::PlainBitmap | Format::RawBitmap => {
+            if format == Format::RawBitmap {
+                reader.separator()?;
+            }
+            read_bitmap(&mut reader, width, height, format == Format::RawBitmap)?
+        }
+        Format::FloatGray | Format::FloatColor => {
+            let scale = reader.float("scale factor")?;
+            reader.separator()?;
+            read_float(
+                &mut reader,
+                width,
+                height,
+                format == Format::FloatColor,
+                scale < 0.0,
+            )?
+        }
+        _ => {
+            let maxval_offset = reader.pos;
+            let maxval = reader.unsigned("maxval")?;
+            if !(1..=65535).contains(&maxval) {
+                return Err(NetpbmError::MaxvalOutOfRange {
+                    offset: maxval_offset,
+                    maxval,
+                });
+            }
+
+            let raw = matches!(format, Format::RawGraymap | Format::RawPixmap);
+            if raw {
+                reader.separator()?;
+            }
+            let channels = if matches!(format, Format::PlainPixmap | Format::RawPixmap) {
+                3
+            } else {
+                1
+            };
+            read_samples(&mut reader, width, height, channels, maxval, raw)?
+        }
+    };
+
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+// 1 is black, raw rows are padded to whole bytes
+fn read_bitmap(
+    reader: &mut Reader,
+    width: usize,
+    height: usize,
+    raw: bool,
+) -> Result<Vec<Color>, NetpbmError> {
+    let mut pixels = Vec::with_capacity(width * height);
+    let row_bytes = width.div_ceil(8);
+
+    for _ in 0..height {
+        let row = if raw {
+            Some(reader.take(row_bytes, "the end of the raster")?)
+        } else {
+            None
+        };
+
+        for x in 0..width {
+            let bit = match row {
+                Some(row) => (row[x / 8] >> (7 - x % 8)) & 1,
+                None => reader.bit()?,
+            };
+            let value = if bit == 1 { 0.0 } else { 1.0 };
+            pixels.push(Color::new(value, value, value));
+        }
+    }
+
+    Ok(pixels)
+}
+
+// Gray or RGB samples scaled by maxval, two big endian bytes each for raw maxval above 255
+fn read_samples(
+    reader: &mut Reader,
+    width: usize,
+    height: usize,
+    channels: usize,
+    maxval: u32,
+    raw: bool,
+) -> Result<Vec<Color>, NetpbmError> {
+    let mut pixels = Vec::with_capacity(width * height);
+    let mut sample = [0.0f32; 3];
+
+    for _ in 0..(width * height) {
+        for value in sample.iter_mut().take(channels) {
+            if !raw {
+                reader.skip_whitespace();
+            }
+            let offset = reader.pos;
+            let raw_value = if !raw {
+                reader.unsigned("a sample")?
+            } else if maxval < 256 {
+                reader.take(1, "the end of the raster")?[0] as u32
+            } else {
+                let bytes = reader.take(2, "the end of the raster")?;
+                u16::from_be_bytes([bytes[0], bytes[1]]) as u32
+            };
+
+            if raw_value > maxval {
+                return Err(NetpbmError::SampleOutOfRange {
+                    offset,
+                    value: raw_value,
+                    maxval,
+                });
+            }
+            *value = raw_value as f32 / maxval as f32;
+        }
+
+        pixels.push(if channels == 1 {
+            Color::new(sample[0], sample[0], sample[0])
+        } else {
+            Color::new(sample[0], sample[1], sample[2])
+        });
+    }
+
+    Ok(pixels)
+}
+
+// Linear floats stored bottom row first, the sign of the scale gives the byte order
+fn read_float(
+    reader: &mut Reader,
+    width: usize,
+    height: usize,
+    color: bool,
+    little_endian: bool,
+) -> Result<Vec<Color>, NetpbmError> {
+    let channels = if color { 3 } else { 1 };
+    let mut pixels = vec![Color::new(0.0, 0.0, 0.0); width * height];
+
+    for row in (0..height).rev() {
+        for pixel in &mut pixels[(row * width)..((row + 1) * width)] {
+            let mut sample = [0.0f32; 3];
+            for value in sample.iter_mut().take(channels) {
+                let bytes: [u8; 4] = reader.take(4, "the end of the raster")?.try_into().unwrap();
+                *value = if little_endian {
+                    f32::from_le_bytes(bytes)
+                } else {
+                    f32::from_be_bytes(bytes)
+                };
+            }
+
+            *pixel = if color {
+                Color::new(sample[0], sample[1], sample[2])
+            } else {
+                Color::new(sample[0], sample[0], sample[0])
+            };
+        }
+    }
+
+    Ok(pixels)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    // Whitespace and comments running from '#' to the end of the line
+    fn skip_whitespace(&mut self) {
+        while let Some(&byte) = self.bytes.get(self.pos) {
+            if byte == b'#' {
+                while self
+                    .bytes
+                    .get(self.pos)
+                    .is_some_and(|&b| b != b'\n' && b != b'\r')
+                {
+                    self.pos += 1;
+                }
+            } else if byte.is_ascii_whitespace() {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn token(&mut self, expected: &'static str) -> Result<(usize, &'a [u8]), NetpbmError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        while self
+            .bytes
+            .get(self.pos)
+            .is_some_and(|b| !b.is_ascii_whitespace() && *b != b'#')
+        {
+            self.pos += 1;
+        }
+
+        if start == self.pos {
+            return Err(NetpbmError::UnexpectedEof {
+                offset: start,
+                expected,
+            });
+        }
+        Ok((start, &self.bytes[start..self.pos]))
+    }
+
+    fn unsigned(&mut self, expected: &'static str) -> Result<u32, NetpbmError> {
+        let (offset, token) = self.token(expected)?;
+        std::str::from_utf8(token)
+            .ok()
+            .filter(|token| token.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|token| token.parse::<u32>().ok())
+            .ok_or(NetpbmError::InvalidToken { offset, expected })
+    }
+
+    fn float(&mut self, expected: &'static str) -> Result<f32, NetpbmError> {
+        let (offset, token) = self.token(expected)?;
+        std::str::from_utf8(token)
+            .ok()
+            .and_then(|token| token.parse::<f32>().ok())
+            .filter(|value| value.is_finite() && *value != 0.0)
+            .ok_or(NetpbmError::InvalidToken { offset, expected })
+    }
+
+    // Plain PBM digits don't need whitespace between them
+    fn bit(&mut self) -> Result<u8, NetpbmError> {
+        self.skip_whitespace();
+        let offset = self.pos;
+        match self.bytes.get(offset) {
+            Some(b'0') => {
+                self.pos += 1;
+                Ok(0)
+            }
+            Some(b'1') => {
+                self.pos += 1;
+                Ok(1)
+            }
+            Some(_) => Err(NetpbmError::InvalidToken {
+                offset,
+                expected: "a 0 or 1 pixel",
+            }),
+            None => Err(NetpbmError::UnexpectedEof {
+                offset,
+                expected: "a pixel",
+            }),
+        }
+    }
+
+    // Raw rasters start after exactly one whitespace byte
+    fn separator(&mut self) -> Result<(), NetpbmError> {
+        match self.bytes.get(self.pos) {
+            Some(byte) if byte.is_ascii_whitespace() => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(_) => Err(NetpbmError::MissingSeparator { offset: self.pos }),
+            None => Err(NetpbmError::UnexpectedEof {
+                offset: self.pos,
+                expected: "the raster",
+            }),
+        }
+    }
+
+    fn take(&mut self, count: usize, expected: &'static str) -> Result<&'a [u8], NetpbmError> {
+        let bytes =
+            self.bytes
+                .get(self.pos..(self.pos + count))
+                .ok_or(NetpbmError::UnexpectedEof {
+                    offset: self.bytes.len(),
+                    expected,
+                })?;
+        self.pos += count;
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NetpbmError, decode};
+    use crate::image::Image;
+
+    fn values(image: &Image) -> Vec<[f32; 3]> {
+        image
+            .pixels
+            .iter()
+            .map(|color| [color.r, color.g, color.b])
+            .collect()
+    }
+
+    fn gray(values: &[f32]) -> Vec<[f32; 3]> {
+        values.iter().map(|&v| [v, v, v]).collect()
+    }
+
+    #[test]
+    fn header_comments_and_whitespace() {
+        let bytes = b"P2# magic\n# whole line\n 2\t#width\n1\r\n4 # maxval\n0 4\n";
+        let image = decode(bytes).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(values(&image), gray(&[0.0, 1.0]));
+
+        // Raw rasters start right after one whitespace byte, even if it looks like a comment
+        let bytes = b"P5 # size next\n2 1 255\n#\x80";
+        let image = decode(bytes).unwrap();
+        assert_eq!(values(&image), gray(&[35.0 / 255.0, 128.0 / 255.0]));
+    }
+
+    #[test]
+    fn sixteen_bit_maxval() {
+        let mut bytes = b"P6 1 1 65535\n".to_vec();
+        bytes.extend([0xff, 0xff, 0x80, 0x00, 0x00, 0x01]);
+        let image = decode(&bytes).unwrap();
+        assert_eq!(values(&image), [[1.0, 32768.0 / 65535.0, 1.0 / 65535.0]]);
+
+        let image = decode(b"P3 1 1 1000\n1000 500 0\n").unwrap();
+        assert_eq!(values(&image), [[1.0, 0.5, 0.0]]);
+
+        assert_eq!(
+            decode(b"P2 1 1 65536\n0\n").unwrap_err(),
+            NetpbmError::MaxvalOutOfRange {
+                offset: 6,
+                maxval: 65536
+            }
+        );
+        assert_eq!(
+            decode(b"P2 1 1 7\n8\n").unwrap_err(),
+            NetpbmError::SampleOutOfRange {
+                offset: 9,
+                value: 8,
+                maxval: 7
+            }
+        );
+    }
+
+    #[test]
+    fn bitmaps_are_packed_and_inverted() {
+        // 1 is black, plain digits need no whitespace between them
+        let image = decode(b"P1 3 2\n101\n0 1 0\n").unwrap();
+        assert_eq!(values(&image), gray(&[0.0, 1.0, 0.0, 1.0, 0.0, 1.0]));
+
+        // Rows of 10 pixels take two bytes, the padding bits are ignored
+        let image = decode(b"P4 10 2\n\xa5\x7f\x0f\xc0").unwrap();
+        let expected = [
+            [1, 0, 1, 0, 0, 1, 0, 1, 0, 1],
+            [0, 0, 0, 0, 1, 1, 1, 1, 1, 1],
+        ];
+        let expected = expected
+            .concat()
+            .iter()
+            .map(|&bit| 1.0 - bit as f32)
+            .collect::<Vec<_>>();
+        assert_eq!(values(&image), gray(&expected));
+    }
+
+    #[test]
+    fn pfm_rows_run_bottom_up() {
+        let rows = [[1.0f32, 2.0], [3.0, 4.0]];
+        // Negative scales are little endian, positive ones big endian
+        for (scale, to_bytes) in [
+            ("-1.0", f32::to_le_bytes as fn(f32) -> [u8; 4]),
+            ("1.0", f32::to_be_bytes),
+        ] {
+            let mut bytes = format!("Pf\n2 2\n{scale}\n").into_bytes();
+            for value in rows.concat() {
+                bytes.extend(to_bytes(value));
+            }
+            let image = decode(&bytes).unwrap();
+            // The first row in the file is the bottom of the image
+            assert_eq!(values(&image), gray(&[3.0, 4.0, 1.0, 2.0]), "{scale}");
+        }
+
+        let mut bytes = b"PF 1 1 -2.5\n".to_vec();
+        for value in [0.25f32, 0.5, 8.0] {
+            bytes.extend(value.to_le_bytes());
+        }
+        assert_eq!(values(&decode(&bytes).unwrap()), [[0.25, 0.5, 8.0]]);
+    }
+
+    #[test]
+    fn truncated_data_is_an_error() {
+        for (bytes, expected) in [
+            (
+                &b"P6 2 1 255\n\x01\x02\x03\x04"[..],
+                "the end of the raster",
+            ),
+            (b"P5 1 1 65535\n\x01", "the end of the raster"),
+            (b"P3 1 1 255\n1 2", "a sample"),
+            (b"P1 2 2\n1 0 1", "a pixel"),
+            (b"PF 1 1 -1.0\n\x00\x00\x00\x00", "the end of the raster"),
+            (b"P2 4", "image height"),
+        ] {
+            match decode(bytes) {
+                Err(NetpbmError::UnexpectedEof {
+                    expected: found, ..
+                }) => assert_eq!(found, expected),
+                other => panic!("{other:?} for {bytes:?}"),
+            }
+        }
+
+        // Sizes far larger than the file are rejected before allocating
+        assert!(matches!(
+            decode(b"P4 100000 100000\n\x00"),
+            Err(NetpbmError::UnexpectedEof { .. })
+        ));
+    }
+}
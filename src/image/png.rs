@@ -0,0 +1,710 @@
+use std::io::{self, BufWriter, ErrorKind, Write};
+
+use crate::color::Color;
+
+use super::{Image, zlib};
+
+// PNG encoding and decoding
+// https://www.w3.org/TR/png-3/
+
+pub(super) const MAGIC: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum PngBitDepth {
+    Eight,
+    Sixteen,
+}
+
+// Adam7 passes as (x start, y start, x step, y step)
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+// Every color type and bit depth, alpha is dropped since images are RGB only
+pub(super) fn decode(bytes: &[u8]) -> Result<Image, io::Error> {
+    if !bytes.starts_with(&MAGIC) {
+        return Err(error("missing PNG signature"));
+    }
+
+    let mut pos = MAGIC.len();
+    let mut header = None;
+    let mut palette = Vec::new();
+    let mut compressed = Vec::new();
+    let mut ended = false;
+
+    while pos < bytes.len() {
+        let length = read_u32(bytes, pos)? as usize;
+        let kind: [u8; 4] = bytes
+            .get((pos + 4)..(pos + 8))
+            .ok_or_else(|| error("truncated chunk header"))?
+            .try_into()
+            .unwrap();
+        let data = bytes
+            .get((pos + 8)..(pos + 8 + length))
+            .ok_or_else(|| error("truncated chunk data"))?;
+        let crc = read_u32(bytes, pos + 8 + length)?;
+
+        let kind_name = String::from_utf8_lossy(&kind).into_owned();
+        if crc32_update(crc32_update(0xffff_ffff, &kind), data) ^ 0xffff_ffff != crc {
+            return Err(error(&format!("CRC mismatch in {kind_name} chunk")));
+        }
+        pos += 12 + length;
+
+        match &kind {
+            b"IHDR" => header = Some(Header::parse(data)?),
+            b"PLTE" => {
+                if data.len() % 3 != 0 || data.len() > 256 * 3 {
+                    return Err(error("invalid palette size"));
+                }
+                palette = data.chunks(3).map(|rgb| [rgb[0], rgb[1], rgb[2]]).collect();
+            }
+            b"IDAT" => compressed.extend_from_slice(data),
+            b"IEND" => {
+                ended = true;
+                break;
+            }
+            _ => {
+                // Bit 5 of the first byte marks ancillary chunks, anything else must be understood
+                if kind[0] & 0x20 == 0 {
+                    return Err(error(&format!("unknown critical chunk {kind_name}")));
+                }
+            }
+        }
+    }
+
+    if !ended {
+        return Err(error("missing IEND chunk"));
+    }
+    let header = header.ok_or_else(|| error("missing IHDR chunk"))?;
+    if header.color_type == 3 && palette.is_empty() {
+        return Err(error("palette image without PLTE chunk"));
+    }
+
+    let data = zlib::decompress(&compressed)?;
+
+    // The header size is only trusted once the image data is known to match it
+    let expected = header
+        .data_size()
+        .ok_or_else(|| error("image size overflows"))?;
+    if data.len() != expected {
+        return Err(error(&format!(
+            "expected {expected} bytes of image data, found {}",
+            data.len()
+        )));
+    }
+
+    let mut pixels = vec![Color::new(0.0, 0.0, 0.0); header.width * header.height];
+    let mut offset = 0;
+
+    for (x0, y0, dx, dy, pass_width, pass_height) in header.passes() {
+        let stride = (pass_width * header.bits_per_pixel()).div_ceil(8);
+        let size = (stride + 1) * pass_height;
+        let pass = &data[offset..(offset + size)];
+        offset += size;
+
+        let rows = unfilter(pass, stride, header.bytes_per_pixel(), pass_height)?;
+
+        for (row_idx, row) in rows.chunks(stride).enumerate() {
+            let y = y0 + row_idx * dy;
+            for col in 0..pass_width {
+                let x = x0 + col * dx;
+                pixels[y * header.width + x] = header.pixel(row, col, &palette)?;
+            }
+        }
+    }
+
+    Ok(Image {
+        width: header.width,
+        height: header.height,
+        pixels,
+    })
+}
+
+struct Header {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: u8,
+    interlaced: bool,
+}
+
+impl Header {
+    fn parse(data: &[u8]) -> Result<Self, io::Error> {
+        if data.len() != 13 {
+            return Err(error("IHDR chunk has the wrong size"));
+        }
+
+        let width = read_u32(data, 0)? as usize;
+        let height = read_u32(data, 4)? as usize;
+        let bit_depth = data[8];
+        let color_type = data[9];
+
+        if width == 0 || height == 0 {
+            return Err(error("image has zero size"));
+        }
+
+        let valid_depth = match color_type {
+            0 => matches!(bit_depth, 1 | 2 | 4 | 8 | 16),
+            3 => matches!(bit_depth, 1 | 2 | 4 | 8),
+            2 | 4 | 6 => matches!(bit_depth, 8 | 16),
+            _ => return Err(error(&format!("invalid color type {color_type}"))),
+        };
+        if !valid_depth {
+            return Err(error(&format!(
+                "bit depth {bit_depth} not allowed for color type {color_type}"
+            )));
+        }
+        if data[10] != 0 || data[11] != 0 {
+            return Err(error("unknown compression or filter method"));
+        }
+
+        let interlaced = match data[12] {
+            0 => false,
+            1 => true,
+            method => return Err(error(&format!("unknown interlace method {method}"))),
+        };
+
+        Ok(Header {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            interlaced,
+        })
+    }
+
+    // Non-empty passes as (x start, y start, x step, y step, width, height)
+    fn passes(&self) -> impl Iterator<Item = (usize, usize, usize, usize, usize, usize)> + '_ {
+        let passes: &[(usize, usize, usize, usize)] = if self.interlaced {
+            &ADAM7
+        } else {
+            &[(0, 0, 1, 1)]
+        };
+
+        passes
+            .iter()
+            .map(|&(x0, y0, dx, dy)| {
+                let pass_width = self.width.saturating_sub(x0).div_ceil(dx);
+                let pass_height = self.height.saturating_sub(y0).div_ceil(dy);
+                (x0, y0, dx, dy, pass_width, pass_height)
+            })
+            .filter(|&(.., pass_width, pass_height)| pass_width != 0 && pass_height != 0)
+    }
+
+    // Bytes of filtered scanlines in all passes, None if the size does not fit in memory
+    fn data_size(&self) -> Option<usize> {
+        self.width.checked_mul(self.height)?;
+        self.passes()
+            .try_fold(0usize, |total, (.., pass_width, pass_height)| {
+                let stride = pass_width.checked_mul(self.bits_per_pixel())?.div_ceil(8);
+                total.checked_add(stride.checked_add(1)?.checked_mul(pass_height)?)
+            })
+    }
+
+    fn channels(&self) -> usize {
+        match self.color_type {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1,
+        }
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.channels() * self.bit_depth as usize
+    }
+
+    // Filters work on whole bytes, sub byte depths use 1
+    fn bytes_per_pixel(&self) -> usize {
+        self.bits_per_pixel().div_ceil(8)
+    }
+
+    fn sample(&self, row: &[u8], idx: usize) -> u16 {
+        match self.bit_depth {
+            16 => u16::from_be_bytes([row[idx * 2], row[idx * 2 + 1]]),
+            8 => row[idx] as u16,
+            depth => {
+                // Packed samples, most significant bits first
+                let depth = depth as usize;
+                let bit = idx * depth;
+                let shift = 8 - depth - bit % 8;
+                ((row[bit / 8] >> shift) & ((1 << depth) - 1) as u8) as u16
+            }
+        }
+    }
+
+    fn pixel(&self, row: &[u8], col: usize, palette: &[[u8; 3]]) -> Result<Color, io::Error> {
+        let max = ((1u32 << self.bit_depth) - 1) as f32;
+        let channels = self.channels();
+        let value = |channel: usize| self.sample(row, col * channels + channel) as f32 / max;
+
+        Ok(match self.color_type {
+            0 | 4 => {
+                let gray = value(0);
+                Color::new(gray, gray, gray)
+            }
+            3 => {
+                let idx = self.sample(row, col) as usize;
+                let [r, g, b] = palette
+                    .get(idx)
+                    .ok_or_else(|| error(&format!("palette index {idx} out of range")))?;
+                Color::new(*r as f32 / 255.0, *g as f32 / 255.0, *b as f32 / 255.0)
+            }
+            _ => Color::new(value(0), value(1), value(2)),
+        })
+    }
+}
+
+fn unfilter(data: &[u8], stride: usize, bpp: usize, rows: usize) -> Result<Vec<u8>, io::Error> {
+    let mut out = vec![0u8; stride * rows];
+    let previous_row = vec![0u8; stride];
+
+    for row in 0..rows {
+        let filter = data[row * (stride + 1)];
+        let line = &data[(row * (stride + 1) + 1)..((row + 1) * (stride + 1))];
+
+        let (done, current) = out.split_at_mut(row * stride);
+        let previous = if row == 0 {
+            &previous_row[..]
+        } else {
+            &done[((row - 1) * stride)..]
+        };
+        let current = &mut current[..stride];
+
+        for idx in 0..stride {
+            let left = if idx >= bpp { current[idx - bpp] } else { 0 };
+            let up = previous[idx];
+            let up_left = if idx >= bpp { previous[idx - bpp] } else { 0 };
+
+            let predicted = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return Err(error(&format!("invalid filter type {filter}"))),
+            };
+            current[idx] = line[idx].wrapping_add(predicted);
+        }
+    }
+
+    Ok(out)
+}
+
+fn read_u32(bytes: &[u8], pos: usize) -> Result<u32, io::Error> {
+    bytes
+        .get(pos..(pos + 4))
+        .map(|b| u32::from_be_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| error("unexpected end of file"))
+}
+
+fn error(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, format!("png: {message}"))
+}
+
+// Gamma corrected like the PPM output, alpha is written as is
+pub(crate) fn write(
+    path: &str,
+    width: u32,
+    height: u32,
+    pixels: &[Color],
+    alpha: Option<&[f32]>,
+    bit_depth: PngBitDepth,
+) -> io::Result<()> {
+    let (depth, bytes_per_sample) = match bit_depth {
+        PngBitDepth::Eight => (8u8, 1),
+        PngBitDepth::Sixteen => (16u8, 2),
+    };
+    // Color type 2 is RGB, 6 is RGBA
+    let (color_type, samples) = if alpha.is_some() { (6u8, 4) } else { (2u8, 3) };
+    let bytes_per_pixel = bytes_per_sample * samples;
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // Bit depth, color type, compression, filter and interlace method
+    header.extend_from_slice(&[depth, color_type, 0, 0, 0]);
+
+    let width = width as usize;
+    let stride = width * bytes_per_pixel;
+    let mut previous = vec![0u8; stride];
+    let mut row = vec![0u8; stride];
+    let mut filtered = Vec::with_capacity((stride + 1) * height as usize);
+
+    for i in 0..height as usize {
+        row.clear();
+        for j in 0..width {
+            let idx = i * width + j;
+            let pixel = &pixels[idx];
+            let a = alpha.map(|alpha| alpha[idx].clamp(0.0, 1.0));
+
+            match bit_depth {
+                PngBitDepth::Eight => {
+                    row.extend_from_slice(&pixel.bytes());
+                    if let Some(a) = a {
+                        row.push((a * 255.0).round() as u8);
+                    }
+                }
+                PngBitDepth::Sixteen => {
+                    for word in pixel.words() {
+                        row.extend_from_slice(&word.to_be_bytes());
+                    }
+                    if let Some(a) = a {
+                        row.extend_from_slice(&((a * 65535.0).round() as u16).to_be_bytes());
+                    }
+                }
+            }
+        }
+
+        filter_row(&row, &previous, bytes_per_pixel, &mut filtered);
+        std::mem::swap(&mut row, &mut previous);
+    }
+
+    let mut out = BufWriter::new(std::fs::File::create(path)?);
+    out.write_all(&MAGIC)?;
+    write_chunk(&mut out, b"IHDR", &header)?;
+    write_chunk(&mut out, b"IDAT", &zlib::compress(&filtered))?;
+    write_chunk(&mut out, b"IEND", &[])?;
+
+    out.flush()?;
+    Ok(())
+}
+
+// Picks the filter with the smallest sum of absolute differences for each row
+fn filter_row(row: &[u8], previous: &[u8], bpp: usize, out: &mut Vec<u8>) {
+    let mut best = Vec::new();
+    let mut best_score = u64::MAX;
+    let mut candidate = Vec::with_capacity(row.len() + 1);
+
+    for filter in 0..5u8 {
+        candidate.clear();
+        candidate.push(filter);
+
+        for (idx, &byte) in row.iter().enumerate() {
+            let left = if idx >= bpp { row[idx - bpp] } else { 0 };
+            let up = previous[idx];
+            let up_left = if idx >= bpp { previous[idx - bpp] } else { 0 };
+
+            let predicted = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                _ => paeth(left, up, up_left),
+            };
+            candidate.push(byte.wrapping_sub(predicted));
+        }
+
+        let score = candidate[1..]
+            .iter()
+            .map(|&b| (b as i8).unsigned_abs() as u64)
+            .sum();
+        if score < best_score {
+            best_score = score;
+            std::mem::swap(&mut best, &mut candidate);
+        }
+    }
+
+    out.extend_from_slice(&best);
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+fn write_chunk<W: Write>(out: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+
+    let crc = crc32_update(crc32_update(0xffff_ffff, kind), data) ^ 0xffff_ffff;
+    out.write_all(&crc.to_be_bytes())
+}
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xedb8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+};
+
+fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc = CRC_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MAGIC, PngBitDepth, decode, unfilter, write_chunk};
+    use crate::{
+        color::Color,
+        image::{Image, ImageFormat, zlib},
+    };
+
+    // Bit depth, color type and interlace method
+    type Format = (u8, u8, u8);
+    const RGB: Format = (8, 2, 0);
+
+    // File with the given header around already filtered scanlines, palette images get a PLTE
+    fn file(
+        width: u32,
+        height: u32,
+        (bit_depth, color_type, interlace): Format,
+        palette: &[[u8; 3]],
+        scanlines: &[u8],
+    ) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[bit_depth, color_type, 0, 0, interlace]);
+
+        let mut bytes = MAGIC.to_vec();
+        write_chunk(&mut bytes, b"IHDR", &header).unwrap();
+        if !palette.is_empty() {
+            write_chunk(&mut bytes, b"PLTE", palette.as_flattened()).unwrap();
+        }
+        write_chunk(&mut bytes, b"IDAT", &zlib::compress(scanlines)).unwrap();
+        write_chunk(&mut bytes, b"IEND", &[]).unwrap();
+        bytes
+    }
+
+    fn values(image: &Image) -> Vec<[f32; 3]> {
+        image
+            .pixels
+            .iter()
+            .map(|color| [color.r, color.g, color.b])
+            .collect()
+    }
+
+    fn gray(values: &[u16], max: f32) -> Vec<[f32; 3]> {
+        values.iter().map(|&v| [v as f32 / max; 3]).collect()
+    }
+
+    // Packed samples run from the most significant bit, rows start on a byte boundary
+    #[test]
+    fn packed_gray_samples() {
+        // Ten pixels span two bytes, the second row is Sub filtered a whole byte at a time
+        let scanlines = [0, 0b1011_0010, 0b1100_0000, 1, 0x55, 0x80 - 0x55];
+        let image = decode(&file(10, 2, (1, 0, 0), &[], &scanlines)).unwrap();
+        assert_eq!(
+            values(&image),
+            gray(
+                &[1, 0, 1, 1, 0, 0, 1, 0, 1, 1, 0, 1, 0, 1, 0, 1, 0, 1, 1, 0],
+                1.0
+            )
+        );
+
+        let image = decode(&file(5, 1, (2, 0, 0), &[], &[0, 0b0001_1011, 0b1000_0000])).unwrap();
+        assert_eq!(values(&image), gray(&[0, 1, 2, 3, 2], 3.0));
+
+        // Up filter adds the row above
+        let scanlines = [0, 0x0f, 0x70, 2, 0x11, 0x10];
+        let image = decode(&file(3, 2, (4, 0, 0), &[], &scanlines)).unwrap();
+        assert_eq!(values(&image), gray(&[0, 15, 7, 2, 0, 8], 15.0));
+
+        let image = decode(&file(2, 1, (16, 0, 0), &[], &[0, 0x80, 0x00, 0x00, 0x01])).unwrap();
+        assert_eq!(values(&image), gray(&[0x8000, 1], 65535.0));
+    }
+
+    #[test]
+    fn palette_indices() {
+        let palette = [[255, 0, 0], [0, 255, 0], [0, 0, 255]];
+        let image = decode(&file(3, 1, (2, 3, 0), &palette, &[0, 0b1000_0100])).unwrap();
+        assert_eq!(
+            values(&image),
+            [[0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]
+        );
+
+        let image = decode(&file(2, 1, (8, 3, 0), &palette, &[0, 1, 0])).unwrap();
+        assert_eq!(values(&image), [[0.0, 1.0, 0.0], [1.0, 0.0, 0.0]]);
+
+        let err = decode(&file(1, 1, (2, 3, 0), &palette, &[0, 0b1100_0000])).unwrap_err();
+        assert_eq!(err.to_string(), "png: palette index 3 out of range");
+        let err = decode(&file(1, 1, (8, 3, 0), &[], &[0, 0])).unwrap_err();
+        assert_eq!(err.to_string(), "png: palette image without PLTE chunk");
+    }
+
+    // Alpha is dropped after unfiltering with the full pixel size
+    #[test]
+    fn alpha_channels() {
+        let image = decode(&file(2, 1, (8, 4, 0), &[], &[0, 128, 255, 64, 0])).unwrap();
+        assert_eq!(values(&image), gray(&[128, 64], 255.0));
+
+        let image = decode(&file(1, 1, (16, 4, 0), &[], &[0, 0x12, 0x34, 0xff, 0xff])).unwrap();
+        assert_eq!(values(&image), gray(&[0x1234], 65535.0));
+
+        // The second row averages the pixel to its left and the one above
+        #[rustfmt::skip]
+        let scanlines = [
+            0, 10, 20, 30, 255, 40, 50, 60, 255,
+            3, 5, 10, 15, 0, 25 - 25, 35 - 35, 45 - 45, 0,
+        ];
+        let image = decode(&file(2, 2, (8, 6, 0), &[], &scanlines)).unwrap();
+        let rgb = |r: f32, g: f32, b: f32| [r / 255.0, g / 255.0, b / 255.0];
+        assert_eq!(
+            values(&image),
+            [
+                rgb(10.0, 20.0, 30.0),
+                rgb(40.0, 50.0, 60.0),
+                rgb(10.0, 20.0, 30.0),
+                rgb(25.0, 35.0, 45.0),
+            ]
+        );
+    }
+
+    // Seven passes, of which a 3x3 image only fills five
+    #[test]
+    fn adam7_interlacing() {
+        #[rustfmt::skip]
+        let scanlines = [
+            0, 10,
+            0, 30,
+            0, 70, 90,
+            0, 20, 0, 80,
+            0, 40, 50, 60,
+        ];
+        let image = decode(&file(3, 3, (8, 0, 1), &[], &scanlines)).unwrap();
+        assert_eq!(
+            values(&image),
+            gray(&[10, 20, 30, 40, 50, 60, 70, 80, 90], 255.0)
+        );
+
+        // Packed passes round each row up to whole bytes
+        #[rustfmt::skip]
+        let scanlines = [
+            0, 0b1000_0000,
+            0, 0b1000_0000,
+            0, 0b1100_0000,
+            0, 0b0000_0000, 0, 0b1000_0000,
+            0, 0b0100_0000,
+        ];
+        let image = decode(&file(3, 3, (1, 0, 1), &[], &scanlines)).unwrap();
+        assert_eq!(values(&image), gray(&[1, 0, 1, 0, 1, 0, 1, 1, 1], 1.0));
+
+        // Interlaced data is exactly the sum of the passes
+        let err = decode(&file(3, 3, (8, 0, 1), &[], &[0; 14])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "png: expected 15 bytes of image data, found 14"
+        );
+    }
+
+    #[test]
+    fn rejects_sizes_that_disagree_with_the_data() {
+        // Two rows of two pixels, each row behind a filter byte
+        let scanlines = [0, 1, 2, 3, 4, 5, 6, 0, 7, 8, 9, 10, 11, 12];
+        let image = decode(&file(2, 2, RGB, &[], &scanlines)).unwrap();
+        assert_eq!((image.width, image.height), (2, 2));
+
+        let truncated = decode(&file(2, 2, RGB, &[], &scanlines[..10])).unwrap_err();
+        assert!(truncated.to_string().contains("found 10"), "{truncated}");
+
+        // Far more pixels than could be allocated, rejected before any allocation
+        let oversized = decode(&file(u32::MAX, u32::MAX, RGB, &[], &scanlines)).unwrap_err();
+        assert!(oversized.to_string().starts_with("png: "), "{oversized}");
+        let oversized = decode(&file(1 << 20, 1 << 20, RGB, &[], &scanlines)).unwrap_err();
+        assert!(oversized.to_string().contains("found 14"), "{oversized}");
+    }
+
+    #[test]
+    fn round_trips_through_a_file() {
+        let (width, height) = (7, 5);
+        let pixels = (0..width * height)
+            .map(|idx| {
+                let (x, y) = ((idx % width) as f32, (idx / width) as f32);
+                Color::new(x / width as f32, y / height as f32, 0.5)
+            })
+            .collect::<Vec<_>>();
+        let alpha = (0..width * height)
+            .map(|idx| (idx % 4) as f32 / 3.0)
+            .collect::<Vec<_>>();
+
+        let path = std::env::temp_dir().join(format!("weekend-rt-{}.png", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        Image::write_as(&path, ImageFormat::Png, width, height, &pixels).unwrap();
+        let image = Image::load(&path).unwrap();
+        assert_eq!(
+            (image.width, image.height),
+            (width as usize, height as usize)
+        );
+
+        for (depth, alpha) in [
+            (PngBitDepth::Eight, None),
+            (PngBitDepth::Sixteen, None),
+            (PngBitDepth::Eight, Some(alpha.as_slice())),
+            (PngBitDepth::Sixteen, Some(alpha.as_slice())),
+        ] {
+            Image::write_png(&path, width, height, &pixels, alpha, depth).unwrap();
+            let bytes = std::fs::read(&path).unwrap();
+            let image = Image::load(&path).unwrap();
+
+            // Pixels come back gamma corrected, as the bytes or words that were written
+            for (idx, pixel) in pixels.iter().enumerate() {
+                let color = image
+                    .sample(idx % width as usize, idx / width as usize)
+                    .unwrap();
+                let expected = match depth {
+                    PngBitDepth::Eight => pixel.bytes().map(|byte| byte as f32 / 255.0),
+                    PngBitDepth::Sixteen => pixel.words().map(|word| word as f32 / 65535.0),
+                };
+                assert_eq!([color.r, color.g, color.b], expected, "{depth:?} {idx}");
+            }
+
+            // decode drops alpha, so read it from the scanlines of the single IDAT chunk after
+            // IHDR
+            assert_eq!(bytes[25], if alpha.is_some() { 6 } else { 2 });
+            let Some(alpha) = alpha else {
+                continue;
+            };
+            let length = u32::from_be_bytes(bytes[33..37].try_into().unwrap()) as usize;
+            assert_eq!(&bytes[37..41], b"IDAT");
+            let (bpp, max) = match depth {
+                PngBitDepth::Eight => (4, 255.0),
+                PngBitDepth::Sixteen => (8, 65535.0),
+            };
+            let data = zlib::decompress(&bytes[41..41 + length]).unwrap();
+            let stride = width as usize * bpp;
+            let samples = unfilter(&data, stride, bpp, height as usize).unwrap();
+            for (idx, pixel) in samples.chunks(bpp).enumerate() {
+                let a = match depth {
+                    PngBitDepth::Eight => pixel[3] as f32,
+                    PngBitDepth::Sixteen => u16::from_be_bytes([pixel[6], pixel[7]]) as f32,
+                };
+                assert_eq!(a, (alpha[idx] * max).round(), "{depth:?} {idx}");
+            }
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
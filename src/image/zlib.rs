@@ -0,0 +1,746 @@
+// zlib (RFC 1950) stream around a deflate (RFC 1951) compressor and decompressor
+
+use std::io::{self, ErrorKind};
+
+const WINDOW_SIZE: usize = 1 << 15;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const MAX_CHAIN: usize = 128;
+const HASH_BITS: usize = 15;
+
+// Symbols per block before the Huffman tables are rebuilt
+const BLOCK_SYMBOLS: usize = 1 << 16;
+
+const END_OF_BLOCK: usize = 256;
+
+// Base values and extra bits for length codes 257..285 and distance codes 0..29
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+// Order in which code length code lengths are stored
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+pub(crate) fn compress(data: &[u8]) -> Vec<u8> {
+    // 32K window, default compression level
+    let mut out = vec![0x78, 0x9c];
+    out.append(&mut deflate(data));
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+pub(crate) fn decompress(data: &[u8]) -> Result<Vec<u8>, io::Error> {
+    let [cmf, flg, ..] = *data else {
+        return Err(error("stream too short"));
+    };
+    if cmf & 0x0f != 8 || cmf >> 4 > 7 {
+        return Err(error("unsupported compression method"));
+    }
+    if !(((cmf as u16) << 8) | flg as u16).is_multiple_of(31) {
+        return Err(error("corrupt header check bits"));
+    }
+    if flg & 0x20 != 0 {
+        return Err(error("preset dictionaries are not supported"));
+    }
+
+    let mut reader = BitReader::new(&data[2..]);
+    let out = inflate(&mut reader)?;
+
+    let checksum_start = 2 + reader.byte_position();
+    let checksum = data
+        .get(checksum_start..(checksum_start + 4))
+        .ok_or_else(|| error("missing adler32 checksum"))?;
+    if u32::from_be_bytes(checksum.try_into().unwrap()) != adler32(&out) {
+        return Err(error("adler32 checksum mismatch"));
+    }
+
+    Ok(out)
+}
+
+pub(crate) fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let mut a = 1u32;
+    let mut b = 0u32;
+
+    // 5552 is the largest chunk that can't overflow before the modulo
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+
+    (b << 16) | a
+}
+
+#[derive(Clone, Copy)]
+enum Symbol {
+    Literal(u8),
+    Match { length: u16, distance: u16 },
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let symbols = lz77(data);
+
+    let mut writer = BitWriter::new();
+
+    if symbols.is_empty() {
+        // Final block with fixed codes containing only the end of block symbol (code 0000000)
+        writer.write_bits(1, 1);
+        writer.write_bits(1, 2);
+        writer.write_bits(0, 7);
+    }
+
+    let block_count = symbols.len().div_ceil(BLOCK_SYMBOLS);
+    for (idx, block) in symbols.chunks(BLOCK_SYMBOLS).enumerate() {
+        write_dynamic_block(&mut writer, block, idx + 1 == block_count);
+    }
+
+    writer.finish()
+}
+
+// Greedy matching over hash chains of 3 byte prefixes
+fn lz77(data: &[u8]) -> Vec<Symbol> {
+    let hash_size = 1 << HASH_BITS;
+    let mut head = vec![usize::MAX; hash_size];
+    let mut prev = vec![usize::MAX; WINDOW_SIZE];
+
+    let hash = |pos: usize| {
+        let value =
+            (data[pos] as usize) << 16 | (data[pos + 1] as usize) << 8 | data[pos + 2] as usize;
+        (value.wrapping_mul(2654435761) >> 8) & (hash_size - 1)
+    };
+
+    let insert = |pos: usize, head: &mut Vec<usize>, prev: &mut Vec<usize>| {
+        if pos + MIN_MATCH <= data.len() {
+            let h = hash(pos);
+            prev[pos % WINDOW_SIZE] = head[h];
+            head[h] = pos;
+        }
+    };
+
+    let mut symbols = Vec::with_capacity(data.len() / 2);
+    let mut pos = 0;
+
+    while pos < data.len() {
+        let mut best_length = 0;
+        let mut best_distance = 0;
+
+        if pos + MIN_MATCH <= data.len() {
+            let max_length = MAX_MATCH.min(data.len() - pos);
+            let mut candidate = head[hash(pos)];
+            let mut chain = 0;
+
+            while candidate != usize::MAX && pos - candidate < WINDOW_SIZE && chain < MAX_CHAIN {
+                let length = data[candidate..]
+                    .iter()
+                    .zip(&data[pos..(pos + max_length)])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+
+                if length > best_length {
+                    best_length = length;
+                    best_distance = pos - candidate;
+                    if length == max_length {
+                        break;
+                    }
+                }
+
+                let next = prev[candidate % WINDOW_SIZE];
+                // Entries older than the window may have been overwritten
+                if next == usize::MAX || next >= candidate {
+                    break;
+                }
+                candidate = next;
+                chain += 1;
+            }
+        }
+
+        if best_length >= MIN_MATCH {
+            symbols.push(Symbol::Match {
+                length: best_length as u16,
+                distance: best_distance as u16,
+            });
+            for idx in pos..(pos + best_length) {
+                insert(idx, &mut head, &mut prev);
+            }
+            pos += best_length;
+        } else {
+            symbols.push(Symbol::Literal(data[pos]));
+            insert(pos, &mut head, &mut prev);
+            pos += 1;
+        }
+    }
+
+    symbols
+}
+
+fn length_code(length: u16) -> (usize, u16, u8) {
+    let idx = LENGTH_BASE.partition_point(|&base| base <= length) - 1;
+    (257 + idx, length - LENGTH_BASE[idx], LENGTH_EXTRA[idx])
+}
+
+fn distance_code(distance: u16) -> (usize, u16, u8) {
+    let idx = DISTANCE_BASE.partition_point(|&base| base <= distance) - 1;
+    (idx, distance - DISTANCE_BASE[idx], DISTANCE_EXTRA[idx])
+}
+
+fn write_dynamic_block(writer: &mut BitWriter, symbols: &[Symbol], last: bool) {
+    let mut literal_freq = [0u32; 286];
+    let mut distance_freq = [0u32; 30];
+
+    for symbol in symbols {
+        match *symbol {
+            Symbol::Literal(byte) => literal_freq[byte as usize] += 1,
+            Symbol::Match { length, distance } => {
+                literal_freq[length_code(length).0] += 1;
+                distance_freq[distance_code(distance).0] += 1;
+            }
+        }
+    }
+    literal_freq[END_OF_BLOCK] += 1;
+
+    let literal_lengths = huffman_lengths(&literal_freq, 15);
+    let distance_lengths = huffman_lengths(&distance_freq, 15);
+
+    let literal_count = 257.max(last_nonzero(&literal_lengths));
+    let distance_count = 1.max(last_nonzero(&distance_lengths));
+
+    // Code lengths of both trees are run length encoded together
+    let mut all_lengths = literal_lengths[..literal_count].to_vec();
+    all_lengths.extend_from_slice(&distance_lengths[..distance_count]);
+    let encoded_lengths = run_length_encode(&all_lengths);
+
+    let mut code_length_freq = [0u32; 19];
+    for &(code, _) in &encoded_lengths {
+        code_length_freq[code as usize] += 1;
+    }
+    let code_length_lengths = huffman_lengths(&code_length_freq, 7);
+    let code_length_count = 4.max(
+        CODE_LENGTH_ORDER
+            .iter()
+            .rposition(|&idx| code_length_lengths[idx] != 0)
+            .map_or(0, |pos| pos + 1),
+    );
+
+    writer.write_bits(last as u32, 1);
+    writer.write_bits(2, 2);
+    writer.write_bits((literal_count - 257) as u32, 5);
+    writer.write_bits((distance_count - 1) as u32, 5);
+    writer.write_bits((code_length_count - 4) as u32, 4);
+    for &idx in &CODE_LENGTH_ORDER[..code_length_count] {
+        writer.write_bits(code_length_lengths[idx] as u32, 3);
+    }
+
+    let code_length_codes = canonical_codes(&code_length_lengths);
+    for &(code, extra) in &encoded_lengths {
+        let code = code as usize;
+        writer.write_code(code_length_codes[code], code_length_lengths[code]);
+        match code {
+            16 => writer.write_bits(extra as u32, 2),
+            17 => writer.write_bits(extra as u32, 3),
+            18 => writer.write_bits(extra as u32, 7),
+            _ => {}
+        }
+    }
+
+    let literal_codes = canonical_codes(&literal_lengths);
+    let distance_codes = canonical_codes(&distance_lengths);
+
+    for symbol in symbols {
+        match *symbol {
+            Symbol::Literal(byte) => {
+                let byte = byte as usize;
+                writer.write_code(literal_codes[byte], literal_lengths[byte]);
+            }
+            Symbol::Match { length, distance } => {
+                let (code, extra, extra_bits) = length_code(length);
+                writer.write_code(literal_codes[code], literal_lengths[code]);
+                writer.write_bits(extra as u32, extra_bits);
+
+                let (code, extra, extra_bits) = distance_code(distance);
+                writer.write_code(distance_codes[code], distance_lengths[code]);
+                writer.write_bits(extra as u32, extra_bits);
+            }
+        }
+    }
+
+    writer.write_code(literal_codes[END_OF_BLOCK], literal_lengths[END_OF_BLOCK]);
+}
+
+fn last_nonzero(lengths: &[u8]) -> usize {
+    lengths
+        .iter()
+        .rposition(|&l| l != 0)
+        .map_or(0, |pos| pos + 1)
+}
+
+// Encodes code lengths with the repeat codes 16 (previous), 17 and 18 (zeros)
+fn run_length_encode(lengths: &[u8]) -> Vec<(u8, u8)> {
+    let mut encoded = Vec::new();
+    let mut idx = 0;
+
+    while idx < lengths.len() {
+        let value = lengths[idx];
+        let run = lengths[idx..].iter().take_while(|&&l| l == value).count();
+
+        if value == 0 && run >= 3 {
+            let run = run.min(138);
+            if run >= 11 {
+                encoded.push((18, (run - 11) as u8));
+            } else {
+                encoded.push((17, (run - 3) as u8));
+            }
+            idx += run;
+        } else if value != 0 && run >= 4 {
+            encoded.push((value, 0));
+            let repeats = (run - 1).min(6);
+            encoded.push((16, (repeats - 3) as u8));
+            idx += 1 + repeats;
+        } else {
+            encoded.push((value, 0));
+            idx += 1;
+        }
+    }
+
+    encoded
+}
+
+// Huffman code lengths limited to max_length bits, frequencies are flattened until they fit
+fn huffman_lengths(freq: &[u32], max_length: u8) -> Vec<u8> {
+    let mut freq = freq.to_vec();
+
+    // A tree needs two leaves, some decoders reject incomplete single code trees
+    while freq.iter().filter(|&&f| f > 0).count() < 2 {
+        if let Some(f) = freq.iter_mut().find(|f| **f == 0) {
+            *f = 1;
+        }
+    }
+
+    loop {
+        let lengths = build_lengths(&freq);
+        if lengths.iter().all(|&l| l <= max_length) {
+            return lengths;
+        }
+        for f in freq.iter_mut().filter(|f| **f > 0) {
+            *f = (*f).div_ceil(2);
+        }
+    }
+}
+
+fn build_lengths(freq: &[u32]) -> Vec<u8> {
+    // Nodes: leaves first, then internal nodes with parent links
+    let mut weights = Vec::new();
+    let mut parents = Vec::new();
+    let mut leaves = Vec::new();
+
+    for (symbol, &f) in freq.iter().enumerate() {
+        if f > 0 {
+            leaves.push(symbol);
+            weights.push(f as u64);
+            parents.push(usize::MAX);
+        }
+    }
+
+    let mut heap = std::collections::BinaryHeap::new();
+    for (node, &weight) in weights.iter().enumerate() {
+        heap.push(std::cmp::Reverse((weight, node)));
+    }
+
+    while heap.len() > 1 {
+        let std::cmp::Reverse((w1, n1)) = heap.pop().unwrap();
+        let std::cmp::Reverse((w2, n2)) = heap.pop().unwrap();
+        let node = weights.len();
+        weights.push(w1 + w2);
+        parents.push(usize::MAX);
+        parents[n1] = node;
+        parents[n2] = node;
+        heap.push(std::cmp::Reverse((w1 + w2, node)));
+    }
+
+    let mut lengths = vec![0u8; freq.len()];
+    for (node, &symbol) in leaves.iter().enumerate() {
+        let mut depth = 0u32;
+        let mut current = node;
+        while parents[current] != usize::MAX {
+            current = parents[current];
+            depth += 1;
+        }
+        lengths[symbol] = depth.min(u8::MAX as u32) as u8;
+    }
+
+    lengths
+}
+
+fn canonical_codes(lengths: &[u8]) -> Vec<u16> {
+    let mut length_count = [0u16; 16];
+    for &length in lengths {
+        length_count[length as usize] += 1;
+    }
+    length_count[0] = 0;
+
+    let mut next_code = [0u16; 16];
+    let mut code = 0u16;
+    for bits in 1..16 {
+        code = (code + length_count[bits - 1]) << 1;
+        next_code[bits] = code;
+    }
+
+    lengths
+        .iter()
+        .map(|&length| {
+            if length == 0 {
+                0
+            } else {
+                let code = next_code[length as usize];
+                next_code[length as usize] += 1;
+                code
+            }
+        })
+        .collect()
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        BitWriter {
+            bytes: Vec::new(),
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    // Values are packed starting at the least significant bit
+    fn write_bits(&mut self, value: u32, bits: u8) {
+        self.buffer |= (value as u64) << self.count;
+        self.count += bits as u32;
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Huffman codes are packed starting at the most significant bit
+    fn write_code(&mut self, code: u16, length: u8) {
+        let reversed = code.reverse_bits() >> (16 - length as u32);
+        self.write_bits(reversed as u32, length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+fn inflate(reader: &mut BitReader) -> Result<Vec<u8>, io::Error> {
+    let mut out = Vec::new();
+
+    loop {
+        let last = reader.read_bits(1)? == 1;
+        match reader.read_bits(2)? {
+            0 => {
+                reader.align_to_byte();
+                let length = reader.read_bits(16)? as u16;
+                let inverse = reader.read_bits(16)? as u16;
+                if length != !inverse {
+                    return Err(error("stored block length check failed"));
+                }
+                for _ in 0..length {
+                    out.push(reader.read_bits(8)? as u8);
+                }
+            }
+            1 => {
+                let (literals, distances) = fixed_tables();
+                inflate_block(reader, &literals, &distances, &mut out)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_tables(reader)?;
+                inflate_block(reader, &literals, &distances, &mut out)?;
+            }
+            _ => return Err(error("invalid block type")),
+        }
+
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    literals: &Huffman,
+    distances: &Huffman,
+    out: &mut Vec<u8>,
+) -> Result<(), io::Error> {
+    loop {
+        let symbol = literals.decode(reader)?;
+
+        if symbol < END_OF_BLOCK {
+            out.push(symbol as u8);
+        } else if symbol == END_OF_BLOCK {
+            return Ok(());
+        } else {
+            let idx = symbol - 257;
+            if idx >= LENGTH_BASE.len() {
+                return Err(error("invalid length code"));
+            }
+            let length = LENGTH_BASE[idx] as usize + reader.read_bits(LENGTH_EXTRA[idx])? as usize;
+
+            let idx = distances.decode(reader)?;
+            if idx >= DISTANCE_BASE.len() {
+                return Err(error("invalid distance code"));
+            }
+            let distance =
+                DISTANCE_BASE[idx] as usize + reader.read_bits(DISTANCE_EXTRA[idx])? as usize;
+
+            if distance > out.len() {
+                return Err(error("distance points before start of output"));
+            }
+
+            // Copies may overlap themselves, so go byte by byte
+            let start = out.len() - distance;
+            for offset in 0..length {
+                out.push(out[start + offset]);
+            }
+        }
+    }
+}
+
+fn fixed_tables() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn dynamic_tables(reader: &mut BitReader) -> Result<(Huffman, Huffman), io::Error> {
+    let literal_count = reader.read_bits(5)? as usize + 257;
+    let distance_count = reader.read_bits(5)? as usize + 1;
+    let code_length_count = reader.read_bits(4)? as usize + 4;
+
+    if literal_count > 286 || distance_count > 30 {
+        return Err(error("too many codes in dynamic block"));
+    }
+
+    let mut code_length_lengths = [0u8; 19];
+    for &idx in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_length_lengths[idx] = reader.read_bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_length_lengths);
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let symbol = code_lengths.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths
+                    .last()
+                    .ok_or_else(|| error("repeat code without a previous length"))?;
+                (previous, 3 + reader.read_bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.read_bits(3)? as usize),
+            _ => (0, 11 + reader.read_bits(7)? as usize),
+        };
+        if lengths.len() + repeat > literal_count + distance_count {
+            return Err(error("code lengths overflow the tables"));
+        }
+        lengths.extend(std::iter::repeat_n(value, repeat));
+    }
+
+    if lengths[END_OF_BLOCK] == 0 {
+        return Err(error("missing end of block code"));
+    }
+
+    Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ))
+}
+
+// Canonical Huffman decoding table, symbols sorted by code length
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; 16];
+        for bits in 1..15 {
+            offsets[bits + 1] = offsets[bits] + counts[bits];
+        }
+
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+
+        Huffman { counts, symbols }
+    }
+
+    // Walks the code one bit at a time, comparing against the first code of each length
+    fn decode(&self, reader: &mut BitReader) -> Result<usize, io::Error> {
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+
+        for bits in 1..16 {
+            code |= reader.read_bits(1)? as i32;
+            let count = self.counts[bits] as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + (code - first)) as usize] as usize);
+            }
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+
+        Err(error("invalid Huffman code"))
+    }
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    buffer: u32,
+    count: u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        BitReader {
+            bytes,
+            position: 0,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    fn read_bits(&mut self, bits: u8) -> Result<u32, io::Error> {
+        while self.count < bits {
+            let byte = *self
+                .bytes
+                .get(self.position)
+                .ok_or_else(|| error("unexpected end of compressed data"))?;
+            self.buffer |= (byte as u32) << self.count;
+            self.position += 1;
+            self.count += 8;
+        }
+
+        let value = self.buffer & ((1u64 << bits) - 1) as u32;
+        self.buffer >>= bits;
+        self.count -= bits;
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+
+    // First byte that hasn't been consumed, whole buffered bytes are given back
+    fn byte_position(&self) -> usize {
+        self.position - (self.count / 8) as usize
+    }
+}
+
+fn error(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, format!("zlib: {message}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compress, decompress};
+
+    #[test]
+    fn inflates_stored_blocks() {
+        // Two stored blocks, "hel" and then the last one with "lo"
+        let data = [
+            0x78, 0x01, 0x00, 0x03, 0x00, 0xfc, 0xff, b'h', b'e', b'l', 0x01, 0x02, 0x00, 0xfd,
+            0xff, b'l', b'o', 0x06, 0x2c, 0x02, 0x15,
+        ];
+        assert_eq!(decompress(&data).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn inflates_fixed_huffman_blocks() {
+        // From zlib, the repeats are a match reaching back six bytes
+        let data = [
+            0x78, 0xda, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x90, 0x00, 0x3a, 0x2e,
+            0x06, 0x7d,
+        ];
+        assert_eq!(decompress(&data).unwrap(), b"hello hello hello");
+    }
+
+    #[test]
+    fn inflates_dynamic_huffman_blocks() {
+        let text = [
+            b"the quick brown fox jumps over the lazy dog, ".repeat(2),
+            b"pack my box with five dozen liquor jugs".to_vec(),
+        ]
+        .concat();
+
+        // From zlib
+        let data = [
+            0x78, 0xda, 0x8d, 0xcb, 0xc9, 0x11, 0x80, 0x20, 0x10, 0x44, 0xd1, 0x54, 0x3a, 0x00,
+            0x93, 0x02, 0x65, 0x53, 0x60, 0xd8, 0x11, 0xa3, 0x77, 0xca, 0x08, 0x3c, 0x76, 0xfd,
+            0xd7, 0xcd, 0x2a, 0xe4, 0xee, 0xf6, 0x0b, 0xb2, 0xd0, 0x8c, 0xd0, 0x74, 0xe3, 0xec,
+            0x21, 0x55, 0xd0, 0x50, 0x05, 0x8d, 0xb3, 0x17, 0xcf, 0xc2, 0x41, 0x66, 0xfb, 0xd6,
+            0x6f, 0x9c, 0x04, 0xbb, 0xb0, 0x20, 0x19, 0x4d, 0xd7, 0x2c, 0xb4, 0x1b, 0x8a, 0xd3,
+            0xa3, 0x22, 0xbc, 0xcb, 0x9d, 0x0a, 0x7f, 0x4d, 0x7d, 0x01, 0xf2, 0x36, 0x2f, 0x14,
+        ];
+        assert_eq!(decompress(&data).unwrap(), text);
+
+        // Our own encoder writes dynamic blocks for anything but empty input
+        let compressed = compress(&text);
+        assert_eq!((compressed[2] >> 1) & 3, 2);
+        assert_eq!(decompress(&compressed).unwrap(), text);
+    }
+
+    #[test]
+    fn rejects_corrupt_streams() {
+        let mut data = compress(b"hello hello hello");
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        assert!(decompress(&data).is_err());
+        assert!(decompress(&data[..4]).is_err());
+    }
+}
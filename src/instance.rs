@@ -0,0 +1,328 @@
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::{
+    aabb::Aabb,
+    bvh::{BVHNode, BuildStrategy, FlatBvh},
+    hittable::HitRecord,
+    interval::Interval,
+    primitive::{self, Primitive},
+    ray::Ray,
+    transform::Transform,
+    vec3::Vec3,
+};
+
+// Bottom level of the two-level hierarchy: primitives in their own object space with their own
+// BVH, built once and shared by every instance of them
+#[derive(Debug)]
+pub(crate) struct Geometry {
+    primitives: Vec<Primitive>,
+    bvh: FlatBvh,
+    aabb: Aabb,
+}
+
+impl Geometry {
+    pub(crate) fn new(mut primitives: Vec<Primitive>, strategy: BuildStrategy) -> Arc<Self> {
+        let root = BVHNode::build(&mut primitives, strategy);
+
+        let mut aabb = Aabb::empty();
+        for primitive in &primitives {
+            aabb.expand(primitive.bounding_box());
+        }
+
+        Arc::new(Geometry {
+            bvh: root.flatten(),
+            primitives,
+            aabb,
+        })
+    }
+
+    pub(crate) fn hit<R: Rng>(
+        &self,
+        rng: &mut R,
+        ray: Ray,
+        ray_interval: Interval,
+    ) -> Option<HitRecord> {
+        self.bvh.hit(rng, ray, ray_interval, &self.primitives)
+    }
+
+    pub(crate) fn occluded<R: Rng>(&self, rng: &mut R, ray: Ray, ray_interval: Interval) -> bool {
+        self.bvh.occluded(rng, ray, ray_interval, &self.primitives)
+    }
+
+    pub(crate) fn primitives(&self) -> &[Primitive] {
+        &self.primitives
+    }
+
+    pub(crate) fn bounding_box(&self) -> &Aabb {
+        &self.aabb
+    }
+}
+
+// Geometry placed in the world by an affine transform. Instances are leaves of the top-level BVH
+// over the world, so moving one only needs that level rebuilt with BVHNode::build
+#[derive(Debug)]
+pub(crate) struct InstanceParams {
+    pub(crate) geometry: Arc<Geometry>,
+    // Object to world
+    transform: Transform,
+    // World space offset reached at time 1, added after the transform
+    motion: Vec3,
+    aabb: Aabb,
+}
+
+impl InstanceParams {
+    pub(crate) fn new(geometry: Arc<Geometry>, transform: Transform, motion: Vec3) -> Self {
+        let mut instance = InstanceParams {
+            geometry,
+            transform,
+            motion,
+            aabb: Aabb::empty(),
+        };
+        instance.aabb = instance.world_bounds();
+        instance
+    }
+
+    // Covers the geometry at both ends of its motion
+    fn world_bounds(&self) -> Aabb {
+        let mut aabb = self.transform.aabb(self.geometry.bounding_box());
+        aabb.expand(
+            &self
+                .transform
+                .then(&Transform::translate(self.motion))
+                .aabb(self.geometry.bounding_box()),
+        );
+        aabb
+    }
+
+    // The object space direction isn't normalized, so t is the same in both spaces
+    fn object_ray(&self, ray: Ray) -> Ray {
+        let to_object = self.transform.inverse();
+        let origin = ray.origin - primitive::displacement(self.motion, ray.time);
+        Ray::new(
+            to_object.point(origin),
+            to_object.vector(ray.direction),
+            ray.time,
+        )
+    }
+
+    pub(crate) fn hit<R: Rng>(
+        &self,
+        rng: &mut R,
+        ray: Ray,
+        ray_interval: Interval,
+    ) -> Option<HitRecord> {
+        let mut hit = self.geometry.hit(rng, self.object_ray(ray), ray_interval)?;
+
+        // The inverse transpose keeps the normal facing the ray, so front_face carries over
+        hit.position = ray.at(hit.t);
+        hit.normal = self.transform.normal(hit.normal).unit();
+
+        Some(hit)
+    }
+
+    pub(crate) fn occluded<R: Rng>(&self, rng: &mut R, ray: Ray, ray_interval: Interval) -> bool {
+        self.geometry
+            .occluded(rng, self.object_ray(ray), ray_interval)
+    }
+
+    pub(crate) fn bounding_box(&self) -> &Aabb {
+        &self.aabb
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    use super::Geometry;
+    use crate::{
+        bvh::{BVHNode, BuildStrategy, SahConfig},
+        interval::Interval,
+        material::Material,
+        point::Point,
+        primitive::{Primitive, SphereParams},
+        ray::Ray,
+        transform::Transform,
+        vec3::Vec3,
+    };
+
+    #[test]
+    fn moving_an_instance_only_rebuilds_the_top_level() {
+        let geometry = Geometry::new(
+            vec![Primitive::Sphere(SphereParams::new(
+                Point::new(0.0, 0.0, 0.0),
+                1.0,
+                Arc::new(Material::Dielectric(1.5)),
+            ))],
+            BuildStrategy::Median,
+        );
+        let mut world: Vec<Primitive> = [-5.0, 0.0, 5.0]
+            .into_iter()
+            .map(|x| {
+                Primitive::new_instance(
+                    geometry.clone(),
+                    Transform::translate(Vec3::new(x, 0.0, 0.0)),
+                )
+            })
+            .collect();
+
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let interval = Interval::new(0.001, f32::INFINITY);
+        let ray = Ray::new(Point::new(0.0, 0.0, 10.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let moved_ray = Ray::new(Point::new(0.0, 20.0, 10.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+
+        let root = BVHNode::build(&mut world, BuildStrategy::Median).flatten();
+        let hit = root.hit(&mut rng, ray, interval, &world).unwrap();
+        assert!((hit.t - 9.0).abs() < 1e-4);
+        assert!(root.hit(&mut rng, moved_ray, interval, &world).is_none());
+
+        // Lift the middle instance, the shared geometry and its BVH stay as they are
+        for primitive in &mut world {
+            if primitive.bounding_box().x.contains(0.0) {
+                *primitive = Primitive::new_instance(
+                    geometry.clone(),
+                    Transform::translate(Vec3::new(0.0, 20.0, 0.0)),
+                );
+            }
+        }
+        let root = BVHNode::build(&mut world, BuildStrategy::Median).flatten();
+
+        assert!(root.hit(&mut rng, ray, interval, &world).is_none());
+        let hit = root.hit(&mut rng, moved_ray, interval, &world).unwrap();
+        assert!((hit.t - 9.0).abs() < 1e-4);
+        assert!((hit.position.y - 20.0).abs() < 1e-4);
+
+        assert_eq!(Arc::strong_count(&geometry), 4);
+        for primitive in &world {
+            let Primitive::Instance(instance) = primitive else {
+                panic!("expected an instance");
+            };
+            assert!(Arc::ptr_eq(&instance.geometry, &geometry));
+        }
+    }
+
+    // The bottom level is built like the top one, the build order of its primitives shows how
+    #[test]
+    fn geometry_follows_the_build_strategy() {
+        let spheres = || {
+            let mut rng = ChaCha8Rng::seed_from_u64(2);
+            let material = Arc::new(Material::Dielectric(1.5));
+            (0..64)
+                .map(|_| {
+                    let center = Point::new(
+                        rng.random_range(-10.0..10.0),
+                        rng.random_range(-1.0..1.0),
+                        rng.random_range(-1.0..1.0),
+                    );
+                    let radius = rng.random_range(0.1..1.0);
+                    Primitive::Sphere(SphereParams::new(center, radius, material.clone()))
+                })
+                .collect::<Vec<_>>()
+        };
+        let order = |primitives: &[Primitive]| {
+            primitives
+                .iter()
+                .map(|primitive| {
+                    let aabb = primitive.bounding_box();
+                    (aabb.x.min, aabb.y.min, aabb.z.min)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let orders = [
+            BuildStrategy::Median,
+            BuildStrategy::Sah(SahConfig::default()),
+        ]
+        .map(|strategy| {
+            let mut world = spheres();
+            BVHNode::build(&mut world, strategy);
+            let geometry = Geometry::new(spheres(), strategy);
+            assert_eq!(order(geometry.primitives()), order(&world));
+            order(&world)
+        });
+        assert_ne!(orders[0], orders[1]);
+    }
+
+    // Moving spheres and moving instances are where the shutter times them, and their bounds cover
+    // the whole motion
+    #[test]
+    fn motion_blur_follows_time() {
+        let material = Arc::new(Material::Dielectric(1.5));
+        let motion = Vec3::new(4.0, 0.0, 0.0);
+        let sphere = Primitive::Sphere(SphereParams::moving(
+            Point::new(0.0, 0.0, 0.0),
+            Point::new(0.0, 0.0, 0.0) + motion,
+            1.0,
+            material.clone(),
+        ));
+        let geometry = Geometry::new(
+            vec![Primitive::Sphere(SphereParams::new(
+                Point::new(0.0, 0.0, 0.0),
+                1.0,
+                material,
+            ))],
+            BuildStrategy::Median,
+        );
+        let instance = Primitive::new_moving_instance(
+            geometry,
+            Transform::translate(Vec3::new(0.0, 5.0, 0.0)),
+            motion,
+        );
+
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let interval = Interval::new(0.001, f32::INFINITY);
+        for (primitive, start) in [
+            (sphere, Point::new(0.0, 0.0, 0.0)),
+            (instance, Point::new(0.0, 5.0, 0.0)),
+        ] {
+            let end = start + motion;
+            let ray_at = |center: Point, time: f32| {
+                Ray::new(
+                    center + Vec3::new(0.0, 0.0, 10.0),
+                    Vec3::new(0.0, 0.0, -1.0),
+                    time,
+                )
+            };
+
+            for (center, time) in [(start, 0.0), (end, 1.0), (start + motion * 0.5, 0.5)] {
+                let hit = primitive
+                    .hit(&mut rng, ray_at(center, time), interval)
+                    .unwrap_or_else(|| panic!("{primitive:?} at {time}"));
+                assert!((hit.t - 9.0).abs() < 1e-4, "{}", hit.t);
+                assert!((hit.position.x - center.x).abs() < 1e-4);
+                assert!((hit.position.y - center.y).abs() < 1e-4);
+            }
+            // Each end is empty at the other time
+            assert!(
+                primitive
+                    .hit(&mut rng, ray_at(start, 1.0), interval)
+                    .is_none()
+            );
+            assert!(
+                primitive
+                    .hit(&mut rng, ray_at(end, 0.0), interval)
+                    .is_none()
+            );
+
+            // Corners of the spheres at both ends, which lie on the box
+            let aabb = primitive.bounding_box();
+            let covers = |interval: Interval, x: f32| interval.min <= x && x <= interval.max;
+            for corner in [
+                start - Vec3::new(1.0, 1.0, 1.0),
+                end + Vec3::new(1.0, 1.0, 1.0),
+            ] {
+                assert!(
+                    covers(aabb.x, corner.x)
+                        && covers(aabb.y, corner.y)
+                        && covers(aabb.z, corner.z),
+                    "{aabb:?} {corner:?}"
+                );
+            }
+        }
+    }
+}
@@ -61,8 +61,8 @@ impl Interval {
         }
     }
 
-    pub(crate) fn expand(self, delta: f32) {
+    pub(crate) fn expand(self, delta: f32) -> Interval {
         let padding = delta / 2.0;
-        Interval::new(self.min - padding, self.max + padding);
+        Interval::new(self.min - padding, self.max + padding)
     }
 }
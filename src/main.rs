@@ -1,57 +1,179 @@
-use std::io;
+use std::{io, path::Path};
 
 mod aabb;
+mod aov;
 mod axis;
+mod background;
 mod bvh;
 mod camera;
+mod cli;
 mod color;
 mod examples;
 mod hittable;
 mod image;
+mod instance;
 mod interval;
 mod material;
+mod medium;
+mod mesh;
 mod noise;
+mod obj;
+mod pdf;
 mod point;
 mod primitive;
 mod ray;
+mod scene;
 mod texture;
+mod transform;
 mod vec3;
 
 fn main() -> std::io::Result<()> {
-    println!(
-        "1: Three Spheres
-2: Many Spheres
-3: Checkers
-4: Earth
-5: Perlin
-6: Quads
-Choose scene: "
-    );
-
-    let stdin = io::stdin();
+    let options = match cli::Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("error: {message}\n\n{}", cli::USAGE);
+            std::process::exit(2);
+        }
+    };
 
-    let mut input = String::new();
+    if options.help {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
+
+    if options.list {
+        for (name, _) in examples::SCENES {
+            println!("{name}");
+        }
+        return Ok(());
+    }
+
+    let (path, format) = match options.output() {
+        Ok(output) => output,
+        Err(message) => {
+            eprintln!("error: {message}\n\n{}", cli::USAGE);
+            std::process::exit(2);
+        }
+    };
 
-    stdin.read_line(&mut input)?;
+    // Both levels of the BVH, the world and each instanced geometry, use the same strategy
+    let strategy = options.bvh.unwrap_or(bvh::BuildStrategy::Median);
 
-    let (camera, bvh_root, world) = match input.trim() {
-        "1" => examples::three_spheres(),
-        "2" => examples::many_spheres(),
-        "3" => examples::checkers(),
-        "4" => examples::earth(),
-        "5" => examples::perlin(),
-        "6" => examples::quads(),
-        _ => return Err(io::Error::from(io::ErrorKind::InvalidInput)),
+    // Built-in scene names first, anything else is a path to a scene file
+    let scene = match &options.scene {
+        Some(name) => match examples::by_name(name) {
+            Some(build) => Ok(build(strategy)),
+            None if Path::new(name).is_file() => {
+                scene::load(name, strategy).map_err(|err| err.to_string())
+            }
+            None => Err(format!(
+                "unknown scene '{name}', run with --list to see the available scenes"
+            )),
+        },
+        None => match choose_scene()? {
+            Some(build) => Ok(build(strategy)),
+            None => Err("unknown scene, run with --list to see the available scenes".to_string()),
+        },
+    };
+    let (mut camera, bvh_root, world) = match scene {
+        Ok(scene) => scene,
+        Err(message) => {
+            eprintln!("error: {message}");
+            std::process::exit(2);
+        }
     };
 
-    let pixels = camera.render(bvh_root, world)?;
+    if options.bvh_stats {
+        // Costs are counted with the SAH parameters the tree was built with, if any
+        let config = match strategy {
+            bvh::BuildStrategy::Sah(config) => config,
+            bvh::BuildStrategy::Median => bvh::SahConfig::default(),
+        };
+        println!("{} primitives, {}", world.len(), bvh_root.stats(&config));
+        return Ok(());
+    }
+
+    if options.image_width.is_some() || options.aspect_ratio.is_some() {
+        let image_width = options.image_width.unwrap_or(camera.image_width);
+        let aspect_ratio = options.aspect_ratio.unwrap_or(camera.aspect_ratio);
+        camera = camera.resized(image_width, aspect_ratio);
+    }
+    if let Some(samples_per_pixel) = options.samples_per_pixel {
+        camera.samples_per_pixel = samples_per_pixel;
+    }
+    camera.min_depth = options.min_depth.unwrap_or(camera.min_depth);
+    camera.max_depth = options.max_depth.unwrap_or(camera.max_depth);
+    camera.integrator = options.integrator.unwrap_or(camera.integrator);
+    camera.aovs = options.aovs.clone();
+    camera.threads = options.threads.or(camera.threads);
+    camera.seed = options.seed.unwrap_or(camera.seed);
+
+    let bvh = bvh_root.flatten();
+
+    if let Some((x, y)) = options.pixel {
+        if x >= camera.image_width || y >= camera.image_height {
+            eprintln!(
+                "error: pixel {x},{y} is outside the {}x{} image",
+                camera.image_width, camera.image_height
+            );
+            std::process::exit(2);
+        }
+        let lights = primitive::Primitive::lights(&world);
+        let materials = primitive::Primitive::materials(&world);
+        let output = camera.render_pixel(x, y, &bvh, &world, &lights, &materials);
+        let color = output[0];
+        println!("Pixel {x},{y}: {} {} {}", color.r, color.g, color.b);
+        for (aov, value) in camera.aovs.iter().zip(&output[1..]) {
+            println!("  {}: {} {} {}", aov.name(), value.r, value.g, value.b);
+        }
+        return Ok(());
+    }
+
+    let (pixels, passes) = camera.render(&bvh, world)?;
 
-    let res = camera.write_img(&pixels);
-    if res.is_ok() {
-        println!("Written image to disk");
+    // Every pass goes in its own image, or into the exr output as channels
+    let mut images = Vec::new();
+    if options.aov_files {
+        images.push((path.clone(), &pixels, &[][..]));
+        for (aov, pass) in camera.aovs.iter().zip(&passes) {
+            images.push((aov.path(&path), pass, &[][..]));
+        }
     } else {
-        println!("Failed to write image to disk");
+        images.push((path, &pixels, &passes[..]));
+    }
+
+    let png_depth = options.png_depth.unwrap_or(image::png::PngBitDepth::Eight);
+    let exr_compression = options
+        .exr_compression
+        .unwrap_or(image::exr::ExrCompression::Zip);
+    for (path, pixels, channels) in images {
+        match camera.write_img(&path, format, png_depth, exr_compression, pixels, channels) {
+            Ok(()) => println!("Written image to {path}"),
+            Err(err) => {
+                println!("Failed to write image to {path}");
+                return Err(err);
+            }
+        }
     }
 
-    res
+    Ok(())
+}
+
+// Interactive menu, by number or name
+fn choose_scene() -> io::Result<Option<examples::SceneBuilder>> {
+    for (idx, (name, _)) in examples::SCENES.iter().enumerate() {
+        println!("{}: {name}", idx + 1);
+    }
+    println!("Choose scene: ");
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    let input = input.trim();
+
+    Ok(match input.parse::<usize>() {
+        Ok(number) => examples::SCENES
+            .get(number.wrapping_sub(1))
+            .map(|(_, build)| *build),
+        Err(_) => examples::by_name(input),
+    })
 }
@@ -1,8 +1,11 @@
-use std::sync::Arc;
+use std::{f32::consts::PI, sync::Arc};
 
 use rand::Rng;
 
-use crate::{color::Color, ray::Ray, texture::Texture, vec3::Vec3};
+use crate::{
+    color::Color, hittable::HitRecord, pdf::Pdf, point::Point, ray::Ray, texture::Texture,
+    vec3::Vec3,
+};
 
 #[derive(Debug)]
 pub(crate) enum Material {
@@ -14,47 +17,60 @@ pub(crate) enum Material {
 
     // index of refraction relative to surrounding media (air or enclosing primitive)
     Dielectric(f32),
+
+    // emitted radiance, absorbs all incoming light
+    DiffuseLight(Arc<Texture>),
+
+    // albedo, scatters uniformly in all directions, the phase function of media
+    Isotropic(Arc<Texture>),
+}
+
+// Sampled continuation of a path from a hit
+#[derive(Debug)]
+pub(crate) struct Scatter {
+    pub(crate) direction: Vec3,
+    // BSDF times the cosine to the normal, the phase function in media. Specular scattering has
+    // no density, this is then the weight of the path
+    pub(crate) bsdf: Color,
+    // Density over solid angle of sampling direction, 1 for specular
+    pub(crate) pdf: f32,
+    // Light only arrives along this exact direction, so it can't be mixed with other samples
+    pub(crate) specular: bool,
 }
 
 impl Material {
-    #[allow(clippy::too_many_arguments)]
     pub(crate) fn scatter<T: Rng>(
         &self,
         rng: &mut T,
         ray: Ray,
-        t: f32,
-        u: f32,
-        v: f32,
-        normal: Vec3,
-        front_face: bool,
-    ) -> (Ray, Color) {
+        hit: &HitRecord,
+    ) -> Option<Scatter> {
         match self {
-            Material::Lambertian(tex) => {
-                let mut scatter_direction = normal + Vec3::random_unit_vector(rng);
-
-                if scatter_direction.near_zero() {
-                    scatter_direction = normal
-                }
-
-                (
-                    Ray::new(ray.at(t), scatter_direction),
-                    tex.sample(u, v, ray.at(t)),
-                )
+            Material::Lambertian(_) | Material::Isotropic(_) => {
+                let pdf = self.pdf(hit)?;
+                let direction = pdf.generate(rng);
+
+                Some(Scatter {
+                    direction,
+                    bsdf: self.bsdf(hit, direction),
+                    pdf: pdf.value(direction),
+                    specular: false,
+                })
             }
             Material::Metal(albedo, fuzz) => {
-                let reflected = ray.direction.reflect(normal);
+                let reflected = ray.direction.reflect(hit.normal);
                 let reflected_fuzzed = reflected.unit() + (*fuzz * Vec3::random_unit_vector(rng));
 
-                (Ray::new(ray.at(t), reflected_fuzzed), *albedo)
+                Some(Scatter::specular(reflected_fuzzed, *albedo))
             }
             Material::Dielectric(ior) => {
                 let color = Color::new(1.0, 1.0, 1.0);
 
-                let refraction_index = if front_face { 1.0 / *ior } else { *ior };
+                let refraction_index = if hit.front_face { 1.0 / *ior } else { *ior };
 
                 let unit_direction = ray.direction.unit();
 
-                let cos_theta = f32::min((-unit_direction).dot(normal), 1.0);
+                let cos_theta = f32::min((-unit_direction).dot(hit.normal), 1.0);
                 let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
                 let cannot_refract = refraction_index * sin_theta > 1.0;
@@ -67,15 +83,66 @@ impl Material {
                 };
 
                 let direction = if cannot_refract || reflectance > rng.random_range(0.0..1.0) {
-                    unit_direction.reflect(normal)
+                    unit_direction.reflect(hit.normal)
                 } else {
-                    unit_direction.refract(normal, refraction_index)
+                    unit_direction.refract(hit.normal, refraction_index)
                 };
 
-                let scattered = Ray::new(ray.at(t), direction);
+                Some(Scatter::specular(direction, color))
+            }
+            Material::DiffuseLight(_) => None,
+        }
+    }
+
+    // Distribution scatter draws directions from, None for specular materials and lights
+    pub(crate) fn pdf(&self, hit: &HitRecord) -> Option<Pdf<'static>> {
+        match self {
+            Material::Lambertian(_) => Some(Pdf::Cosine(hit.normal)),
+            Material::Isotropic(_) => Some(Pdf::Sphere),
+            _ => None,
+        }
+    }
+
+    // BSDF times the cosine for light leaving along direction, zero for specular materials
+    pub(crate) fn bsdf(&self, hit: &HitRecord, direction: Vec3) -> Color {
+        match self {
+            Material::Lambertian(tex) => {
+                let cosine = direction.unit().dot(hit.normal).max(0.0);
+                tex.sample(hit.u, hit.v, hit.position) * (cosine / PI)
+            }
+            Material::Isotropic(tex) => tex.sample(hit.u, hit.v, hit.position) / (4.0 * PI),
+            _ => Color::new(0.0, 0.0, 0.0),
+        }
+    }
 
-                (scattered, color)
+    // Fraction of light reflected at hit, white for glass which lets all of it through and black
+    // for lights which reflect none
+    pub(crate) fn albedo(&self, hit: &HitRecord) -> Color {
+        match self {
+            Material::Lambertian(tex) | Material::Isotropic(tex) => {
+                tex.sample(hit.u, hit.v, hit.position)
             }
+            Material::Metal(albedo, _) => *albedo,
+            Material::Dielectric(_) => Color::new(1.0, 1.0, 1.0),
+            Material::DiffuseLight(_) => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    pub(crate) fn emitted(&self, u: f32, v: f32, p: Point) -> Color {
+        match self {
+            Material::DiffuseLight(tex) => tex.sample(u, v, p),
+            _ => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+impl Scatter {
+    fn specular(direction: Vec3, attenuation: Color) -> Self {
+        Scatter {
+            direction,
+            bsdf: attenuation,
+            pdf: 1.0,
+            specular: true,
         }
     }
 }
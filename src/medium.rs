@@ -0,0 +1,302 @@
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::{
+    aabb::Aabb,
+    hittable::HitRecord,
+    instance::Geometry,
+    interval::Interval,
+    material::Material,
+    noise::{self, Perlin},
+    point::Point,
+    ray::Ray,
+    vec3::Vec3,
+};
+
+mod grid;
+
+pub(crate) use grid::VoxelGrid;
+
+// Density of a medium in its boundary's space
+#[derive(Debug)]
+pub(crate) enum Density {
+    Constant(f32),
+
+    // voxels, scale
+    Grid(VoxelGrid, f32),
+
+    // noise, scale
+    Turbulence(Perlin, f32),
+}
+
+impl Density {
+    fn at(&self, p: Point) -> f32 {
+        match self {
+            Density::Constant(density) => *density,
+            Density::Grid(grid, scale) => scale * grid.lookup(p),
+            Density::Turbulence(perlin, scale) => scale * perlin.density(&p),
+        }
+    }
+
+    // Upper bound of the density anywhere, the majorant for tracking
+    fn max(&self) -> f32 {
+        match self {
+            Density::Constant(density) => *density,
+            Density::Grid(grid, scale) => scale * grid.max(),
+            Density::Turbulence(_, scale) => scale * noise::TURBULENCE_MAX,
+        }
+    }
+}
+
+// Participating medium filling a closed boundary, e.g. smoke, fog or clouds
+// https://raytracing.github.io/books/RayTracingTheNextWeek.html#volumes
+// https://pbr-book.org/4ed/Volume_Scattering/Volume_Scattering_Processes#sec:null-scattering
+#[derive(Debug)]
+pub(crate) struct MediumParams {
+    boundary: Arc<Geometry>,
+    density: Density,
+    majorant: f32,
+    // Phase function, usually Material::Isotropic. Textures that vary in space, like a voxel grid
+    // texture, give a varying albedo, they are sampled where the ray scatters
+    phase: Arc<Material>,
+}
+
+impl MediumParams {
+    pub(crate) fn new(boundary: Arc<Geometry>, density: Density, phase: Arc<Material>) -> Self {
+        MediumParams {
+            boundary,
+            majorant: density.max(),
+            density,
+            phase,
+        }
+    }
+
+    // Delta tracking: tentative collisions are spaced as if the whole medium had the majorant
+    // density, each one is real with the ratio of the density there to the majorant. Constant
+    // media accept the first collision without drawing again
+    pub(crate) fn hit<R: Rng>(
+        &self,
+        rng: &mut R,
+        ray: Ray,
+        ray_interval: Interval,
+    ) -> Option<HitRecord> {
+        let (t_enter, t_exit) = self.span(rng, ray, ray_interval)?;
+        let step = 1.0 / (self.majorant * ray.direction.length());
+
+        let mut t = t_enter;
+        loop {
+            t -= (1.0 - rng.random::<f32>()).ln() * step;
+            if t >= t_exit {
+                return None;
+            }
+
+            let density = self.density.at(ray.at(t));
+            if density >= self.majorant || rng.random::<f32>() * self.majorant < density {
+                break;
+            }
+        }
+
+        // Scattering has no surface, the normal and facing are arbitrary
+        Some(HitRecord {
+            position: ray.at(t),
+            normal: Vec3::new(1.0, 0.0, 0.0),
+            material: self.phase.clone(),
+            t,
+            u: 0.0,
+            v: 0.0,
+            front_face: true,
+        })
+    }
+
+    // Ratio tracking: the same tentative collisions, each weighting the estimate by the chance of
+    // it being a null collision
+    pub(crate) fn transmittance<R: Rng>(
+        &self,
+        rng: &mut R,
+        ray: Ray,
+        ray_interval: Interval,
+    ) -> f32 {
+        let Some((t_enter, t_exit)) = self.span(rng, ray, ray_interval) else {
+            return 1.0;
+        };
+        let step = 1.0 / (self.majorant * ray.direction.length());
+
+        let mut transmittance = 1.0;
+        let mut t = t_enter;
+        loop {
+            t -= (1.0 - rng.random::<f32>()).ln() * step;
+            if t >= t_exit || transmittance == 0.0 {
+                return transmittance;
+            }
+            transmittance *= (1.0 - self.density.at(ray.at(t)) / self.majorant).max(0.0);
+        }
+    }
+
+    // Blocks the ray with the chance of it not getting through
+    pub(crate) fn occluded<R: Rng>(&self, rng: &mut R, ray: Ray, ray_interval: Interval) -> bool {
+        let transmittance = self.transmittance(rng, ray, ray_interval);
+        transmittance < 1.0 && rng.random::<f32>() >= transmittance
+    }
+
+    // Part of the ray inside the boundary, rays starting inside begin at their origin. Assumes a
+    // convex boundary
+    fn span<R: Rng>(&self, rng: &mut R, ray: Ray, ray_interval: Interval) -> Option<(f32, f32)> {
+        if self.majorant <= 0.0 {
+            return None;
+        }
+
+        let enter = self.boundary.hit(rng, ray, Interval::all())?;
+        let exit = self
+            .boundary
+            .hit(rng, ray, Interval::new(enter.t + 0.0001, f32::INFINITY))?;
+
+        let t_enter = enter.t.max(ray_interval.min).max(0.0);
+        let t_exit = exit.t.min(ray_interval.max);
+        (t_enter < t_exit).then_some((t_enter, t_exit))
+    }
+
+    pub(crate) fn phase(&self) -> &Arc<Material> {
+        &self.phase
+    }
+
+    pub(crate) fn bounding_box(&self) -> &Aabb {
+        self.boundary.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::{Density, MediumParams, VoxelGrid};
+    use crate::{
+        aabb::Aabb, bvh::BuildStrategy, color::Color, instance::Geometry, interval::Interval,
+        material::Material, point::Point, primitive::Primitive, ray::Ray, texture::Texture,
+        vec3::Vec3,
+    };
+
+    const SAMPLES: usize = 20_000;
+
+    // Medium filling the cube from -1 to 1
+    fn cube_medium(density: Density) -> MediumParams {
+        let phase = Arc::new(Material::Isotropic(Arc::new(Texture::from_color(
+            Color::new(1.0, 1.0, 1.0),
+        ))));
+        let boundary = Geometry::new(
+            Primitive::new_box(
+                Point::new(-1.0, -1.0, -1.0),
+                Point::new(1.0, 1.0, 1.0),
+                phase.clone(),
+            ),
+            BuildStrategy::Median,
+        );
+        MediumParams::new(boundary, density, phase)
+    }
+
+    // Delta tracking, ratio tracking and occlusion all agree with the expected transmittance
+    fn assert_transmittance(medium: &MediumParams, expected: f32) {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let interval = Interval::new(0.001, f32::INFINITY);
+        // Direction isn't unit length, distances are still measured in world units
+        let ray = Ray::new(Point::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -2.0), 0.0);
+
+        let (mut passed, mut unoccluded, mut ratio) = (0, 0, 0.0);
+        for _ in 0..SAMPLES {
+            match medium.hit(&mut rng, ray, interval) {
+                Some(hit) => assert!((-1.0..=1.0).contains(&hit.position.z)),
+                None => passed += 1,
+            }
+            unoccluded += !medium.occluded(&mut rng, ray, interval) as usize;
+            ratio += medium.transmittance(&mut rng, ray, interval);
+        }
+
+        for estimate in [
+            passed as f32 / SAMPLES as f32,
+            unoccluded as f32 / SAMPLES as f32,
+            ratio / SAMPLES as f32,
+        ] {
+            assert!((estimate - expected).abs() < 0.02, "{estimate} {expected}");
+        }
+
+        // Rays ending before the boundary never scatter
+        let short = Interval::new(0.001, 1.9);
+        assert!(medium.hit(&mut rng, ray, short).is_none());
+        assert_eq!(medium.transmittance(&mut rng, ray, short), 1.0);
+    }
+
+    #[test]
+    fn constant_density_follows_beer_lambert() {
+        // Two units of density 0.5
+        assert_transmittance(&cube_medium(Density::Constant(0.5)), (-1.0f32).exp());
+    }
+
+    #[test]
+    fn grid_density_follows_beer_lambert() {
+        // Density 0.25 along the ray, one dense corner voxel far from it raises the majorant so
+        // most tentative collisions are null
+        let resolution = [8, 8, 8];
+        let mut values = vec![0.25; 512];
+        values[0] = 1.0;
+        let bounds = Aabb::new_between(Point::new(-1.0, -1.0, -1.0), Point::new(1.0, 1.0, 1.0));
+        let grid = VoxelGrid::new(bounds, resolution, values);
+
+        assert_transmittance(&cube_medium(Density::Grid(grid, 1.0)), (-0.5f32).exp());
+    }
+
+    #[test]
+    fn voxel_files_load_and_interpolate() {
+        let bounds = Aabb::new_between(Point::new(0.0, 0.0, 0.0), Point::new(2.0, 1.0, 1.0));
+        let directory = std::env::temp_dir();
+        let id = std::process::id();
+        let text = directory.join(format!("weekend-rt-{id}.voxels"));
+        let raw = directory.join(format!("weekend-rt-{id}.raw"));
+
+        std::fs::write(&text, "# two voxels along x\nvoxels 2 1 1\n0 1\n").unwrap();
+        std::fs::write(&raw, [0.0f32, 1.0].map(f32::to_le_bytes).concat()).unwrap();
+        let text = text.to_string_lossy().into_owned();
+        let raw = raw.to_string_lossy().into_owned();
+
+        let grids = [
+            VoxelGrid::load(&text, None, bounds),
+            VoxelGrid::load(&raw, Some([2, 1, 1]), bounds),
+        ];
+        let missing_resolution = VoxelGrid::load(&raw, None, bounds);
+        let wrong_resolution = VoxelGrid::load(&raw, Some([3, 1, 1]), bounds);
+        let overflow_raw = VoxelGrid::load(&raw, Some([usize::MAX / 4, 1, 2]), bounds);
+        std::fs::remove_file(&text).unwrap();
+        std::fs::remove_file(&raw).unwrap();
+
+        assert!(missing_resolution.is_err());
+        assert!(wrong_resolution.is_err());
+
+        // Header counts whose product overflows are errors rather than panics or huge allocations
+        let huge = format!("voxels {} {} 2\n0 1\n", usize::MAX / 2, usize::MAX / 2);
+        let overflow = directory.join(format!("weekend-rt-{id}-overflow.voxels"));
+        std::fs::write(&overflow, huge).unwrap();
+        let overflow_text = VoxelGrid::load(&overflow.to_string_lossy(), None, bounds);
+        std::fs::remove_file(&overflow).unwrap();
+        for result in [overflow_text, overflow_raw] {
+            let error = result.unwrap_err().to_string();
+            assert!(error.contains("overflow"), "{error}");
+        }
+
+        for grid in grids {
+            let grid = grid.unwrap();
+            assert_eq!(grid.max(), 1.0);
+            // Values sit at voxel centers, halfway between them is the average, outside is empty
+            for (x, expected) in [(0.2, 0.0), (0.5, 0.0), (1.0, 0.5), (1.5, 1.0), (2.5, 0.0)] {
+                let density = grid.lookup(Point::new(x, 0.5, 0.5));
+                assert!((density - expected).abs() < 1e-6, "{x}: {density}");
+            }
+
+            // The same grid as a texture scales its color, e.g. a varying albedo
+            let texture = Texture::Grid(grid, Color::new(0.5, 1.0, 0.0));
+            let albedo = texture.sample(0.0, 0.0, Point::new(1.0, 0.5, 0.5));
+            assert_eq!((albedo.r, albedo.g, albedo.b), (0.25, 0.5, 0.0));
+        }
+    }
+}
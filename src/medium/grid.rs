@@ -0,0 +1,207 @@
+use std::{
+    io::{self, ErrorKind},
+    str::FromStr,
+};
+
+use crate::{aabb::Aabb, point::Point};
+
+// Dense grid of densities or albedos stretched over its bounds, each value sits at the center of its voxel
+#[derive(Debug)]
+pub(crate) struct VoxelGrid {
+    bounds: Aabb,
+    resolution: [usize; 3],
+    // x varies fastest, then y, then z
+    values: Vec<f32>,
+    max: f32,
+}
+
+impl VoxelGrid {
+    pub(crate) fn new(bounds: Aabb, resolution: [usize; 3], values: Vec<f32>) -> Self {
+        assert_eq!(values.len(), resolution.iter().product::<usize>());
+        let max = values.iter().copied().fold(0.0, f32::max);
+
+        VoxelGrid {
+            bounds,
+            resolution,
+            values,
+            max,
+        }
+    }
+
+    // Text files start with "voxels nx ny nz" followed by the values, '#' starts a comment.
+    // Anything else is raw little-endian f32 values, which needs the resolution from the caller
+    pub(crate) fn load(
+        path: &str,
+        resolution: Option<[usize; 3]>,
+        bounds: Aabb,
+    ) -> Result<Self, io::Error> {
+        let contents = std::fs::read(path)?;
+
+        let (resolution, values) = match std::str::from_utf8(&contents) {
+            Ok(text) if first_token(text) == Some("voxels") => parse_text(path, text)?,
+            _ => {
+                let resolution = resolution.ok_or_else(|| {
+                    load_error(path, "raw voxel files need a resolution".to_string())
+                })?;
+                (resolution, parse_raw(path, &contents, resolution)?)
+            }
+        };
+
+        if let Some(value) = values
+            .iter()
+            .find(|value| !value.is_finite() || **value < 0.0)
+        {
+            return Err(load_error(
+                path,
+                format!("voxel value {value} must be finite and non-negative"),
+            ));
+        }
+
+        Ok(VoxelGrid::new(bounds, resolution, values))
+    }
+
+    pub(crate) fn max(&self) -> f32 {
+        self.max
+    }
+
+    // Trilinear interpolation between voxel centers, edge voxels extend to the bounds and
+    // everything outside is empty
+    pub(crate) fn lookup(&self, p: Point) -> f32 {
+        let mut cell = [0; 3];
+        let mut weight = [0.0; 3];
+
+        for (axis, (interval, coordinate)) in [
+            (self.bounds.x, p.x),
+            (self.bounds.y, p.y),
+            (self.bounds.z, p.z),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            if !interval.contains(coordinate) {
+                return 0.0;
+            }
+            let count = self.resolution[axis];
+            let g = (coordinate - interval.min) / interval.size() * count as f32 - 0.5;
+            let g = g.clamp(0.0, (count - 1) as f32);
+            cell[axis] = (g as usize).min(count.saturating_sub(2));
+            weight[axis] = g - cell[axis] as f32;
+        }
+
+        let mut accum = 0.0;
+        for (dz, wz) in [(0, 1.0 - weight[2]), (1, weight[2])] {
+            for (dy, wy) in [(0, 1.0 - weight[1]), (1, weight[1])] {
+                for (dx, wx) in [(0, 1.0 - weight[0]), (1, weight[0])] {
+                    let w = wx * wy * wz;
+                    if w > 0.0 {
+                        accum += w * self.value(cell[0] + dx, cell[1] + dy, cell[2] + dz);
+                    }
+                }
+            }
+        }
+        accum
+    }
+
+    fn value(&self, x: usize, y: usize, z: usize) -> f32 {
+        let [nx, ny, _] = self.resolution;
+        self.values[(z * ny + y) * nx + x]
+    }
+}
+
+fn first_token(text: &str) -> Option<&str> {
+    text.lines()
+        .flat_map(|line| {
+            line.split('#')
+                .next()
+                .unwrap_or_default()
+                .split_whitespace()
+        })
+        .next()
+}
+
+fn parse_text(path: &str, text: &str) -> Result<([usize; 3], Vec<f32>), io::Error> {
+    let mut tokens = text.lines().enumerate().flat_map(|(line_idx, line)| {
+        let line = line.split('#').next().unwrap_or_default();
+        line.split_whitespace()
+            .map(move |token| (line_idx + 1, token))
+    });
+
+    // Header, already known to start with "voxels"
+    tokens.next();
+    let mut resolution = [0; 3];
+    for count in &mut resolution {
+        let (line, token) = tokens
+            .next()
+            .ok_or_else(|| load_error(path, "missing resolution after 'voxels'".to_string()))?;
+        *count = match usize::from_str(token) {
+            Ok(count) if count > 0 => count,
+            _ => {
+                return Err(load_error(
+                    &format!("{path}:{line}"),
+                    format!("invalid resolution '{token}'"),
+                ));
+            }
+        };
+    }
+
+    // Values are only counted against the header at the end, it doesn't size the allocation
+    let expected = voxel_count(path, resolution)?;
+    let mut values = Vec::new();
+    for (line, token) in tokens {
+        let value = f32::from_str(token).map_err(|_| {
+            load_error(
+                &format!("{path}:{line}"),
+                format!("invalid density '{token}'"),
+            )
+        })?;
+        values.push(value);
+    }
+
+    if values.len() != expected {
+        let [nx, ny, nz] = resolution;
+        return Err(load_error(
+            path,
+            format!(
+                "expected {expected} values for {nx}x{ny}x{nz} voxels, found {}",
+                values.len()
+            ),
+        ));
+    }
+
+    Ok((resolution, values))
+}
+
+fn parse_raw(path: &str, contents: &[u8], resolution: [usize; 3]) -> Result<Vec<f32>, io::Error> {
+    let expected = voxel_count(path, resolution)?
+        .checked_mul(4)
+        .ok_or_else(|| load_error(path, "voxel count overflows".to_string()))?;
+    if resolution.contains(&0) || contents.len() != expected {
+        let [nx, ny, nz] = resolution;
+        return Err(load_error(
+            path,
+            format!(
+                "expected {expected} bytes for {nx}x{ny}x{nz} voxels, found {}",
+                contents.len()
+            ),
+        ));
+    }
+
+    Ok(contents
+        .chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect())
+}
+
+fn voxel_count(path: &str, resolution: [usize; 3]) -> Result<usize, io::Error> {
+    resolution
+        .iter()
+        .try_fold(1usize, |count, n| count.checked_mul(*n))
+        .ok_or_else(|| {
+            let [nx, ny, nz] = resolution;
+            load_error(path, format!("{nx}x{ny}x{nz} voxels overflow"))
+        })
+}
+
+fn load_error(location: &str, message: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, format!("{location}: {message}"))
+}
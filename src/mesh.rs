@@ -0,0 +1,100 @@
+use std::sync::Arc;
+
+use crate::{
+    material::Material,
+    point::Point,
+    primitive::{Primitive, TriangleParams},
+    vec3::Vec3,
+};
+
+// Indexed triangle mesh, vertex attributes are shared between all triangles referencing them
+#[derive(Debug)]
+pub(crate) struct Mesh {
+    pub(crate) positions: Vec<Point>,
+    // Optional per-vertex attributes, same length as positions when present
+    pub(crate) normals: Option<Vec<Vec3>>,
+    pub(crate) uvs: Option<Vec<(f32, f32)>>,
+    // Three vertex indices per triangle, counter-clockwise winding gives the outward normal
+    pub(crate) indices: Vec<[u32; 3]>,
+}
+
+impl Mesh {
+    pub(crate) fn new(
+        positions: Vec<Point>,
+        indices: Vec<[u32; 3]>,
+        normals: Option<Vec<Vec3>>,
+        uvs: Option<Vec<(f32, f32)>>,
+    ) -> Mesh {
+        assert!(
+            indices
+                .iter()
+                .flatten()
+                .all(|&idx| (idx as usize) < positions.len()),
+            "mesh index out of bounds"
+        );
+        if let Some(normals) = &normals {
+            assert_eq!(normals.len(), positions.len());
+        }
+        if let Some(uvs) = &uvs {
+            assert_eq!(uvs.len(), positions.len());
+        }
+
+        Mesh {
+            positions,
+            normals,
+            uvs,
+            indices,
+        }
+    }
+
+    pub(crate) fn triangle_count(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub(crate) fn vertices(&self, triangle: usize) -> [Point; 3] {
+        let [a, b, c] = self.indices[triangle];
+        [
+            self.positions[a as usize],
+            self.positions[b as usize],
+            self.positions[c as usize],
+        ]
+    }
+
+    // Interpolated vertex normal, not normalized, None if the mesh has no normals
+    pub(crate) fn shading_normal(
+        &self,
+        triangle: usize,
+        b0: f32,
+        b1: f32,
+        b2: f32,
+    ) -> Option<Vec3> {
+        let normals = self.normals.as_ref()?;
+        let [a, b, c] = self.indices[triangle];
+        Some(b0 * normals[a as usize] + b1 * normals[b as usize] + b2 * normals[c as usize])
+    }
+
+    // Interpolated texture coordinates, None if the mesh has no uvs
+    pub(crate) fn texture_uv(
+        &self,
+        triangle: usize,
+        b0: f32,
+        b1: f32,
+        b2: f32,
+    ) -> Option<(f32, f32)> {
+        let uvs = self.uvs.as_ref()?;
+        let [a, b, c] = self.indices[triangle];
+        let (ua, va) = uvs[a as usize];
+        let (ub, vb) = uvs[b as usize];
+        let (uc, vc) = uvs[c as usize];
+        Some((b0 * ua + b1 * ub + b2 * uc, b0 * va + b1 * vb + b2 * vc))
+    }
+
+    // One triangle primitive per mesh face, all sharing the same material
+    pub(crate) fn primitives(mesh: &Arc<Mesh>, material: Arc<Material>) -> Vec<Primitive> {
+        (0..mesh.triangle_count())
+            .map(|idx| {
+                Primitive::Triangle(TriangleParams::new(mesh.clone(), idx, material.clone()))
+            })
+            .collect()
+    }
+}
@@ -14,6 +14,11 @@ pub(crate) struct Perlin {
 
 const POINT_COUNT: usize = 256;
 
+const TURBULENCE_DEPTH: u32 = 7;
+
+// Octave weights sum to just under 2 and the noise itself stays within 1
+pub(crate) const TURBULENCE_MAX: f32 = 2.0;
+
 impl Perlin {
     pub(crate) fn new(scale: f32, seed: u64) -> Perlin {
         let mut rng = ChaCha8Rng::seed_from_u64(seed);
@@ -75,7 +80,12 @@ impl Perlin {
 
     pub(crate) fn value(&self, point: &Point) -> Color {
         Color::new(0.5, 0.5, 0.5)
-            * (1.0 + (self.scale * point.z + 10.0 * self.turbulence(point, 7)).sin())
+            * (1.0 + (self.scale * point.z + 10.0 * self.turbulence(point, TURBULENCE_DEPTH)).sin())
+    }
+
+    // Turbulence at the noise's own scale, never above TURBULENCE_MAX
+    pub(crate) fn density(&self, point: &Point) -> f32 {
+        self.turbulence(&(Vec3::from(*point) * self.scale).into(), TURBULENCE_DEPTH)
     }
 
     fn trilinear_interpolation(c: &[[[Vec3; 2]; 2]; 2], u: f32, v: f32, w: f32) -> f32 {
@@ -0,0 +1,457 @@
+use std::{
+    collections::HashMap,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    str::{FromStr, SplitWhitespace},
+    sync::Arc,
+};
+
+use crate::{
+    color::Color, image::Image, material::Material, mesh::Mesh, point::Point, primitive::Primitive,
+    texture::Texture, vec3::Vec3,
+};
+
+// Wavefront OBJ loader, every (group, material) pair becomes its own indexed mesh
+pub(crate) fn load(path: &str) -> Result<Vec<Primitive>, io::Error> {
+    let contents = std::fs::read_to_string(path)?;
+    let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+
+    let default_material = Arc::new(Material::Lambertian(Arc::new(Texture::from_color(
+        Color::new(0.8, 0.8, 0.8),
+    ))));
+
+    let mut positions = Vec::<Point>::new();
+    let mut uvs = Vec::<(f32, f32)>::new();
+    let mut normals = Vec::<Vec3>::new();
+
+    let mut materials = HashMap::<String, Arc<Material>>::new();
+    let mut textures = HashMap::<PathBuf, Arc<Texture>>::new();
+
+    let mut builder = MeshBuilder::new(default_material.clone());
+    let mut world = Vec::new();
+
+    for (line_idx, line) in contents.lines().enumerate() {
+        let line_number = line_idx + 1;
+        let error = |message: String| parse_error(path, line_number, message);
+
+        let line = line.split('#').next().unwrap_or_default();
+        let mut tokens = line.split_whitespace();
+
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+
+        match keyword {
+            "v" => {
+                let [x, y, z] = parse_floats(&mut tokens, keyword).map_err(error)?;
+                positions.push(Point::new(x, y, z));
+            }
+            "vt" => {
+                // Only u is required by the spec, v defaults to 0
+                let u = parse_float(tokens.next(), keyword).map_err(error)?;
+                let v = match tokens.next() {
+                    Some(token) => parse_float(Some(token), keyword).map_err(error)?,
+                    None => 0.0,
+                };
+                uvs.push((u, v));
+            }
+            "vn" => {
+                let [x, y, z] = parse_floats(&mut tokens, keyword).map_err(error)?;
+                normals.push(Vec3::new(x, y, z));
+            }
+            "f" => {
+                let corners = tokens
+                    .map(|token| parse_corner(token, positions.len(), uvs.len(), normals.len()))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(error)?;
+
+                if corners.len() < 3 {
+                    return Err(error(format!(
+                        "face needs at least 3 vertices, found {}",
+                        corners.len()
+                    )));
+                }
+
+                // Fan triangulation around the first corner
+                for idx in 1..(corners.len() - 1) {
+                    builder.push_triangle(
+                        [corners[0], corners[idx], corners[idx + 1]],
+                        &positions,
+                        &uvs,
+                        &normals,
+                    );
+                }
+            }
+            "g" | "o" => {
+                builder.flush(&mut world);
+            }
+            "usemtl" => {
+                let name = tokens
+                    .next()
+                    .ok_or_else(|| error("missing material name".to_string()))?;
+                let material = materials
+                    .get(name)
+                    .ok_or_else(|| error(format!("unknown material '{name}'")))?
+                    .clone();
+
+                builder.flush(&mut world);
+                builder.material = material;
+            }
+            "mtllib" => {
+                for library in tokens {
+                    let library_path = directory.join(library);
+                    load_mtl(&library_path, &mut materials, &mut textures).map_err(|e| {
+                        if e.kind() == ErrorKind::InvalidData {
+                            e
+                        } else {
+                            error(format!("failed to read '{library}': {e}"))
+                        }
+                    })?;
+                }
+            }
+            // Smoothing groups, lines, points and free-form geometry are ignored
+            _ => {}
+        }
+    }
+
+    builder.flush(&mut world);
+
+    Ok(world)
+}
+
+fn load_mtl(
+    path: &Path,
+    materials: &mut HashMap<String, Arc<Material>>,
+    textures: &mut HashMap<PathBuf, Arc<Texture>>,
+) -> Result<(), io::Error> {
+    let name = path.to_string_lossy();
+    let contents = std::fs::read_to_string(path)?;
+    let directory = path.parent().unwrap_or(Path::new(""));
+
+    let mut current: Option<(String, MtlEntry)> = None;
+
+    for (line_idx, line) in contents.lines().enumerate() {
+        let line_number = line_idx + 1;
+        let error = |message: String| parse_error(&name, line_number, message);
+
+        let line = line.split('#').next().unwrap_or_default();
+        let mut tokens = line.split_whitespace();
+
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+
+        if keyword == "newmtl" {
+            let material_name = tokens
+                .next()
+                .ok_or_else(|| error("missing material name".to_string()))?;
+            if let Some((name, entry)) = current.take() {
+                materials.insert(name, Arc::new(entry.into_material()));
+            }
+            current = Some((material_name.to_string(), MtlEntry::default()));
+            continue;
+        }
+
+        let Some((_, entry)) = current.as_mut() else {
+            return Err(error(format!("'{keyword}' before any newmtl")));
+        };
+
+        match keyword {
+            "Kd" => {
+                let [r, g, b] = parse_floats(&mut tokens, keyword).map_err(error)?;
+                entry.diffuse = Color::new(r, g, b);
+            }
+            "Ks" => {
+                let [r, g, b] = parse_floats(&mut tokens, keyword).map_err(error)?;
+                entry.specular = Color::new(r, g, b);
+            }
+            "Ke" => {
+                let [r, g, b] = parse_floats(&mut tokens, keyword).map_err(error)?;
+                entry.emission = Color::new(r, g, b);
+            }
+            "Ns" => entry.shininess = parse_float(tokens.next(), keyword).map_err(error)?,
+            "Ni" => entry.ior = Some(parse_float(tokens.next(), keyword).map_err(error)?),
+            "d" => entry.dissolve = parse_float(tokens.next(), keyword).map_err(error)?,
+            "Tr" => entry.dissolve = 1.0 - parse_float(tokens.next(), keyword).map_err(error)?,
+            "illum" => {
+                let illum = tokens
+                    .next()
+                    .and_then(|token| u32::from_str(token).ok())
+                    .ok_or_else(|| error("expected illumination model number".to_string()))?;
+                entry.illum = illum;
+            }
+            "map_Kd" => {
+                // Options like -s or -o are not supported, the file name is the last token
+                let file = tokens
+                    .last()
+                    .ok_or_else(|| error("missing texture file name".to_string()))?;
+                let texture_path = directory.join(file);
+
+                let texture = match textures.get(&texture_path) {
+                    Some(texture) => texture.clone(),
+                    None => {
+                        let image = Image::load(&texture_path.to_string_lossy())
+                            .map_err(|e| error(format!("failed to load '{file}': {e}")))?;
+                        let texture = Arc::new(Texture::Image(image));
+                        textures.insert(texture_path, texture.clone());
+                        texture
+                    }
+                };
+
+                entry.diffuse_map = Some(texture);
+            }
+            // Ambient and the remaining maps have no equivalent material yet
+            _ => {}
+        }
+    }
+
+    if let Some((name, entry)) = current.take() {
+        materials.insert(name, Arc::new(entry.into_material()));
+    }
+
+    Ok(())
+}
+
+struct MtlEntry {
+    diffuse: Color,
+    specular: Color,
+    emission: Color,
+    shininess: f32,
+    ior: Option<f32>,
+    dissolve: f32,
+    illum: u32,
+    diffuse_map: Option<Arc<Texture>>,
+}
+
+impl Default for MtlEntry {
+    fn default() -> Self {
+        MtlEntry {
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            specular: Color::new(0.0, 0.0, 0.0),
+            emission: Color::new(0.0, 0.0, 0.0),
+            shininess: 0.0,
+            ior: None,
+            dissolve: 1.0,
+            illum: 2,
+            diffuse_map: None,
+        }
+    }
+}
+
+impl MtlEntry {
+    fn into_material(self) -> Material {
+        if self.emission.r > 0.0 || self.emission.g > 0.0 || self.emission.b > 0.0 {
+            return Material::DiffuseLight(Arc::new(Texture::from_color(self.emission)));
+        }
+
+        // Illumination models 4, 6, 7 and 9 are the transparent/refractive ones
+        let transparent = self.dissolve < 1.0 || matches!(self.illum, 4 | 6 | 7 | 9);
+        if transparent {
+            return Material::Dielectric(self.ior.unwrap_or(1.5));
+        }
+
+        let luminance = |c: Color| 0.2126 * c.r + 0.7152 * c.g + 0.0722 * c.b;
+        let reflective = self.illum == 3 || luminance(self.specular) > luminance(self.diffuse);
+        if reflective && self.diffuse_map.is_none() && luminance(self.specular) > 0.0 {
+            // Map the Phong exponent to a fuzz radius, sharp highlights give polished metal
+            let fuzz = (2.0 / (self.shininess + 2.0)).sqrt().clamp(0.0, 1.0);
+            return Material::Metal(self.specular, fuzz);
+        }
+
+        let texture = self
+            .diffuse_map
+            .unwrap_or_else(|| Arc::new(Texture::from_color(self.diffuse)));
+        Material::Lambertian(texture)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct Corner {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+// Collects triangles for the current group and material, deduplicating vertices
+struct MeshBuilder {
+    material: Arc<Material>,
+    vertices: HashMap<Corner, u32>,
+    positions: Vec<Point>,
+    uvs: Vec<Option<(f32, f32)>>,
+    normals: Vec<Option<Vec3>>,
+    indices: Vec<[u32; 3]>,
+}
+
+impl MeshBuilder {
+    fn new(material: Arc<Material>) -> Self {
+        MeshBuilder {
+            material,
+            vertices: HashMap::new(),
+            positions: Vec::new(),
+            uvs: Vec::new(),
+            normals: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    fn push_triangle(
+        &mut self,
+        corners: [Corner; 3],
+        positions: &[Point],
+        uvs: &[(f32, f32)],
+        normals: &[Vec3],
+    ) {
+        let indices = corners.map(|corner| {
+            *self.vertices.entry(corner).or_insert_with(|| {
+                self.positions.push(positions[corner.position]);
+                self.uvs.push(corner.uv.map(|idx| uvs[idx]));
+                self.normals.push(corner.normal.map(|idx| normals[idx]));
+                (self.positions.len() - 1) as u32
+            })
+        });
+
+        self.indices.push(indices);
+    }
+
+    fn flush(&mut self, world: &mut Vec<Primitive>) {
+        if self.indices.is_empty() {
+            return;
+        }
+
+        // Attributes are only kept if every vertex of the mesh has them
+        let uvs = std::mem::take(&mut self.uvs).into_iter().collect();
+        let normals = std::mem::take(&mut self.normals).into_iter().collect();
+
+        let mesh = Arc::new(Mesh::new(
+            std::mem::take(&mut self.positions),
+            std::mem::take(&mut self.indices),
+            normals,
+            uvs,
+        ));
+        self.vertices.clear();
+
+        world.append(&mut Mesh::primitives(&mesh, self.material.clone()));
+    }
+}
+
+// Parses "v", "v/vt", "v//vn" or "v/vt/vn", 1-based with negative indices relative to the end
+fn parse_corner(
+    token: &str,
+    position_count: usize,
+    uv_count: usize,
+    normal_count: usize,
+) -> Result<Corner, String> {
+    let mut parts = token.split('/');
+
+    let resolve = |part: Option<&str>, count: usize, name: &str| -> Result<Option<usize>, String> {
+        match part {
+            None | Some("") => Ok(None),
+            Some(part) => {
+                let idx = i64::from_str(part)
+                    .map_err(|_| format!("invalid {name} index '{part}' in '{token}'"))?;
+                let resolved = if idx < 0 { count as i64 + idx } else { idx - 1 };
+                if resolved < 0 || resolved >= count as i64 {
+                    return Err(format!(
+                        "{name} index {idx} out of range, {count} defined so far"
+                    ));
+                }
+                Ok(Some(resolved as usize))
+            }
+        }
+    };
+
+    let position = resolve(parts.next(), position_count, "vertex")?
+        .ok_or_else(|| format!("missing vertex index in '{token}'"))?;
+    let uv = resolve(parts.next(), uv_count, "texture coordinate")?;
+    let normal = resolve(parts.next(), normal_count, "normal")?;
+
+    if parts.next().is_some() {
+        return Err(format!("too many indices in '{token}'"));
+    }
+
+    Ok(Corner {
+        position,
+        uv,
+        normal,
+    })
+}
+
+fn parse_float(token: Option<&str>, keyword: &str) -> Result<f32, String> {
+    let token = token.ok_or_else(|| format!("missing value for '{keyword}'"))?;
+    f32::from_str(token).map_err(|_| format!("invalid number '{token}' for '{keyword}'"))
+}
+
+fn parse_floats(tokens: &mut SplitWhitespace, keyword: &str) -> Result<[f32; 3], String> {
+    Ok([
+        parse_float(tokens.next(), keyword)?,
+        parse_float(tokens.next(), keyword)?,
+        parse_float(tokens.next(), keyword)?,
+    ])
+}
+
+fn parse_error(path: &str, line: usize, message: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, format!("{path}:{line}: {message}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use super::load;
+
+    // Writes the source to a temporary file for the loader, which reads from paths
+    fn load_source(name: &str, source: &str) -> Result<usize, (ErrorKind, String)> {
+        let path =
+            std::env::temp_dir().join(format!("weekend-rt-{}-{name}.obj", std::process::id()));
+        std::fs::write(&path, source).unwrap();
+        let path = path.to_string_lossy().into_owned();
+        let world = load(&path);
+        std::fs::remove_file(&path).unwrap();
+        world
+            .map(|world| world.len())
+            .map_err(|err| (err.kind(), err.to_string().replace(&path, "model.obj")))
+    }
+
+    #[test]
+    fn polygons_are_fan_triangulated() {
+        let vertices = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0.5 2 0\nv 0 1 0\n";
+        let quad = format!("{vertices}f 1 2 3 5\n");
+        let pentagon = format!("{vertices}f 1 2 3 4 5\n");
+        // Relative indices, and polygons mixed with triangles in one group
+        let mixed = format!("{vertices}f -5 -4 -3 -1\nf 1 2 3\ng other\nf 1 2 3 4 5\n");
+
+        assert_eq!(load_source("quad", &quad), Ok(2));
+        assert_eq!(load_source("pentagon", &pentagon), Ok(3));
+        assert_eq!(load_source("mixed", &mixed), Ok(6));
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let vertices = "# a square\nv 0 0 0\nv 1 0 0\n\nv 1 1 0\nv 0 1 0\n";
+        for (face, message) in [
+            ("f 1 2", "face needs at least 3 vertices, found 2"),
+            ("f 1 2 x", "invalid vertex index 'x' in 'x'"),
+            ("f 1 2 9", "vertex index 9 out of range, 4 defined so far"),
+            (
+                "f 1/1 2/1 3/1",
+                "texture coordinate index 1 out of range, 0 defined so far",
+            ),
+            ("f 1/// 2 3", "too many indices in '1///'"),
+        ] {
+            let source = format!("{vertices}f 1 2 3\n{face}\nf 1 3 4\n");
+            assert_eq!(
+                load_source("malformed", &source),
+                Err((ErrorKind::InvalidData, format!("model.obj:8: {message}"))),
+            );
+        }
+
+        let source = format!("{vertices}v 1 nope 0\n");
+        assert_eq!(
+            load_source("vertex", &source),
+            Err((
+                ErrorKind::InvalidData,
+                "model.obj:7: invalid number 'nope' for 'v'".to_string()
+            )),
+        );
+    }
+}
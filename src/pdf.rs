@@ -0,0 +1,163 @@
+use std::f32::consts::PI;
+
+use rand::Rng;
+
+use crate::{interval::Interval, point::Point, primitive::Primitive, vec3::Vec3};
+
+// Distributions of directions to continue paths along, with their densities over solid angle
+// https://raytracing.github.io/books/RayTracingTheRestOfYourLife.html#generatingrandomdirections
+#[derive(Debug)]
+pub(crate) enum Pdf<'a> {
+    // Cosine weighted around a unit normal, for diffuse surfaces
+    Cosine(Vec3),
+
+    // Uniform over all directions, for isotropic media
+    Sphere,
+
+    // Toward the primitives as seen from origin at a moment, each one picked with the same chance
+    Primitives {
+        primitives: &'a [&'a Primitive],
+        origin: Point,
+        time: f32,
+    },
+
+    // Either of the two with the same chance
+    Mixture(&'a Pdf<'a>, &'a Pdf<'a>),
+}
+
+impl Pdf<'_> {
+    pub(crate) fn value(&self, direction: Vec3) -> f32 {
+        match self {
+            Pdf::Cosine(normal) => (direction.unit().dot(*normal) / PI).max(0.0),
+            Pdf::Sphere => 1.0 / (4.0 * PI),
+            Pdf::Primitives {
+                primitives,
+                origin,
+                time,
+            } => {
+                primitives
+                    .iter()
+                    .map(|primitive| {
+                        primitive.pdf_value(
+                            *origin,
+                            direction,
+                            *time,
+                            Interval::new(0.001, f32::INFINITY),
+                        )
+                    })
+                    .sum::<f32>()
+                    / primitives.len() as f32
+            }
+            Pdf::Mixture(a, b) => 0.5 * a.value(direction) + 0.5 * b.value(direction),
+        }
+    }
+
+    // Directions aren't unit length
+    pub(crate) fn generate<R: Rng>(&self, rng: &mut R) -> Vec3 {
+        match self {
+            Pdf::Cosine(normal) => {
+                // A point on the unit sphere touching the surface, offset by the normal
+                let direction = *normal + Vec3::random_unit_vector(rng);
+                if direction.near_zero() {
+                    *normal
+                } else {
+                    direction
+                }
+            }
+            Pdf::Sphere => Vec3::random_unit_vector(rng),
+            Pdf::Primitives {
+                primitives,
+                origin,
+                time,
+            } => {
+                let idx = rng.random_range(0..primitives.len());
+                primitives[idx].random(rng, *origin, *time)
+            }
+            Pdf::Mixture(a, b) => {
+                if rng.random_bool(0.5) {
+                    a.generate(rng)
+                } else {
+                    b.generate(rng)
+                }
+            }
+        }
+    }
+}
+
+// Weight of a sample drawn with density pdf when the other strategy could also have drawn it with
+// density other, the weights of both strategies add up to one
+// https://pbr-book.org/4ed/Monte_Carlo_Integration/Improving_Efficiency#MultipleImportanceSampling
+pub(crate) fn power_heuristic(pdf: f32, other: f32) -> f32 {
+    let (pdf, other) = (pdf * pdf, other * other);
+    if pdf + other > 0.0 {
+        pdf / (pdf + other)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{f32::consts::PI, sync::Arc};
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::Pdf;
+    use crate::{
+        color::Color,
+        material::Material,
+        point::Point,
+        primitive::{Primitive, QuadParams, SphereParams},
+        texture::Texture,
+        vec3::Vec3,
+    };
+
+    // Every density integrates to one over the sphere of directions, and gives the directions it
+    // generates a non-zero density
+    #[test]
+    fn pdfs_integrate_to_one() {
+        let light = Arc::new(Material::DiffuseLight(Arc::new(Texture::from_color(
+            Color::new(1.0, 1.0, 1.0),
+        ))));
+        let quad = Primitive::Quad(QuadParams::new(
+            Point::new(-1.0, 2.0, -1.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.5, 2.0),
+            light.clone(),
+        ));
+        let sphere = Primitive::Sphere(SphereParams::new(Point::new(2.0, 0.0, 1.0), 1.5, light));
+        let lights = [&quad, &sphere];
+
+        let normal = Vec3::new(1.0, 2.0, 2.0).unit();
+        let toward = |primitives| Pdf::Primitives {
+            primitives,
+            origin: Point::new(0.0, 0.0, 0.0),
+            time: 0.0,
+        };
+        let (cosine, both) = (Pdf::Cosine(normal), toward(&lights[..]));
+
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        for pdf in [
+            Pdf::Sphere,
+            Pdf::Cosine(normal),
+            toward(&lights[..1]),
+            toward(&lights[1..]),
+            Pdf::Mixture(&cosine, &both),
+        ] {
+            let samples = 200_000;
+            let integral = (0..samples)
+                .map(|_| pdf.value(Vec3::random_unit_vector(&mut rng)))
+                .sum::<f32>()
+                * 4.0
+                * PI
+                / samples as f32;
+            assert!((integral - 1.0).abs() < 0.03, "{pdf:?}: {integral}");
+
+            for _ in 0..1000 {
+                let direction = pdf.generate(&mut rng);
+                assert!(pdf.value(direction) > 0.0, "{pdf:?}: {direction:?}");
+            }
+        }
+    }
+}
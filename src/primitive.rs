@@ -1,13 +1,27 @@
 use std::{f32::consts::PI, sync::Arc};
 
+use rand::Rng;
+
 use crate::{
-    aabb::Aabb, hittable::HitRecord, interval::Interval, material::Material, point::Point,
-    ray::Ray, vec3::Vec3,
+    aabb::Aabb,
+    hittable::HitRecord,
+    instance::{Geometry, InstanceParams},
+    interval::Interval,
+    material::Material,
+    medium::{Density, MediumParams},
+    mesh::Mesh,
+    point::Point,
+    ray::Ray,
+    transform::Transform,
+    vec3::Vec3,
 };
 
 #[derive(Debug)]
 pub(crate) struct SphereParams {
+    // Center at time 0
     pub(crate) center: Point,
+    // Offset of the center at time 1, zero for spheres that stay put
+    pub(crate) motion: Vec3,
     pub(crate) radius: f32,
     pub(crate) material: Arc<Material>,
     pub(crate) aabb: Aabb,
@@ -23,40 +37,42 @@ pub(crate) struct QuadParams {
     pub(crate) aabb: Aabb,
     pub(crate) normal: Vec3,
     pub(crate) d: f32,
+    pub(crate) area: f32,
+}
+
+#[derive(Debug)]
+pub(crate) struct TriangleParams {
+    pub(crate) mesh: Arc<Mesh>,
+    pub(crate) index: usize,
+    pub(crate) material: Arc<Material>,
+    pub(crate) aabb: Aabb,
 }
 
 #[derive(Debug)]
 pub(crate) enum Primitive {
     Sphere(SphereParams),
     Quad(QuadParams),
+    Triangle(TriangleParams),
+    // Boxed so the transforms don't grow every other primitive
+    Instance(Box<InstanceParams>),
+    // Volume inside a boundary, scatters at a random depth instead of at a surface
+    Medium(Box<MediumParams>),
 }
 
 impl Primitive {
-    pub(crate) fn hit(&self, ray: Ray, ray_inteval: Interval) -> Option<HitRecord> {
+    // Only media draw from rng, surfaces are hit the same way every time
+    pub(crate) fn hit<R: Rng>(
+        &self,
+        rng: &mut R,
+        ray: Ray,
+        ray_inteval: Interval,
+    ) -> Option<HitRecord> {
         match self {
             Primitive::Sphere(params) => {
-                let ray_to_sphere = params.center - ray.origin;
-                let a = ray.direction.length_squared();
-                let h = ray.direction.dot(ray_to_sphere);
-                let c = ray_to_sphere.length_squared() - params.radius * params.radius;
-                let discriminant = h * h - a * c;
-
-                if discriminant < 0.0 {
-                    return None;
-                }
-                let sqrt_d = discriminant.sqrt();
-
-                // Find nearest root that lies in the acceptable range of ray_tmin..ray_tmax
-                let mut root = (h - sqrt_d) / a;
-                if !ray_inteval.surrounds(root) {
-                    root = (h + sqrt_d) / a;
-                    if !ray_inteval.surrounds(root) {
-                        return None;
-                    }
-                }
+                let root = params.intersect(ray, ray_inteval)?;
 
                 let position = ray.at(root);
-                let outward_normal = (position - params.center) / params.radius;
+                let outward_normal = (position - params.center_at(ray.time)) / params.radius;
 
                 let (u, v) = Self::get_sphere_uv(Point::from(outward_normal));
 
@@ -70,42 +86,61 @@ impl Primitive {
                 ))
             }
             Primitive::Quad(params) => {
-                let denominator = params.normal.dot(ray.direction);
+                let (t, alpha, beta) = params.intersect(ray, ray_inteval)?;
 
-                // Check if parallell to plane
-                if denominator.abs() < 1e-8 {
-                    return None;
-                }
+                // Normal has to face the incoming ray, otherwise light scatters through the quad
+                Some(HitRecord::new(
+                    ray,
+                    t,
+                    alpha,
+                    beta,
+                    params.normal,
+                    params.material.clone(),
+                ))
+            }
+            Primitive::Triangle(params) => {
+                let (t, b0, b1, b2) = params.intersect(ray, ray_inteval)?;
 
-                let t = (params.d - params.normal.dot(ray.origin.into())) / denominator;
-                if !ray_inteval.contains(t) {
-                    return None;
-                }
+                let [p0, p1, p2] = params.mesh.vertices(params.index);
+                let geometric_normal = (p1 - p0).cross(p2 - p0).unit();
 
-                // Ray intersection with plane
-                let intersection = ray.at(t);
-                let planar_hitpoint_vector = intersection - params.q;
-                let alpha = params.w.dot(planar_hitpoint_vector.cross(params.v));
-                let beta = params.w.dot(params.u.cross(planar_hitpoint_vector));
+                // Without texture coordinates the barycentrics of p1 and p2 are used as u, v
+                let (u, v) = params
+                    .mesh
+                    .texture_uv(params.index, b0, b1, b2)
+                    .unwrap_or((b1, b2));
 
-                // println!("{alpha},{beta}");
+                let mut hit =
+                    HitRecord::new(ray, t, u, v, geometric_normal, params.material.clone());
 
-                if !is_interior(alpha, beta) {
-                    return None;
+                // Facing is decided by the geometric normal, the interpolated normal only shades
+                if let Some(shading_normal) = params.mesh.shading_normal(params.index, b0, b1, b2)
+                    && !shading_normal.near_zero()
+                {
+                    let shading_normal = shading_normal.unit();
+                    hit.normal = if shading_normal.dot(hit.normal) < 0.0 {
+                        -shading_normal
+                    } else {
+                        shading_normal
+                    };
                 }
 
-                let front_face = params.normal.dot(ray.direction).is_sign_positive();
-
-                Some(HitRecord {
-                    position: intersection,
-                    normal: params.normal,
-                    material: params.material.clone(),
-                    t,
-                    u: alpha,
-                    v: beta,
-                    front_face,
-                })
+                Some(hit)
             }
+            Primitive::Instance(params) => params.hit(rng, ray, ray_inteval),
+            Primitive::Medium(params) => params.hit(rng, ray, ray_inteval),
+        }
+    }
+
+    // Any hit inside the interval, skips building a HitRecord
+    // Media block a ray only where it scatters, so this is random for them as well
+    pub(crate) fn occluded<R: Rng>(&self, rng: &mut R, ray: Ray, ray_interval: Interval) -> bool {
+        match self {
+            Primitive::Sphere(params) => params.intersect(ray, ray_interval).is_some(),
+            Primitive::Quad(params) => params.intersect(ray, ray_interval).is_some(),
+            Primitive::Triangle(params) => params.intersect(ray, ray_interval).is_some(),
+            Primitive::Instance(params) => params.occluded(rng, ray, ray_interval),
+            Primitive::Medium(params) => params.occluded(rng, ray, ray_interval),
         }
     }
 
@@ -113,10 +148,176 @@ impl Primitive {
         match self {
             Primitive::Sphere(params) => params.bounding_box(),
             Primitive::Quad(params) => params.bounding_box(),
+            Primitive::Triangle(params) => params.bounding_box(),
+            Primitive::Instance(params) => params.bounding_box(),
+            Primitive::Medium(params) => params.bounding_box(),
+        }
+    }
+
+    // Emitters that can be sampled directly, paths are steered toward them
+    pub(crate) fn lights(world: &[Primitive]) -> Vec<&Primitive> {
+        world
+            .iter()
+            .filter(|primitive| match primitive {
+                Primitive::Sphere(params) => matches!(*params.material, Material::DiffuseLight(_)),
+                Primitive::Quad(params) => matches!(*params.material, Material::DiffuseLight(_)),
+                _ => false,
+            })
+            .collect()
+    }
+
+    // Every material that can show up in a hit once, in the order they first turn up in world.
+    // Medium boundaries are never hit, their phase function is
+    pub(crate) fn materials(world: &[Primitive]) -> Vec<&Arc<Material>> {
+        fn collect<'a>(primitives: &'a [Primitive], materials: &mut Vec<&'a Arc<Material>>) {
+            for primitive in primitives {
+                let material = match primitive {
+                    Primitive::Sphere(params) => &params.material,
+                    Primitive::Quad(params) => &params.material,
+                    Primitive::Triangle(params) => &params.material,
+                    Primitive::Instance(params) => {
+                        collect(params.geometry.primitives(), materials);
+                        continue;
+                    }
+                    Primitive::Medium(params) => params.phase(),
+                };
+                if !materials.iter().any(|seen| Arc::ptr_eq(seen, material)) {
+                    materials.push(material);
+                }
+            }
+        }
+
+        let mut materials = Vec::new();
+        collect(world, &mut materials);
+        materials
+    }
+
+    // Density over solid angle of random() picking direction from origin, zero for primitives
+    // that can't be sampled or aren't hit within ray_interval
+    pub(crate) fn pdf_value(
+        &self,
+        origin: Point,
+        direction: Vec3,
+        time: f32,
+        ray_interval: Interval,
+    ) -> f32 {
+        let ray = Ray::new(origin, direction, time);
+
+        match self {
+            Primitive::Sphere(params) => {
+                if params.intersect(ray, ray_interval).is_none() {
+                    return 0.0;
+                }
+                match params.cos_theta_max(origin, time) {
+                    Some(cos_theta_max) => 1.0 / (2.0 * PI * (1.0 - cos_theta_max)),
+                    None => 1.0 / (4.0 * PI),
+                }
+            }
+            Primitive::Quad(params) => {
+                let Some((t, _, _)) = params.intersect(ray, ray_interval) else {
+                    return 0.0;
+                };
+                let distance_squared = t * t * direction.length_squared();
+                let cosine = (direction.dot(params.normal) / direction.length()).abs();
+                distance_squared / (cosine * params.area)
+            }
+            _ => 0.0,
+        }
+    }
+
+    // Direction from origin toward a random point of the primitive
+    pub(crate) fn random<R: Rng>(&self, rng: &mut R, origin: Point, time: f32) -> Vec3 {
+        match self {
+            // Uniform over the cone the sphere fills, any direction from inside it
+            Primitive::Sphere(params) => {
+                let Some(cos_theta_max) = params.cos_theta_max(origin, time) else {
+                    return Vec3::random_unit_vector(rng);
+                };
+                let w = (params.center_at(time) - origin).unit();
+                let (u, v) = w.orthonormal_basis();
+
+                let phi = 2.0 * PI * rng.random::<f32>();
+                let cos_theta = 1.0 + rng.random::<f32>() * (cos_theta_max - 1.0);
+                let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+                u * (phi.cos() * sin_theta) + v * (phi.sin() * sin_theta) + w * cos_theta
+            }
+            // Uniform over the area
+            Primitive::Quad(params) => {
+                let p = params.q + params.u * rng.random::<f32>() + params.v * rng.random::<f32>();
+                p - origin
+            }
+            _ => Vec3::random_unit_vector(rng),
         }
     }
 
-    fn get_sphere_uv(p: Point) -> (f32, f32) {
+    pub(crate) fn new_instance(geometry: Arc<Geometry>, transform: Transform) -> Primitive {
+        Primitive::new_moving_instance(geometry, transform, Vec3::new(0.0, 0.0, 0.0))
+    }
+
+    // Moves by motion between time 0 and 1, any geometry can be blurred this way
+    pub(crate) fn new_moving_instance(
+        geometry: Arc<Geometry>,
+        transform: Transform,
+        motion: Vec3,
+    ) -> Primitive {
+        Primitive::Instance(Box::new(InstanceParams::new(geometry, transform, motion)))
+    }
+
+    // Fills the boundary with a medium, scattering by the phase material
+    pub(crate) fn new_medium(
+        boundary: Arc<Geometry>,
+        density: Density,
+        phase: Arc<Material>,
+    ) -> Primitive {
+        Primitive::Medium(Box::new(MediumParams::new(boundary, density, phase)))
+    }
+
+    // Axis aligned box spanned by two opposite corners, built from six quads
+    pub(crate) fn new_box(a: Point, b: Point, material: Arc<Material>) -> Vec<Primitive> {
+        let min = Point::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+        let max = Point::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+
+        let dx = Vec3::new(max.x - min.x, 0.0, 0.0);
+        let dy = Vec3::new(0.0, max.y - min.y, 0.0);
+        let dz = Vec3::new(0.0, 0.0, max.z - min.z);
+
+        vec![
+            // front
+            Primitive::Quad(QuadParams::new(
+                Point::new(min.x, min.y, max.z),
+                dx,
+                dy,
+                material.clone(),
+            )),
+            // right
+            Primitive::Quad(QuadParams::new(
+                Point::new(max.x, min.y, max.z),
+                -dz,
+                dy,
+                material.clone(),
+            )),
+            // back
+            Primitive::Quad(QuadParams::new(
+                Point::new(max.x, min.y, min.z),
+                -dx,
+                dy,
+                material.clone(),
+            )),
+            // left
+            Primitive::Quad(QuadParams::new(min, dz, dy, material.clone())),
+            // top
+            Primitive::Quad(QuadParams::new(
+                Point::new(min.x, max.y, max.z),
+                dx,
+                -dz,
+                material.clone(),
+            )),
+            // bottom
+            Primitive::Quad(QuadParams::new(min, dx, dz, material)),
+        ]
+    }
+
+    pub(crate) fn get_sphere_uv(p: Point) -> (f32, f32) {
         // p: a given point on the sphere of radius one, centered at the origin.
         // u: returned value [0,1] of angle around the Y axis from X=-1.
         // v: returned value [0,1] of angle from Y=-1 to Y=+1.
@@ -137,12 +338,66 @@ impl SphereParams {
         let radius_vector = Vec3::new(radius, radius, radius);
         SphereParams {
             center,
+            motion: Vec3::new(0.0, 0.0, 0.0),
             radius,
             material,
             aabb: Aabb::new_between(center - radius_vector, center + radius_vector),
         }
     }
 
+    // Center moves linearly from start at time 0 to end at time 1
+    pub(crate) fn moving(
+        start: Point,
+        end: Point,
+        radius: f32,
+        material: Arc<Material>,
+    ) -> SphereParams {
+        let mut sphere = SphereParams::new(start, radius, material);
+        let radius_vector = Vec3::new(sphere.radius, sphere.radius, sphere.radius);
+        sphere.motion = end - start;
+        sphere
+            .aabb
+            .expand(&Aabb::new_between(end - radius_vector, end + radius_vector));
+        sphere
+    }
+
+    pub(crate) fn center_at(&self, time: f32) -> Point {
+        self.center + displacement(self.motion, time)
+    }
+
+    // Cosine of the half angle of the cone the sphere fills seen from origin, None from inside
+    fn cos_theta_max(&self, origin: Point, time: f32) -> Option<f32> {
+        let distance_squared = (self.center_at(time) - origin).length_squared();
+        let radius_squared = self.radius * self.radius;
+        (distance_squared > radius_squared)
+            .then(|| (1.0 - radius_squared / distance_squared).sqrt())
+    }
+
+    // Nearest root inside the interval
+    fn intersect(&self, ray: Ray, ray_interval: Interval) -> Option<f32> {
+        let ray_to_sphere = self.center_at(ray.time) - ray.origin;
+        let a = ray.direction.length_squared();
+        let h = ray.direction.dot(ray_to_sphere);
+        let c = ray_to_sphere.length_squared() - self.radius * self.radius;
+        let discriminant = h * h - a * c;
+
+        if discriminant < 0.0 {
+            return None;
+        }
+        let sqrt_d = discriminant.sqrt();
+
+        // Find nearest root that lies in the acceptable range of ray_tmin..ray_tmax
+        let mut root = (h - sqrt_d) / a;
+        if !ray_interval.surrounds(root) {
+            root = (h + sqrt_d) / a;
+            if !ray_interval.surrounds(root) {
+                return None;
+            }
+        }
+
+        Some(root)
+    }
+
     fn bounding_box(&self) -> &Aabb {
         &self.aabb
     }
@@ -166,7 +421,35 @@ impl QuadParams {
             aabb,
             normal,
             d,
+            area: n.length(),
+        }
+    }
+
+    // Returns t and the planar coordinates of the hit along u and v
+    fn intersect(&self, ray: Ray, ray_interval: Interval) -> Option<(f32, f32, f32)> {
+        let denominator = self.normal.dot(ray.direction);
+
+        // Check if parallell to plane
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = (self.d - self.normal.dot(ray.origin.into())) / denominator;
+        if !ray_interval.contains(t) {
+            return None;
         }
+
+        // Ray intersection with plane
+        let intersection = ray.at(t);
+        let planar_hitpoint_vector = intersection - self.q;
+        let alpha = self.w.dot(planar_hitpoint_vector.cross(self.v));
+        let beta = self.w.dot(self.u.cross(planar_hitpoint_vector));
+
+        if !is_interior(alpha, beta) {
+            return None;
+        }
+
+        Some((t, alpha, beta))
     }
 
     fn bounding_box(&self) -> &Aabb {
@@ -174,8 +457,207 @@ impl QuadParams {
     }
 }
 
+impl TriangleParams {
+    pub(crate) fn new(mesh: Arc<Mesh>, index: usize, material: Arc<Material>) -> TriangleParams {
+        let [p0, p1, p2] = mesh.vertices(index);
+        let mut aabb = Aabb::new_between(p0, p1);
+        aabb.expand(&Aabb::new_between(p2, p2));
+
+        TriangleParams {
+            mesh,
+            index,
+            material,
+            aabb,
+        }
+    }
+
+    // Standalone triangle, backed by its own single face mesh
+    pub(crate) fn from_points(p0: Point, p1: Point, p2: Point, material: Arc<Material>) -> Self {
+        let mesh = Mesh::new(vec![p0, p1, p2], vec![[0, 1, 2]], None, None);
+        TriangleParams::new(Arc::new(mesh), 0, material)
+    }
+
+    fn bounding_box(&self) -> &Aabb {
+        &self.aabb
+    }
+
+    // Watertight ray/triangle intersection (Woop, Benthin, Wald 2013)
+    // Returns t and the barycentric weights of the three vertices
+    fn intersect(&self, ray: Ray, ray_interval: Interval) -> Option<(f32, f32, f32, f32)> {
+        let direction = [ray.direction.x, ray.direction.y, ray.direction.z];
+
+        // Permute axes so that z is the dominant ray direction, swap x/y to keep winding
+        let kz = if direction[0].abs() > direction[1].abs() {
+            if direction[0].abs() > direction[2].abs() {
+                0
+            } else {
+                2
+            }
+        } else if direction[1].abs() > direction[2].abs() {
+            1
+        } else {
+            2
+        };
+        let mut kx = (kz + 1) % 3;
+        let mut ky = (kx + 1) % 3;
+        if direction[kz] < 0.0 {
+            std::mem::swap(&mut kx, &mut ky);
+        }
+
+        // Shear constants
+        let sx = direction[kx] / direction[kz];
+        let sy = direction[ky] / direction[kz];
+        let sz = 1.0 / direction[kz];
+
+        let relative = |p: Point| {
+            let p = p - ray.origin;
+            [p.x, p.y, p.z]
+        };
+        let [p0, p1, p2] = self.mesh.vertices(self.index);
+        let (a, b, c) = (relative(p0), relative(p1), relative(p2));
+
+        let ax = a[kx] - sx * a[kz];
+        let ay = a[ky] - sy * a[kz];
+        let bx = b[kx] - sx * b[kz];
+        let by = b[ky] - sy * b[kz];
+        let cx = c[kx] - sx * c[kz];
+        let cy = c[ky] - sy * c[kz];
+
+        // Scaled barycentric coordinates (edge functions)
+        let mut e0 = cx * by - cy * bx;
+        let mut e1 = ax * cy - ay * cx;
+        let mut e2 = bx * ay - by * ax;
+
+        // Fall back to double precision on edges so that neighbouring triangles never leak rays
+        if e0 == 0.0 || e1 == 0.0 || e2 == 0.0 {
+            e0 = (cx as f64 * by as f64 - cy as f64 * bx as f64) as f32;
+            e1 = (ax as f64 * cy as f64 - ay as f64 * cx as f64) as f32;
+            e2 = (bx as f64 * ay as f64 - by as f64 * ax as f64) as f32;
+        }
+
+        if (e0 < 0.0 || e1 < 0.0 || e2 < 0.0) && (e0 > 0.0 || e1 > 0.0 || e2 > 0.0) {
+            return None;
+        }
+
+        let det = e0 + e1 + e2;
+        if det == 0.0 {
+            return None;
+        }
+
+        // A ray exactly on an edge belongs to only one of the triangles sharing it. Neighbours with
+        // the same winding run the edge in opposite directions, so a rule on the direction picks
+        // one of them (the top-left rule of rasterizers)
+        let owns = |e: f32, dx: f32, dy: f32| {
+            let (dx, dy) = if det > 0.0 { (dx, dy) } else { (-dx, -dy) };
+            e != 0.0 || dy < 0.0 || (dy == 0.0 && dx > 0.0)
+        };
+        if !(owns(e0, cx - bx, cy - by) && owns(e1, ax - cx, ay - cy) && owns(e2, bx - ax, by - ay))
+        {
+            return None;
+        }
+
+        let az = sz * a[kz];
+        let bz = sz * b[kz];
+        let cz = sz * c[kz];
+        let t = (e0 * az + e1 * bz + e2 * cz) / det;
+
+        if !ray_interval.surrounds(t) {
+            return None;
+        }
+
+        Some((t, e0 / det, e1 / det, e2 / det))
+    }
+}
+
+// Linear motion from time 0 to 1, held at either end outside that range so bounding boxes over
+// both ends stay valid for any shutter
+pub(crate) fn displacement(motion: Vec3, time: f32) -> Vec3 {
+    motion * time.clamp(0.0, 1.0)
+}
+
 pub(crate) fn is_interior(a: f32, b: f32) -> bool {
     let unit_interval = Interval::new(0.0, 1.0);
 
     unit_interval.contains(a) && unit_interval.contains(b)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    use super::Primitive;
+    use crate::{
+        interval::Interval, material::Material, mesh::Mesh, point::Point, ray::Ray, vec3::Vec3,
+    };
+
+    // Triangles of a grid of quads in the z = 0 plane, each split along its diagonal
+    fn grid(size: u32) -> Vec<Primitive> {
+        let positions = (0..=size)
+            .flat_map(|y| (0..=size).map(move |x| Point::new(x as f32, y as f32, 0.0)))
+            .collect();
+        let corner = |x: u32, y: u32| y * (size + 1) + x;
+        let indices = (0..size)
+            .flat_map(|y| (0..size).map(move |x| (x, y)))
+            .flat_map(|(x, y)| {
+                [
+                    [corner(x, y), corner(x + 1, y), corner(x + 1, y + 1)],
+                    [corner(x, y), corner(x + 1, y + 1), corner(x, y + 1)],
+                ]
+            })
+            .collect();
+        let mesh = Arc::new(Mesh::new(positions, indices, None, None));
+        Mesh::primitives(&mesh, Arc::new(Material::Dielectric(1.5)))
+    }
+
+    fn hit_count(triangles: &[Primitive], ray: Ray) -> usize {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let interval = Interval::new(0.001, f32::INFINITY);
+        triangles
+            .iter()
+            .filter(|triangle| triangle.hit(&mut rng, ray, interval).is_some())
+            .count()
+    }
+
+    #[test]
+    fn shared_edges_are_hit_once() {
+        let quad = grid(1);
+        // Along the diagonal both triangles share, from either side and at an angle
+        for k in 1..8 {
+            let on_edge = Point::new(k as f32 / 8.0, k as f32 / 8.0, 0.0);
+            for direction in [
+                Vec3::new(0.0, 0.0, -1.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                Vec3::new(0.3, -0.2, -1.0),
+            ] {
+                let ray = Ray::new(on_edge - direction * 4.0, direction, 0.0);
+                assert_eq!(hit_count(&quad, ray), 1, "{on_edge:?} {direction:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn shared_vertices_are_hit_once() {
+        // The center vertex is shared by six triangles
+        let triangles = grid(2);
+        for direction in [Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 0.0, 1.0)] {
+            let ray = Ray::new(Point::new(1.0, 1.0, 0.0) - direction * 4.0, direction, 0.0);
+            assert_eq!(hit_count(&triangles, ray), 1, "{direction:?}");
+        }
+
+        // Rays on the grid lines and corners inside the mesh neither slip through nor hit twice
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        for _ in 0..500 {
+            let snap = |v: f32| (v * 4.0).round() / 4.0;
+            let (x, y) = (
+                snap(rng.random_range(0.2..1.8)),
+                snap(rng.random_range(0.2..1.8)),
+            );
+            let direction = Vec3::new(0.0, 0.0, -1.0);
+            let ray = Ray::new(Point::new(x, y, 5.0), direction, 0.0);
+            assert_eq!(hit_count(&triangles, ray), 1, "{x} {y}");
+        }
+    }
+}
@@ -5,11 +5,17 @@ use crate::vec3::Vec3;
 pub(crate) struct Ray {
     pub(crate) origin: Point,
     pub(crate) direction: Vec3,
+    // Moment within the camera shutter, moving primitives are hit where they are at this time
+    pub(crate) time: f32,
 }
 
 impl Ray {
-    pub(crate) fn new(origin: Point, direction: Vec3) -> Ray {
-        Ray { origin, direction }
+    pub(crate) fn new(origin: Point, direction: Vec3, time: f32) -> Ray {
+        Ray {
+            origin,
+            direction,
+            time,
+        }
     }
 
     pub(crate) fn at(&self, distance: f32) -> Point {
@@ -0,0 +1,1319 @@
+use std::{
+    collections::HashMap,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    aabb::Aabb,
+    background::{Background, EnvironmentMap},
+    bvh::{BVHNode, BuildStrategy},
+    camera::Camera,
+    color::Color,
+    examples::Scene,
+    image::Image,
+    instance::Geometry,
+    interval::Interval,
+    material::Material,
+    medium::{Density, VoxelGrid},
+    noise::Perlin,
+    obj,
+    point::Point,
+    primitive::{Primitive, QuadParams, SphereParams, TriangleParams},
+    texture::Texture,
+    transform::Transform,
+    vec3::Vec3,
+};
+
+// Text scene description, a list of statements made of a keyword, an optional name and kind,
+// then a block of properties. Newlines don't matter and '#' starts a comment:
+//
+//   camera { position 13 2 3 look_at 0 0 0 vertical_fov 20 }
+//   texture checks checker { scale 3.125 even 0.2 0.3 0.1 odd 0.9 0.9 0.9 }
+//   material ground lambertian { texture checks }
+//   sphere { center 0 -1000 0 radius 1000 material ground }
+//
+// See scenes/*.scene for complete examples
+pub(crate) fn load(path: &str, strategy: BuildStrategy) -> Result<Scene, io::Error> {
+    let source = std::fs::read_to_string(path)?;
+    parse(&source, path, strategy)
+}
+
+fn parse(source: &str, path: &str, strategy: BuildStrategy) -> Result<Scene, io::Error> {
+    let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+
+    let tokens = tokenize(source).map_err(|err| err.into_io(path))?;
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+        directory,
+        textures: HashMap::new(),
+        materials: HashMap::new(),
+        geometries: HashMap::new(),
+        camera: None,
+        background: None,
+        world: Vec::new(),
+        strategy,
+    };
+
+    parser.parse().map_err(|err| err.into_io(path))?;
+
+    let Some(mut camera) = parser.camera else {
+        return Err(ParseError::new(Location::default(), "scene has no camera").into_io(path));
+    };
+    if let Some(background) = parser.background {
+        camera.background = background;
+    }
+
+    let mut world = parser.world;
+    if world.is_empty() {
+        return Err(ParseError::new(Location::default(), "scene has no primitives").into_io(path));
+    }
+    let bvh_root = BVHNode::build(&mut world, strategy);
+
+    Ok((camera, bvh_root, Arc::new(world)))
+}
+
+fn load_obj(path: &Path, location: Location) -> Result<Vec<Primitive>, ParseError> {
+    obj::load(&path.to_string_lossy()).map_err(|err| ParseError::new(location, &err.to_string()))
+}
+
+// None when the block has no transform properties, the primitives then stay in world space
+fn transform(block: &Block) -> Result<Option<Transform>, ParseError> {
+    if !["scale", "rotate", "rotate_axis", "translate"]
+        .iter()
+        .any(|key| block.properties.contains_key(key))
+    {
+        return Ok(None);
+    }
+
+    let mut transform = Transform::identity();
+
+    if let Ok(factors) = block.vector("scale") {
+        if factors.x == 0.0 || factors.y == 0.0 || factors.z == 0.0 {
+            let (_, location) = block.properties["scale"];
+            return Err(ParseError::new(location, "scale factors can't be zero"));
+        }
+        transform = transform.then(&Transform::scale(factors));
+    }
+
+    if let Ok(degrees) = block.number("rotate") {
+        let axis = block.vector_or("rotate_axis", Vec3::new(0.0, 1.0, 0.0));
+        if axis.near_zero() {
+            let (_, location) = block.properties["rotate_axis"];
+            return Err(ParseError::new(location, "rotate_axis can't be zero"));
+        }
+        transform = transform.then(&Transform::rotate(axis, degrees));
+    }
+
+    if let Ok(offset) = block.vector("translate") {
+        transform = transform.then(&Transform::translate(offset));
+    }
+
+    Ok(Some(transform))
+}
+
+// Voxel counts are written as a vector, they have to be whole and positive
+fn voxel_resolution(block: &Block, resolution: Vec3) -> Result<[usize; 3], ParseError> {
+    let counts = [resolution.x, resolution.y, resolution.z];
+    if counts
+        .iter()
+        .any(|count| *count < 1.0 || count.fract() != 0.0)
+    {
+        let (_, location) = block.properties["resolution"];
+        return Err(ParseError::new(
+            location,
+            "resolution must be three positive whole numbers",
+        ));
+    }
+    Ok(counts.map(|count| count as usize))
+}
+
+// 1-based position in the source
+#[derive(Clone, Copy, Debug, Default)]
+struct Location {
+    line: usize,
+    column: usize,
+}
+
+#[derive(Debug)]
+struct ParseError {
+    location: Location,
+    message: String,
+}
+
+impl ParseError {
+    fn new(location: Location, message: &str) -> Self {
+        ParseError {
+            location,
+            message: message.to_string(),
+        }
+    }
+
+    fn into_io(self, path: &str) -> io::Error {
+        let message = if self.location.line == 0 {
+            format!("{path}: {}", self.message)
+        } else {
+            format!(
+                "{path}:{}:{}: {}",
+                self.location.line, self.location.column, self.message
+            )
+        };
+        io::Error::new(ErrorKind::InvalidData, message)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum TokenKind {
+    Word(String),
+    // Double quoted, for paths with spaces
+    Text(String),
+    Open,
+    Close,
+}
+
+#[derive(Debug)]
+struct Token {
+    kind: TokenKind,
+    location: Location,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match &self.kind {
+            TokenKind::Word(word) => format!("'{word}'"),
+            TokenKind::Text(text) => format!("\"{text}\""),
+            TokenKind::Open => "'{'".to_string(),
+            TokenKind::Close => "'}'".to_string(),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+
+    for (line_idx, line) in source.lines().enumerate() {
+        let mut chars = line.char_indices().peekable();
+
+        while let Some(&(start, c)) = chars.peek() {
+            let location = Location {
+                line: line_idx + 1,
+                column: line[..start].chars().count() + 1,
+            };
+
+            match c {
+                '#' => break,
+                c if c.is_whitespace() => {
+                    chars.next();
+                }
+                '{' | '}' => {
+                    chars.next();
+                    let kind = if c == '{' {
+                        TokenKind::Open
+                    } else {
+                        TokenKind::Close
+                    };
+                    tokens.push(Token { kind, location });
+                }
+                '"' => {
+                    chars.next();
+                    let mut text = String::new();
+                    loop {
+                        match chars.next() {
+                            Some((_, '"')) => break,
+                            Some((_, c)) => text.push(c),
+                            None => return Err(ParseError::new(location, "unterminated string")),
+                        }
+                    }
+                    tokens.push(Token {
+                        kind: TokenKind::Text(text),
+                        location,
+                    });
+                }
+                _ => {
+                    let mut word = String::new();
+                    while let Some(&(_, c)) = chars.peek() {
+                        if c.is_whitespace() || matches!(c, '{' | '}' | '"' | '#') {
+                            break;
+                        }
+                        word.push(c);
+                        chars.next();
+                    }
+                    tokens.push(Token {
+                        kind: TokenKind::Word(word),
+                        location,
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+// How many tokens a property takes and how they are read
+#[derive(Clone, Copy)]
+enum ValueKind {
+    Number,
+    Integer,
+    Vector,
+    Name,
+    Path,
+}
+
+#[derive(Debug)]
+enum Value {
+    Number(f32),
+    Integer(u32),
+    Vector(Vec3),
+    Name(String),
+    Path(String),
+}
+
+// Properties of one statement, checked against its schema while parsing
+struct Block {
+    statement: String,
+    location: Location,
+    properties: HashMap<&'static str, (Value, Location)>,
+}
+
+impl Block {
+    fn missing(&self, key: &str) -> ParseError {
+        ParseError::new(
+            self.location,
+            &format!("{} is missing '{key}'", self.statement),
+        )
+    }
+
+    fn number(&self, key: &str) -> Result<f32, ParseError> {
+        match self.properties.get(key) {
+            Some((Value::Number(value), _)) => Ok(*value),
+            _ => Err(self.missing(key)),
+        }
+    }
+
+    fn number_or(&self, key: &str, default: f32) -> f32 {
+        self.number(key).unwrap_or(default)
+    }
+
+    fn integer(&self, key: &str) -> Result<u32, ParseError> {
+        match self.properties.get(key) {
+            Some((Value::Integer(value), _)) => Ok(*value),
+            _ => Err(self.missing(key)),
+        }
+    }
+
+    fn integer_or(&self, key: &str, default: u32) -> u32 {
+        self.integer(key).unwrap_or(default)
+    }
+
+    fn vector(&self, key: &str) -> Result<Vec3, ParseError> {
+        match self.properties.get(key) {
+            Some((Value::Vector(value), _)) => Ok(*value),
+            _ => Err(self.missing(key)),
+        }
+    }
+
+    fn vector_or(&self, key: &str, default: Vec3) -> Vec3 {
+        self.vector(key).unwrap_or(default)
+    }
+
+    fn point(&self, key: &str) -> Result<Point, ParseError> {
+        self.vector(key).map(Point::from)
+    }
+
+    fn color(&self, key: &str) -> Result<Color, ParseError> {
+        self.vector(key).map(Color::from)
+    }
+
+    fn name(&self, key: &str) -> Option<(&str, Location)> {
+        match self.properties.get(key) {
+            Some((Value::Name(name), location)) => Some((name, *location)),
+            _ => None,
+        }
+    }
+
+    fn path(&self, key: &str) -> Result<(&str, Location), ParseError> {
+        match self.properties.get(key) {
+            Some((Value::Path(path), location)) => Ok((path, *location)),
+            _ => Err(self.missing(key)),
+        }
+    }
+}
+
+const CAMERA: &[(&str, ValueKind)] = &[
+    ("position", ValueKind::Vector),
+    ("look_at", ValueKind::Vector),
+    ("view_up", ValueKind::Vector),
+    ("focal_length", ValueKind::Number),
+    ("defocus_angle", ValueKind::Number),
+    ("aspect_ratio", ValueKind::Number),
+    ("image_width", ValueKind::Integer),
+    ("vertical_fov", ValueKind::Number),
+    ("samples_per_pixel", ValueKind::Integer),
+    ("min_depth", ValueKind::Integer),
+    ("max_depth", ValueKind::Integer),
+    ("shutter_open", ValueKind::Number),
+    ("shutter_close", ValueKind::Number),
+];
+
+const PRIMITIVES: &[&str] = &["sphere", "quad", "triangle", "box", "obj"];
+
+// Primitives with an inside that can hold a medium
+const CLOSED: &[&str] = &["sphere", "box"];
+
+// Optional on every primitive, applied as scale, then rotate (degrees) about rotate_axis, then
+// translate. Motion is the offset reached at time 1, for motion blur
+const PLACEMENT: &[(&str, ValueKind)] = &[
+    ("scale", ValueKind::Vector),
+    ("rotate", ValueKind::Number),
+    ("rotate_axis", ValueKind::Vector),
+    ("translate", ValueKind::Vector),
+    ("motion", ValueKind::Vector),
+];
+
+// Optional on closed primitives, any of them fills the primitive with a medium that scatters by
+// its material. Density scales a voxel grid file or Perlin turbulence at the noise scale, and is
+// the constant density without either
+const MEDIUM: &[(&str, ValueKind)] = &[
+    ("density", ValueKind::Number),
+    ("grid", ValueKind::Path),
+    ("resolution", ValueKind::Vector),
+    ("noise", ValueKind::Number),
+    ("seed", ValueKind::Integer),
+];
+
+// Materials taking either a texture by name or an inline color
+const TEXTURED: &[(&str, ValueKind)] =
+    &[("texture", ValueKind::Name), ("color", ValueKind::Vector)];
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    directory: &'a Path,
+    textures: HashMap<String, Arc<Texture>>,
+    materials: HashMap<String, Arc<Material>>,
+    // Models loaded for instancing, by resolved path
+    geometries: HashMap<PathBuf, Arc<Geometry>>,
+    camera: Option<Camera>,
+    background: Option<Background>,
+    world: Vec<Primitive>,
+    // For the world and every geometry's own BVH
+    strategy: BuildStrategy,
+}
+
+impl Parser<'_> {
+    fn parse(&mut self) -> Result<(), ParseError> {
+        while let Some(token) = self.tokens.get(self.position) {
+            let location = token.location;
+            let keyword = self.word("a statement")?;
+
+            match keyword.as_str() {
+                "camera" => self.camera(location)?,
+                "background" => self.background(location)?,
+                "texture" => self.texture()?,
+                "material" => self.material()?,
+                keyword if PRIMITIVES.contains(&keyword) => self.primitive(keyword, location)?,
+                _ => {
+                    return Err(ParseError::new(
+                        location,
+                        &format!(
+                            "unknown statement '{keyword}', expected camera, background, \
+                             texture, material, sphere, quad, triangle, box or obj"
+                        ),
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn camera(&mut self, location: Location) -> Result<(), ParseError> {
+        if self.camera.is_some() {
+            return Err(ParseError::new(location, "scene has more than one camera"));
+        }
+
+        let block = self.block("camera", location, CAMERA)?;
+
+        let image_width = block.integer_or("image_width", 400);
+        let samples_per_pixel = block.integer_or("samples_per_pixel", 100);
+        let aspect_ratio = block.number_or("aspect_ratio", 16.0 / 9.0);
+        if image_width == 0 || samples_per_pixel == 0 || aspect_ratio <= 0.0 {
+            return Err(ParseError::new(
+                location,
+                "image_width, samples_per_pixel and aspect_ratio must be positive",
+            ));
+        }
+
+        let shutter_open = block.number_or("shutter_open", 0.0);
+        let shutter_close = block.number_or("shutter_close", shutter_open);
+        if shutter_close < shutter_open {
+            return Err(ParseError::new(
+                location,
+                "shutter_close must not be before shutter_open",
+            ));
+        }
+
+        let mut camera = Camera::new(
+            block.point("position")?,
+            block.point("look_at")?,
+            block.vector_or("view_up", Vec3::new(0.0, 1.0, 0.0)),
+            block.number_or("focal_length", 1.0),
+            block.number_or("defocus_angle", 0.0),
+            aspect_ratio,
+            image_width,
+            block.number_or("vertical_fov", 20.0),
+            samples_per_pixel,
+            block.integer("max_depth").ok(),
+        );
+        camera.min_depth = block.integer_or("min_depth", camera.min_depth);
+        camera.shutter = Interval::new(shutter_open, shutter_close);
+
+        self.camera = Some(camera);
+        Ok(())
+    }
+
+    fn background(&mut self, location: Location) -> Result<(), ParseError> {
+        if self.background.is_some() {
+            return Err(ParseError::new(
+                location,
+                "scene has more than one background",
+            ));
+        }
+
+        let kind_location = self.location();
+        let kind = self.word("a background kind")?;
+        let statement = format!("{kind} background");
+
+        let background = match kind.as_str() {
+            "solid" => {
+                let block = self.block(&statement, location, &[("color", ValueKind::Vector)])?;
+                Background::Solid(block.color("color")?)
+            }
+            "gradient" => {
+                let block = self.block(
+                    &statement,
+                    location,
+                    &[
+                        ("horizon", ValueKind::Vector),
+                        ("zenith", ValueKind::Vector),
+                    ],
+                )?;
+                Background::Gradient(block.color("horizon")?, block.color("zenith")?)
+            }
+            "environment" => {
+                let block = self.block(&statement, location, &[("image", ValueKind::Path)])?;
+                let (path, path_location) = block.path("image")?;
+                Background::Environment(EnvironmentMap::new(self.image(path, path_location)?))
+            }
+            _ => {
+                return Err(ParseError::new(
+                    kind_location,
+                    &format!(
+                        "unknown background '{kind}', expected solid, gradient or environment"
+                    ),
+                ));
+            }
+        };
+
+        self.background = Some(background);
+        Ok(())
+    }
+
+    fn texture(&mut self) -> Result<(), ParseError> {
+        let (name, location) = self.definition_name("texture")?;
+        if self.textures.contains_key(&name) {
+            return Err(ParseError::new(
+                location,
+                &format!("texture '{name}' is already defined"),
+            ));
+        }
+
+        let kind_location = self.location();
+        let kind = self.word("a texture kind")?;
+        let statement = format!("texture '{name}'");
+
+        let texture = match kind.as_str() {
+            "color" => {
+                let block = self.block(&statement, location, &[("color", ValueKind::Vector)])?;
+                Texture::Color(block.color("color")?)
+            }
+            "checker" => {
+                let block = self.block(
+                    &statement,
+                    location,
+                    &[
+                        ("scale", ValueKind::Number),
+                        ("even", ValueKind::Vector),
+                        ("odd", ValueKind::Vector),
+                    ],
+                )?;
+                Texture::Checker(
+                    block.number_or("scale", 1.0),
+                    block.color("even")?,
+                    block.color("odd")?,
+                )
+            }
+            "image" => {
+                let block = self.block(&statement, location, &[("path", ValueKind::Path)])?;
+                let (path, path_location) = block.path("path")?;
+                Texture::Image(self.image(path, path_location)?)
+            }
+            "noise" => {
+                let block = self.block(
+                    &statement,
+                    location,
+                    &[("scale", ValueKind::Number), ("seed", ValueKind::Integer)],
+                )?;
+                Texture::Noise(Perlin::new(
+                    block.number_or("scale", 1.0),
+                    block.integer_or("seed", 0) as u64,
+                ))
+            }
+            "grid" => {
+                let block = self.block(
+                    &statement,
+                    location,
+                    &[
+                        ("path", ValueKind::Path),
+                        ("resolution", ValueKind::Vector),
+                        ("min", ValueKind::Vector),
+                        ("max", ValueKind::Vector),
+                        ("color", ValueKind::Vector),
+                    ],
+                )?;
+                let (path, path_location) = block.path("path")?;
+                let resolution = match block.vector("resolution") {
+                    Ok(resolution) => Some(voxel_resolution(&block, resolution)?),
+                    Err(_) => None,
+                };
+                let bounds = Aabb::new_between(block.point("min")?, block.point("max")?);
+                let path = self.resolve(path);
+                let grid = VoxelGrid::load(&path.to_string_lossy(), resolution, bounds)
+                    .map_err(|err| ParseError::new(path_location, &err.to_string()))?;
+                Texture::Grid(
+                    grid,
+                    Color::from(block.vector_or("color", Vec3::new(1.0, 1.0, 1.0))),
+                )
+            }
+            _ => {
+                return Err(ParseError::new(
+                    kind_location,
+                    &format!(
+                        "unknown texture '{kind}', expected color, checker, image, noise or grid"
+                    ),
+                ));
+            }
+        };
+
+        self.textures.insert(name, Arc::new(texture));
+        Ok(())
+    }
+
+    fn material(&mut self) -> Result<(), ParseError> {
+        let (name, location) = self.definition_name("material")?;
+        if self.materials.contains_key(&name) {
+            return Err(ParseError::new(
+                location,
+                &format!("material '{name}' is already defined"),
+            ));
+        }
+
+        let kind_location = self.location();
+        let kind = self.word("a material kind")?;
+        let statement = format!("material '{name}'");
+
+        let material = match kind.as_str() {
+            "lambertian" => {
+                let block = self.block(&statement, location, TEXTURED)?;
+                Material::Lambertian(self.texture_or_color(&block)?)
+            }
+            "metal" => {
+                let block = self.block(
+                    &statement,
+                    location,
+                    &[("albedo", ValueKind::Vector), ("fuzz", ValueKind::Number)],
+                )?;
+                Material::Metal(block.color("albedo")?, block.number_or("fuzz", 0.0))
+            }
+            "dielectric" => {
+                let block = self.block(&statement, location, &[("ior", ValueKind::Number)])?;
+                Material::Dielectric(block.number("ior")?)
+            }
+            "diffuse_light" => {
+                let block = self.block(&statement, location, TEXTURED)?;
+                Material::DiffuseLight(self.texture_or_color(&block)?)
+            }
+            "isotropic" => {
+                let block = self.block(&statement, location, TEXTURED)?;
+                Material::Isotropic(self.texture_or_color(&block)?)
+            }
+            _ => {
+                return Err(ParseError::new(
+                    kind_location,
+                    &format!(
+                        "unknown material '{kind}', expected lambertian, metal, dielectric, \
+                         diffuse_light or isotropic"
+                    ),
+                ));
+            }
+        };
+
+        self.materials.insert(name, Arc::new(material));
+        Ok(())
+    }
+
+    fn primitive(&mut self, keyword: &str, location: Location) -> Result<(), ParseError> {
+        let (block, primitives) = match keyword {
+            "sphere" => {
+                let block = self.block(
+                    keyword,
+                    location,
+                    &[
+                        ("center", ValueKind::Vector),
+                        ("radius", ValueKind::Number),
+                        ("material", ValueKind::Name),
+                    ],
+                )?;
+                let center = block.point("center")?;
+                let radius = block.number("radius")?;
+                let material = self.material_ref(&block)?;
+
+                // Spheres can move by themselves, without an instance around them
+                if let (Ok(motion), None) = (block.vector("motion"), transform(&block)?) {
+                    let sphere = Primitive::Sphere(SphereParams::moving(
+                        center,
+                        center + motion,
+                        radius,
+                        material,
+                    ));
+                    let mut primitives = self.fill(&block, vec![sphere])?;
+                    self.world.append(&mut primitives);
+                    return Ok(());
+                }
+
+                let sphere = Primitive::Sphere(SphereParams::new(center, radius, material));
+                (block, vec![sphere])
+            }
+            "quad" => {
+                let block = self.block(
+                    keyword,
+                    location,
+                    &[
+                        ("corner", ValueKind::Vector),
+                        ("u", ValueKind::Vector),
+                        ("v", ValueKind::Vector),
+                        ("material", ValueKind::Name),
+                    ],
+                )?;
+                let quad = Primitive::Quad(QuadParams::new(
+                    block.point("corner")?,
+                    block.vector("u")?,
+                    block.vector("v")?,
+                    self.material_ref(&block)?,
+                ));
+                (block, vec![quad])
+            }
+            "triangle" => {
+                let block = self.block(
+                    keyword,
+                    location,
+                    &[
+                        ("a", ValueKind::Vector),
+                        ("b", ValueKind::Vector),
+                        ("c", ValueKind::Vector),
+                        ("material", ValueKind::Name),
+                    ],
+                )?;
+                let triangle = Primitive::Triangle(TriangleParams::from_points(
+                    block.point("a")?,
+                    block.point("b")?,
+                    block.point("c")?,
+                    self.material_ref(&block)?,
+                ));
+                (block, vec![triangle])
+            }
+            "box" => {
+                let block = self.block(
+                    keyword,
+                    location,
+                    &[
+                        ("min", ValueKind::Vector),
+                        ("max", ValueKind::Vector),
+                        ("material", ValueKind::Name),
+                    ],
+                )?;
+                let sides = Primitive::new_box(
+                    block.point("min")?,
+                    block.point("max")?,
+                    self.material_ref(&block)?,
+                );
+                (block, sides)
+            }
+            _ => {
+                // Models bring their own materials from the MTL file
+                let block = self.block(keyword, location, &[("path", ValueKind::Path)])?;
+                let (path, path_location) = block.path("path")?;
+                let path = self.resolve(path);
+
+                // Transformed or moving copies of a model share one bottom-level BVH, only the
+                // top level grows with the number of copies
+                let transform = transform(&block)?;
+                let motion = block.vector("motion").ok();
+                if transform.is_some() || motion.is_some() {
+                    let geometry = match self.geometries.get(&path) {
+                        Some(geometry) => geometry.clone(),
+                        None => {
+                            let geometry =
+                                Geometry::new(load_obj(&path, path_location)?, self.strategy);
+                            self.geometries.insert(path, geometry.clone());
+                            geometry
+                        }
+                    };
+                    self.world.push(Primitive::new_moving_instance(
+                        geometry,
+                        transform.unwrap_or_default(),
+                        motion.unwrap_or_default(),
+                    ));
+                    return Ok(());
+                }
+
+                let primitives = load_obj(&path, path_location)?;
+                (block, primitives)
+            }
+        };
+
+        let primitives = match (transform(&block)?, block.vector("motion").ok()) {
+            (None, None) => primitives,
+            (transform, motion) => vec![Primitive::new_moving_instance(
+                Geometry::new(primitives, self.strategy),
+                transform.unwrap_or_default(),
+                motion.unwrap_or_default(),
+            )],
+        };
+
+        let mut primitives = self.fill(&block, primitives)?;
+        self.world.append(&mut primitives);
+        Ok(())
+    }
+
+    // Turns the placed primitives into the boundary of a medium when the block has medium
+    // properties. Media live in world space, so densities are per world unit however the boundary
+    // is scaled
+    fn fill(
+        &self,
+        block: &Block,
+        primitives: Vec<Primitive>,
+    ) -> Result<Vec<Primitive>, ParseError> {
+        // resolution and seed only qualify a grid or noise, they don't make a medium on their own
+        for (key, needs) in [("resolution", "grid"), ("seed", "noise")] {
+            if let Some((_, location)) = block.properties.get(key)
+                && !block.properties.contains_key(needs)
+            {
+                return Err(ParseError::new(
+                    *location,
+                    &format!("'{key}' only applies to media with a '{needs}'"),
+                ));
+            }
+        }
+        if !["density", "grid", "noise"]
+            .iter()
+            .any(|key| block.properties.contains_key(key))
+        {
+            return Ok(primitives);
+        }
+
+        let scale = block.number_or("density", 1.0);
+        if scale <= 0.0 {
+            let (_, location) = block.properties["density"];
+            return Err(ParseError::new(location, "density must be positive"));
+        }
+
+        let boundary = Geometry::new(primitives, self.strategy);
+        let density = match (block.path("grid"), block.number("noise")) {
+            (Ok(_), Ok(_)) => {
+                let (_, location) = block.properties["noise"];
+                return Err(ParseError::new(
+                    location,
+                    "a medium takes either a grid or noise, not both",
+                ));
+            }
+            (Ok((path, path_location)), _) => {
+                let resolution = match block.vector("resolution") {
+                    Ok(resolution) => Some(voxel_resolution(block, resolution)?),
+                    Err(_) => None,
+                };
+                // The grid stretches over the placed boundary's bounds
+                let path = self.resolve(path);
+                let grid = VoxelGrid::load(
+                    &path.to_string_lossy(),
+                    resolution,
+                    *boundary.bounding_box(),
+                )
+                .map_err(|err| ParseError::new(path_location, &err.to_string()))?;
+                Density::Grid(grid, scale)
+            }
+            (_, Ok(noise_scale)) => Density::Turbulence(
+                Perlin::new(noise_scale, block.integer_or("seed", 0) as u64),
+                scale,
+            ),
+            _ => Density::Constant(scale),
+        };
+
+        let material = self.material_ref(block)?;
+        Ok(vec![Primitive::new_medium(boundary, density, material)])
+    }
+
+    // Reads '{', then "key value" pairs allowed by the schema until '}'
+    fn block(
+        &mut self,
+        statement: &str,
+        location: Location,
+        schema: &[(&'static str, ValueKind)],
+    ) -> Result<Block, ParseError> {
+        self.expect(TokenKind::Open, &format!("'{{' to start the {statement}"))?;
+
+        // Every primitive can be placed by a transform
+        let extra_schema = if PRIMITIVES.contains(&statement) {
+            PLACEMENT
+        } else {
+            &[]
+        };
+        let medium_schema = if CLOSED.contains(&statement) {
+            MEDIUM
+        } else {
+            &[]
+        };
+
+        let mut block = Block {
+            statement: statement.to_string(),
+            location,
+            properties: HashMap::new(),
+        };
+
+        loop {
+            let key_location = self.location();
+            let token = self.next(&format!("'}}' to end the {statement}"))?;
+            let key = match &token.kind {
+                TokenKind::Close => break,
+                TokenKind::Word(word) => word.clone(),
+                _ => {
+                    let found = token.describe();
+                    return Err(ParseError::new(
+                        key_location,
+                        &format!("expected a property name, found {found}"),
+                    ));
+                }
+            };
+
+            let Some(&(key, kind)) = schema
+                .iter()
+                .chain(extra_schema)
+                .chain(medium_schema)
+                .find(|(name, _)| *name == key)
+            else {
+                let allowed = schema
+                    .iter()
+                    .chain(extra_schema)
+                    .chain(medium_schema)
+                    .map(|(name, _)| *name)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                return Err(ParseError::new(
+                    key_location,
+                    &format!("unknown property '{key}' for {statement}, expected one of {allowed}"),
+                ));
+            };
+
+            if block.properties.contains_key(key) {
+                return Err(ParseError::new(
+                    key_location,
+                    &format!("'{key}' is set more than once"),
+                ));
+            }
+
+            let value_location = self.location();
+            let value = match kind {
+                ValueKind::Number => Value::Number(self.number(key)?),
+                ValueKind::Integer => Value::Integer(self.integer(key)?),
+                ValueKind::Vector => Value::Vector(Vec3::new(
+                    self.number(key)?,
+                    self.number(key)?,
+                    self.number(key)?,
+                )),
+                ValueKind::Name => Value::Name(self.word(&format!("a name for '{key}'"))?),
+                ValueKind::Path => Value::Path(self.text(key)?),
+            };
+            block.properties.insert(key, (value, value_location));
+        }
+
+        Ok(block)
+    }
+
+    fn definition_name(&mut self, statement: &str) -> Result<(String, Location), ParseError> {
+        let location = self.location();
+        let name = self.word(&format!("a {statement} name"))?;
+        Ok((name, location))
+    }
+
+    fn texture_or_color(&self, block: &Block) -> Result<Arc<Texture>, ParseError> {
+        match (block.name("texture"), block.vector("color")) {
+            (Some(_), Ok(_)) => Err(ParseError::new(
+                block.location,
+                &format!("{} sets both 'texture' and 'color'", block.statement),
+            )),
+            (Some((name, location)), Err(_)) => {
+                self.textures.get(name).cloned().ok_or_else(|| {
+                    ParseError::new(location, &format!("undefined texture '{name}'"))
+                })
+            }
+            (None, Ok(color)) => Ok(Arc::new(Texture::Color(Color::from(color)))),
+            (None, Err(_)) => Err(ParseError::new(
+                block.location,
+                &format!("{} needs a 'texture' or a 'color'", block.statement),
+            )),
+        }
+    }
+
+    fn material_ref(&self, block: &Block) -> Result<Arc<Material>, ParseError> {
+        let (name, location) = block
+            .name("material")
+            .ok_or_else(|| block.missing("material"))?;
+        self.materials
+            .get(name)
+            .cloned()
+            .ok_or_else(|| ParseError::new(location, &format!("undefined material '{name}'")))
+    }
+
+    fn image(&self, path: &str, location: Location) -> Result<Image, ParseError> {
+        let path = self.resolve(path);
+        Image::load(&path.to_string_lossy()).map_err(|err| {
+            ParseError::new(
+                location,
+                &format!("can't load image '{}': {err}", path.display()),
+            )
+        })
+    }
+
+    // Relative paths are relative to the scene file
+    fn resolve(&self, path: &str) -> PathBuf {
+        self.directory.join(path)
+    }
+
+    fn location(&self) -> Location {
+        self.tokens
+            .get(self.position)
+            .or(self.tokens.last())
+            .map(|token| token.location)
+            .unwrap_or_default()
+    }
+
+    fn next(&mut self, expected: &str) -> Result<&Token, ParseError> {
+        let location = self.location();
+        let token = self.tokens.get(self.position).ok_or_else(|| {
+            ParseError::new(
+                location,
+                &format!("expected {expected}, found the end of the file"),
+            )
+        })?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, kind: TokenKind, expected: &str) -> Result<(), ParseError> {
+        let location = self.location();
+        let token = self.next(expected)?;
+        if token.kind != kind {
+            let found = token.describe();
+            return Err(ParseError::new(
+                location,
+                &format!("expected {expected}, found {found}"),
+            ));
+        }
+        Ok(())
+    }
+
+    fn word(&mut self, expected: &str) -> Result<String, ParseError> {
+        let location = self.location();
+        let token = self.next(expected)?;
+        match &token.kind {
+            TokenKind::Word(word) => Ok(word.clone()),
+            _ => {
+                let found = token.describe();
+                Err(ParseError::new(
+                    location,
+                    &format!("expected {expected}, found {found}"),
+                ))
+            }
+        }
+    }
+
+    // Paths may be quoted or bare
+    fn text(&mut self, key: &str) -> Result<String, ParseError> {
+        let location = self.location();
+        let expected = format!("a path for '{key}'");
+        let token = self.next(&expected)?;
+        match &token.kind {
+            TokenKind::Word(text) | TokenKind::Text(text) => Ok(text.clone()),
+            _ => {
+                let found = token.describe();
+                Err(ParseError::new(
+                    location,
+                    &format!("expected {expected}, found {found}"),
+                ))
+            }
+        }
+    }
+
+    // Plain numbers or fractions like 16/9
+    fn number(&mut self, key: &str) -> Result<f32, ParseError> {
+        let location = self.location();
+        let word = self.word(&format!("a number for '{key}'"))?;
+
+        let value = match word.split_once('/') {
+            Some((numerator, denominator)) => numerator
+                .parse::<f32>()
+                .ok()
+                .zip(denominator.parse::<f32>().ok())
+                .map(|(numerator, denominator)| numerator / denominator),
+            None => word.parse::<f32>().ok(),
+        };
+
+        value.filter(|value| value.is_finite()).ok_or_else(|| {
+            ParseError::new(
+                location,
+                &format!("expected a number for '{key}', found '{word}'"),
+            )
+        })
+    }
+
+    fn integer(&mut self, key: &str) -> Result<u32, ParseError> {
+        let location = self.location();
+        let word = self.word(&format!("a whole number for '{key}'"))?;
+        word.parse::<u32>().map_err(|_| {
+            ParseError::new(
+                location,
+                &format!("expected a whole number for '{key}', found '{word}'"),
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use crate::{
+        bvh::BuildStrategy, examples, image::Image, material::Material, point::Point,
+        primitive::Primitive, texture::Texture, vec3::Vec3,
+    };
+
+    fn parse_error(source: &str) -> std::io::Error {
+        match super::parse(source, "test.scene", BuildStrategy::Median) {
+            Ok(_) => panic!("parsed {source:?}"),
+            Err(err) => err,
+        }
+    }
+
+    // Line, column and message for mistakes starting on the third line, after a valid camera and
+    // material. Checks on a property's value point at the value
+    #[test]
+    fn errors_name_the_line_and_column() {
+        let start =
+            "camera { position 0 0 1 look_at 0 0 0 }\nmaterial white lambertian { color 1 1 1 }\n";
+        for (statement, message) in [
+            (
+                "quad { corner 0 0 0 u 1 0 0 v 0 1 0 material white colour 1 1 1 }",
+                "3:52: unknown property 'colour' for quad, expected one of corner, u, v, \
+                 material, scale, rotate, rotate_axis, translate, motion",
+            ),
+            (
+                "sphere { center 0 0 0 radius 1 radius 2 material white }",
+                "3:32: 'radius' is set more than once",
+            ),
+            ("obj { path \"model.obj }", "3:12: unterminated string"),
+            (
+                "sphere { center 0 0 0 radius 1 material chalk }",
+                "3:41: undefined material 'chalk'",
+            ),
+            (
+                "box { min 0 0 0 max 1 1 1 material white resolution 4 4 4 density 2 }",
+                "3:53: 'resolution' only applies to media with a 'grid'",
+            ),
+            (
+                "sphere { center 0 0 0 radius one material white }",
+                "3:30: expected a number for 'radius', found 'one'",
+            ),
+            (
+                "sphere {\n  center 0 0 0\n  radius 1 material white",
+                "5:21: expected '}' to end the sphere, found the end of the file",
+            ),
+            (
+                "cylinder { }",
+                "3:1: unknown statement 'cylinder', expected camera, background, texture, \
+                 material, sphere, quad, triangle, box or obj",
+            ),
+        ] {
+            let source = format!("{start}{statement}\n");
+            let err = parse_error(&source);
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+            assert_eq!(err.to_string(), format!("test.scene:{message}"));
+        }
+
+        // Whole file problems have no position
+        let err = parse_error("");
+        assert_eq!(err.to_string(), "test.scene: scene has no camera");
+        let err = parse_error(start);
+        assert_eq!(err.to_string(), "test.scene: scene has no primitives");
+    }
+
+    // Every scene file describes one of the built-in scenes, named the same with dashes
+    #[test]
+    fn scene_files_match_examples() {
+        // The earth texture is stored with git lfs and may only be a pointer file
+        let earth_texture = Image::load("resources/nasa_bmng.ppm").is_ok();
+
+        let mut paths = std::fs::read_dir("scenes")
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        paths.sort();
+        assert!(!paths.is_empty());
+
+        for path in paths {
+            let name = path
+                .file_stem()
+                .unwrap()
+                .to_string_lossy()
+                .replace('_', "-");
+            if name == "earth" && !earth_texture {
+                continue;
+            }
+            let build = examples::by_name(&name).unwrap_or_else(|| panic!("no example '{name}'"));
+
+            let (camera, _, world) =
+                super::load(&path.to_string_lossy(), BuildStrategy::Median).unwrap();
+            let (expected, _, expected_world) = build(BuildStrategy::Median);
+            assert_eq!(world.len(), expected_world.len(), "{name}");
+
+            let near = |a: f32, b: f32| (a - b).abs() <= 1e-4 * b.abs().max(1.0);
+            let near_vec = |a: Vec3, b: Vec3| near(a.x, b.x) && near(a.y, b.y) && near(a.z, b.z);
+            let point = |p: Point| Vec3::from(p);
+            assert!(
+                near_vec(point(camera.position), point(expected.position)),
+                "{name}"
+            );
+            assert!(
+                near_vec(point(camera.pixel00_loc), point(expected.pixel00_loc)),
+                "{name}"
+            );
+            assert!(near_vec(camera.w, expected.w), "{name}");
+            assert!(
+                near_vec(camera.pixel_delta_u, expected.pixel_delta_u),
+                "{name}"
+            );
+            assert!(
+                near_vec(camera.pixel_delta_v, expected.pixel_delta_v),
+                "{name}"
+            );
+            assert!(
+                near_vec(camera.defocus_disk_u, expected.defocus_disk_u),
+                "{name}"
+            );
+            for (value, expected) in [
+                (camera.focal_length, expected.focal_length),
+                (camera.defocus_angle, expected.defocus_angle),
+                (camera.aspect_ratio, expected.aspect_ratio),
+                (camera.vertical_fov, expected.vertical_fov),
+                (camera.shutter.min, expected.shutter.min),
+                (camera.shutter.max, expected.shutter.max),
+            ] {
+                assert!(near(value, expected), "{name} {value} {expected}");
+            }
+            assert_eq!(
+                (camera.image_width, camera.image_height),
+                (expected.image_width, expected.image_height),
+                "{name}"
+            );
+            assert_eq!(
+                camera.samples_per_pixel, expected.samples_per_pixel,
+                "{name}"
+            );
+            assert_eq!(camera.max_depth, expected.max_depth, "{name}");
+            for direction in [Vec3::new(0.0, 1.0, 0.0), Vec3::new(1.0, -0.5, 0.2)] {
+                let (value, expected) = (
+                    camera.background.sample(direction),
+                    expected.background.sample(direction),
+                );
+                assert!(
+                    near_vec(
+                        Vec3::new(value.r, value.g, value.b),
+                        Vec3::new(expected.r, expected.g, expected.b)
+                    ),
+                    "{name} {value:?} {expected:?}"
+                );
+            }
+
+            // Both worlds are built the same way, so a primitive and its match share a position
+            for (idx, (primitive, expected)) in world.iter().zip(expected_world.iter()).enumerate()
+            {
+                let (aabb, expected_aabb) = (primitive.bounding_box(), expected.bounding_box());
+                for (axis, expected_axis) in [
+                    (aabb.x, expected_aabb.x),
+                    (aabb.y, expected_aabb.y),
+                    (aabb.z, expected_aabb.z),
+                ] {
+                    assert!(
+                        near(axis.min, expected_axis.min) && near(axis.max, expected_axis.max),
+                        "{name} primitive {idx}: {aabb:?} {expected_aabb:?}"
+                    );
+                }
+
+                let materials = Primitive::materials(std::slice::from_ref(primitive));
+                let expected_materials = Primitive::materials(std::slice::from_ref(expected));
+                assert_eq!(
+                    materials.len(),
+                    expected_materials.len(),
+                    "{name} primitive {idx}"
+                );
+                for (material, expected) in materials.into_iter().zip(expected_materials) {
+                    let (kind, values) = material_values(material);
+                    let (expected_kind, expected_values) = material_values(expected);
+                    assert_eq!(kind, expected_kind, "{name} primitive {idx}");
+                    assert!(
+                        values
+                            .iter()
+                            .zip(&expected_values)
+                            .all(|(&value, &expected)| near(value, expected)),
+                        "{name} primitive {idx} {kind}: {values:?} {expected_values:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    // Variant and parameters, textures are compared by their color at a few points
+    fn material_values(material: &Material) -> (&'static str, Vec<f32>) {
+        let texture = |texture: &Texture| {
+            [
+                (0.1, 0.2, Point::new(0.3, -1.7, 2.9)),
+                (0.8, 0.6, Point::new(-4.1, 0.5, 1.2)),
+            ]
+            .into_iter()
+            .flat_map(|(u, v, p)| {
+                let color = texture.sample(u, v, p);
+                [color.r, color.g, color.b]
+            })
+            .collect()
+        };
+        match material {
+            Material::Lambertian(albedo) => ("lambertian", texture(albedo)),
+            Material::Metal(albedo, fuzz) => ("metal", vec![albedo.r, albedo.g, albedo.b, *fuzz]),
+            Material::Dielectric(index) => ("dielectric", vec![*index]),
+            Material::DiffuseLight(emission) => ("diffuse_light", texture(emission)),
+            Material::Isotropic(albedo) => ("isotropic", texture(albedo)),
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{color::Color, image::Image, noise::Perlin, point::Point};
+use crate::{color::Color, image::Image, medium::VoxelGrid, noise::Perlin, point::Point};
 
 #[derive(Debug)]
 pub(crate) enum Texture {
@@ -6,6 +6,9 @@ pub(crate) enum Texture {
     Checker(f32, Color, Color),
     Image(Image),
     Noise(Perlin),
+
+    // Color scaled by a voxel grid, e.g. the varying albedo of a medium
+    Grid(VoxelGrid, Color),
 }
 
 impl Texture {
@@ -23,21 +26,9 @@ impl Texture {
                     *c2
                 }
             }
-            Texture::Image(image) => {
-                // flip v to image coordinates
-                let u = u.clamp(0.0, 1.0);
-                let v = 1.0 - v.clamp(0.0, 1.0);
-
-                let i = (u * image.width as f32) as usize;
-                let j = (v * image.height as f32) as usize;
-                if let Some(pixel) = image.sample(i, j) {
-                    // let color_scale = 1.0 / 255.0;
-                    pixel
-                } else {
-                    Color::new(1.0, 0.0, 1.0)
-                }
-            }
+            Texture::Image(image) => image.sample_uv(u, v),
             Texture::Noise(perlin) => perlin.value(&p),
+            Texture::Grid(grid, color) => *color * grid.lookup(p),
         }
     }
 
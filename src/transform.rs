@@ -0,0 +1,251 @@
+use crate::{aabb::Aabb, interval::Interval, point::Point, vec3::Vec3};
+
+type Matrix = [[f32; 4]; 4];
+
+const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+// Affine 4x4 transform stored with its inverse, every constructor knows its inverse exactly so
+// no general matrix inversion is needed
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Transform {
+    matrix: Matrix,
+    inverse: Matrix,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::identity()
+    }
+}
+
+impl Transform {
+    pub(crate) const fn identity() -> Self {
+        Transform {
+            matrix: IDENTITY,
+            inverse: IDENTITY,
+        }
+    }
+
+    pub(crate) fn translate(offset: Vec3) -> Self {
+        let mut matrix = IDENTITY;
+        let mut inverse = IDENTITY;
+        for (row, value) in [offset.x, offset.y, offset.z].into_iter().enumerate() {
+            matrix[row][3] = value;
+            inverse[row][3] = -value;
+        }
+        Transform { matrix, inverse }
+    }
+
+    // Counterclockwise looking down the axis towards the origin
+    pub(crate) fn rotate(axis: Vec3, degrees: f32) -> Self {
+        let Vec3 { x, y, z } = axis.unit();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let t = 1.0 - cos;
+
+        // Rodrigues' rotation formula
+        let matrix = [
+            [
+                t * x * x + cos,
+                t * x * y - sin * z,
+                t * x * z + sin * y,
+                0.0,
+            ],
+            [
+                t * x * y + sin * z,
+                t * y * y + cos,
+                t * y * z - sin * x,
+                0.0,
+            ],
+            [
+                t * x * z - sin * y,
+                t * y * z + sin * x,
+                t * z * z + cos,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+
+        // Rotations are orthogonal, the inverse is the transpose
+        Transform {
+            matrix,
+            inverse: transpose(&matrix),
+        }
+    }
+
+    // Zero factors would flatten the object and have no inverse
+    pub(crate) fn scale(factors: Vec3) -> Self {
+        let mut matrix = IDENTITY;
+        let mut inverse = IDENTITY;
+        for (idx, value) in [factors.x, factors.y, factors.z].into_iter().enumerate() {
+            matrix[idx][idx] = value;
+            inverse[idx][idx] = 1.0 / value;
+        }
+        Transform { matrix, inverse }
+    }
+
+    // This transform followed by the next one
+    pub(crate) fn then(&self, next: &Transform) -> Self {
+        Transform {
+            matrix: multiply(&next.matrix, &self.matrix),
+            inverse: multiply(&self.inverse, &next.inverse),
+        }
+    }
+
+    pub(crate) fn inverse(&self) -> Self {
+        Transform {
+            matrix: self.inverse,
+            inverse: self.matrix,
+        }
+    }
+
+    pub(crate) fn point(&self, p: Point) -> Point {
+        let m = &self.matrix;
+        Point::new(
+            m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3],
+            m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3],
+            m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3],
+        )
+    }
+
+    // Directions ignore the translation
+    pub(crate) fn vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.matrix;
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+
+    // Normals go through the inverse transpose to stay perpendicular under non-uniform scaling,
+    // the result is not unit length
+    pub(crate) fn normal(&self, n: Vec3) -> Vec3 {
+        let m = &self.inverse;
+        Vec3::new(
+            m[0][0] * n.x + m[1][0] * n.y + m[2][0] * n.z,
+            m[0][1] * n.x + m[1][1] * n.y + m[2][1] * n.z,
+            m[0][2] * n.x + m[1][2] * n.y + m[2][2] * n.z,
+        )
+    }
+
+    // Box around all eight transformed corners
+    pub(crate) fn aabb(&self, aabb: &Aabb) -> Aabb {
+        let mut min = [f32::INFINITY; 3];
+        let mut max = [f32::NEG_INFINITY; 3];
+
+        for x in [aabb.x.min, aabb.x.max] {
+            for y in [aabb.y.min, aabb.y.max] {
+                for z in [aabb.z.min, aabb.z.max] {
+                    let p = self.point(Point::new(x, y, z));
+                    for (idx, value) in [p.x, p.y, p.z].into_iter().enumerate() {
+                        min[idx] = min[idx].min(value);
+                        max[idx] = max[idx].max(value);
+                    }
+                }
+            }
+        }
+
+        Aabb::new(
+            Interval::new(min[0], max[0]),
+            Interval::new(min[1], max[1]),
+            Interval::new(min[2], max[2]),
+        )
+        .pad_to_minimums()
+    }
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut result = [[0.0; 4]; 4];
+    for (row, result_row) in result.iter_mut().enumerate() {
+        for (col, value) in result_row.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[row][k] * b[k][col]).sum();
+        }
+    }
+    result
+}
+
+fn transpose(m: &Matrix) -> Matrix {
+    let mut result = [[0.0; 4]; 4];
+    for (row, result_row) in result.iter_mut().enumerate() {
+        for (col, value) in result_row.iter_mut().enumerate() {
+            *value = m[col][row];
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IDENTITY, Matrix, Transform, multiply};
+    use crate::{aabb::Aabb, point::Point, vec3::Vec3};
+
+    fn near(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    fn near_identity(m: &Matrix) -> bool {
+        m.iter()
+            .flatten()
+            .zip(IDENTITY.iter().flatten())
+            .all(|(a, b)| near(*a, *b))
+    }
+
+    fn transform() -> Transform {
+        Transform::scale(Vec3::new(2.0, 0.5, 3.0))
+            .then(&Transform::rotate(Vec3::new(1.0, 2.0, 3.0), 40.0))
+            .then(&Transform::translate(Vec3::new(1.0, -2.0, 5.0)))
+    }
+
+    #[test]
+    fn inverse_undoes_the_transform() {
+        let transform = transform();
+        assert!(near_identity(&multiply(
+            &transform.matrix,
+            &transform.inverse
+        )));
+        assert!(near_identity(&transform.then(&transform.inverse()).matrix));
+        assert!(near_identity(&transform.inverse().then(&transform).matrix));
+
+        let p = Point::new(0.3, -1.2, 2.5);
+        let back = transform.inverse().point(transform.point(p));
+        assert!(near(back.x, p.x) && near(back.y, p.y) && near(back.z, p.z));
+        let v = Vec3::new(-0.7, 0.1, 0.4);
+        let back = transform.inverse().vector(transform.vector(v));
+        assert!(near(back.x, v.x) && near(back.y, v.y) && near(back.z, v.z));
+
+        // Scaled first, then moved
+        let moved = Transform::scale(Vec3::new(2.0, 2.0, 2.0))
+            .then(&Transform::translate(Vec3::new(1.0, 0.0, 0.0)))
+            .point(Point::new(1.0, 1.0, 1.0));
+        assert!(near(moved.x, 3.0) && near(moved.y, 2.0) && near(moved.z, 2.0));
+    }
+
+    #[test]
+    fn normals_stay_perpendicular() {
+        let transform = transform();
+        let (a, b) = (Vec3::new(1.0, 1.0, 0.0), Vec3::new(0.0, 1.0, -2.0));
+        let normal = transform.normal(a.cross(b));
+
+        // Tangents transform as vectors, a normal transformed the same way would tilt off the surface
+        for tangent in [a, b] {
+            let tangent = transform.vector(tangent).unit();
+            assert!(normal.unit().dot(tangent).abs() < 1e-5);
+            assert!(transform.vector(a.cross(b)).unit().dot(tangent).abs() > 0.01);
+        }
+    }
+
+    #[test]
+    fn aabb_covers_the_corners() {
+        let unit = Aabb::new_between(Point::new(0.0, 0.0, 0.0), Point::new(1.0, 1.0, 1.0));
+        let aabb = Transform::rotate(Vec3::new(0.0, 0.0, 1.0), 45.0).aabb(&unit);
+        let half_diagonal = 0.5_f32.sqrt();
+        assert!(near(aabb.x.min, -half_diagonal) && near(aabb.x.max, half_diagonal));
+        assert!(near(aabb.y.min, 0.0) && near(aabb.y.max, 2.0 * half_diagonal));
+        assert!(near(aabb.z.min, 0.0) && near(aabb.z.max, 1.0));
+    }
+}
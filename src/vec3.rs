@@ -75,6 +75,18 @@ impl Vec3 {
         }
     }
 
+    // Two unit vectors perpendicular to this unit vector and to each other
+    // https://graphics.pixar.com/library/OrthonormalB/paper.pdf
+    pub(crate) fn orthonormal_basis(&self) -> (Vec3, Vec3) {
+        let sign = 1.0f32.copysign(self.z);
+        let a = -1.0 / (sign + self.z);
+        let b = self.x * self.y * a;
+        (
+            Vec3::new(1.0 + sign * self.x * self.x * a, sign * b, -sign * self.x),
+            Vec3::new(b, sign + self.y * self.y * a, -self.y),
+        )
+    }
+
     pub(crate) fn axis(&self, axis: Axis) -> f32 {
         match axis {
             Axis::X => self.x,